@@ -1,23 +1,38 @@
 use std::{
-    fs,
+    cmp::Reverse,
+    collections::HashMap,
+    fs::{self, DirEntry},
+    io::{self, ErrorKind},
+    net::SocketAddr,
     path::{Path, PathBuf},
-    sync::mpsc::Sender,
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
     thread::{self, sleep},
     time::Duration,
 };
 
-use globset::Glob;
-use tracing::{debug, error, info};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use tracing::{debug, error, info, info_span, warn};
 
 use crate::{
-    config::{Config, ConfigError},
-    job_orchestration::{JobOrchestrator, JobRequest},
-    workflow::Library,
+    config::{Config, ConfigError, MonitorMode},
+    db::{self, DbError},
+    file_watcher,
+    job_orchestration::{JobOrchestrator, JobRequest, JobSender, RunSummary},
+    metrics,
+    workflow::{Library, SortStrategy},
+    workflow_runner::{check_ffmpeg_available, FfmpegUnavailable, Runner},
     Workflow,
 };
 
 pub(crate) struct App {
     config: Config,
+    /// Whether the state database should be used
+    use_db: bool,
+    /// Address to serve metrics on, they are not served when absent
+    metrics_address: Option<SocketAddr>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -26,11 +41,30 @@ pub(crate) enum Error {
     Config(#[from] ConfigError),
     #[error(transparent)]
     CannotStartLibraryMonitor(std::io::Error),
+    #[error("builtin tasks are configured, but ffmpeg is not usable ({0}). Install ffmpeg (e.g. \"apt install ffmpeg\") and make sure it is in PATH, or remove the builtin tasks from your workflows")]
+    FfmpegUnavailable(#[source] FfmpegUnavailable),
+    #[error("unable to initialize the state database: {0}")]
+    DatabaseInit(#[source] DbError),
+    #[error("unable to determine where to write the task logs: {0}")]
+    TaskLogDirectory(#[source] DbError),
+    #[error("unable to serve metrics: {0}")]
+    CannotServeMetrics(#[source] std::io::Error),
+    #[error("unable to listen for signals: {0}")]
+    CannotListenForSignals(#[source] std::io::Error),
 }
 
 impl App {
-    pub(crate) fn new(config: Config) -> Self {
-        Self { config }
+    pub(crate) fn new(config: Config, use_db: bool) -> Self {
+        Self {
+            config,
+            use_db,
+            metrics_address: None,
+        }
+    }
+
+    pub(crate) fn with_metrics_address(mut self, metrics_address: Option<SocketAddr>) -> Self {
+        self.metrics_address = metrics_address;
+        self
     }
 
     /// Start the actual application.
@@ -41,7 +75,12 @@ impl App {
 
         let mut library_threads = Vec::with_capacity(libraries.len());
 
-        let (mut job_orchestrator, sender) = JobOrchestrator::new();
+        let (mut job_orchestrator, sender) = self.create_orchestrator()?;
+
+        if let Some(address) = self.metrics_address {
+            metrics::serve(address, job_orchestrator.metrics())
+                .map_err(Error::CannotServeMetrics)?;
+        }
 
         // create and move the job orchestrator to its own thread.
         // this will allow it to always receive new directory scans
@@ -51,18 +90,24 @@ impl App {
                 job_orchestrator.start();
             });
 
+        let mut scan_requesters = Vec::with_capacity(libraries.len());
+
         for library in libraries.iter() {
             debug!("starting library monitor for library {}", library.name);
 
             let library = library.clone();
             let job_sender = sender.clone();
+            let monitor_mode = self.config.monitor_mode;
+            let (scan_requester, scan_requests) = channel();
+            scan_requesters.push(scan_requester.clone());
 
             let thread_builder =
                 thread::Builder::new().name(format!("library-monitor({})", library.name.clone()));
 
             let handle = thread_builder
                 .spawn(move || {
-                    LibraryMonitor::new(library, job_sender).start();
+                    LibraryMonitor::new(library, job_sender, monitor_mode)
+                        .start(scan_requester, scan_requests);
                 })
                 .map_err(Error::CannotStartLibraryMonitor)?;
 
@@ -72,6 +117,12 @@ impl App {
         // let's not keep an instance after starting the threads
         drop(sender);
 
+        #[cfg(unix)]
+        listen_for_scan_signal(scan_requesters).map_err(Error::CannotListenForSignals)?;
+        // without a signal to listen for, the monitors simply wait for their next scan
+        #[cfg(not(unix))]
+        drop(scan_requesters);
+
         for thread in library_threads {
             let _ = thread.join();
         }
@@ -80,29 +131,146 @@ impl App {
     }
 }
 
+impl App {
+    /// Scan every library once and process all found files, instead of monitoring the libraries.
+    /// Returns once every dispatched file has been handled.
+    pub(crate) fn run_once(&self) -> Result<RunSummary, Error> {
+        let (job_orchestrator, sender) = self.create_orchestrator()?;
+
+        for library in self.config.libraries.iter() {
+            let _library_span = info_span!("library", name = %library.name).entered();
+
+            if library.wait_for_stable_size {
+                warn!("wait_for_stable_size is ignored, a single scan cannot compare sizes");
+            }
+
+            if let Err(err) =
+                LibraryMonitor::new(library.clone(), sender.clone(), MonitorMode::Poll)
+                    .dispatch_all()
+            {
+                error!("error occurred during library scan, see below");
+                error!("{err}");
+            }
+        }
+
+        // every file has been dispatched, so the orchestrator knows everything it needs to do
+        drop(sender);
+
+        Ok(job_orchestrator.run_until_idle())
+    }
+
+    /// Create the orchestrator, with the jobs of a previous run restored
+    fn create_orchestrator(&self) -> Result<(JobOrchestrator, JobSender), Error> {
+        let libraries = &self.config.libraries;
+
+        for library in libraries.iter() {
+            for warning in library.validate() {
+                warn!("library \"{}\": {warning}", library.name);
+            }
+        }
+
+        if libraries
+            .iter()
+            .any(|library| library.workflow.uses_builtin_tasks())
+        {
+            debug!("builtin tasks are configured, checking if ffmpeg is usable");
+            check_ffmpeg_available().map_err(Error::FfmpegUnavailable)?;
+        }
+
+        let mut runner = if self.use_db {
+            Runner::with_probe_cache(db::get_connection().map_err(Error::DatabaseInit)?)
+        } else {
+            Runner::new()
+        };
+
+        if self.config.write_task_logs {
+            let task_log_directory = db::state_directory()
+                .map_err(Error::TaskLogDirectory)?
+                .join("logs");
+            info!(
+                "writing the output of tasks to {}",
+                task_log_directory.display()
+            );
+            runner = runner.with_task_logs(task_log_directory);
+        }
+
+        let (mut job_orchestrator, sender) = JobOrchestrator::new(
+            Arc::new(runner),
+            self.use_db,
+            self.config.quarantine_threshold,
+            self.config.defer_duration,
+            self.config.max_concurrent_jobs,
+        )
+        .map_err(Error::DatabaseInit)?;
+
+        job_orchestrator.limit_concurrent_jobs_per_library(libraries);
+        job_orchestrator.set_completion_cooldown(self.config.completion_cooldown);
+
+        // restore jobs from before a restart, before any new scan results come in
+        job_orchestrator.restore_queue(libraries);
+
+        if let Some(older_than) = self.config.prune_history_older_than {
+            job_orchestrator.prune_history(older_than);
+        }
+
+        Ok((job_orchestrator, sender))
+    }
+}
+
+/// Request an immediate scan of every library when SIGUSR1 is received, e.g. with "kill -USR1".
+/// Only SIGUSR1 is handled, so the default behaviour of other signals such as SIGTERM is kept.
+#[cfg(unix)]
+fn listen_for_scan_signal(scan_requesters: Vec<Sender<()>>) -> std::io::Result<()> {
+    use signal_hook::{consts::SIGUSR1, iterator::Signals};
+
+    let mut signals = Signals::new([SIGUSR1])?;
+
+    thread::Builder::new()
+        .name(String::from("signal-listener"))
+        .spawn(move || {
+            for _ in signals.forever() {
+                info!("received SIGUSR1, scanning all libraries");
+                for scan_requester in &scan_requesters {
+                    let _ = scan_requester.send(());
+                }
+            }
+        })?;
+
+    Ok(())
+}
+
 struct LibraryMonitor {
     library: Library,
-    job_sender: Sender<Box<JobRequest>>,
+    job_sender: JobSender,
+    mode: MonitorMode,
+    /// Sizes of the files found during the previous scan
+    previous_sizes: HashMap<PathBuf, u64>,
+    /// Position in the scanned files where the next scan continues, when dispatching is limited
+    scan_offset: usize,
 }
 
 impl LibraryMonitor {
-    fn new(library: Library, job_sender: Sender<Box<JobRequest>>) -> Self {
+    fn new(library: Library, job_sender: JobSender, mode: MonitorMode) -> Self {
         Self {
             library,
             job_sender,
+            mode,
+            previous_sizes: HashMap::new(),
+            scan_offset: 0,
         }
     }
+}
 
-    fn get_directory_glob(&self) -> String {
-        let extensions_part = format!(
-            ".{{{}}}",
-            self.library.workflow.included_extensions.join(",")
-        );
-        PathBuf::from(&self.library.directory)
-            .join(format!("**/*{}", extensions_part))
-            .to_string_lossy()
-            .to_string()
-    }
+/// Longest time between scans of a library of which the scans keep failing
+const MAX_SCAN_BACKOFF: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Time until the next scan after consecutive failed scans, the interval doubles with every failure
+fn scan_backoff(interval: Duration, consecutive_failures: u32) -> Duration {
+    let factor = 2u32.saturating_pow(consecutive_failures.saturating_sub(1));
+
+    interval
+        .checked_mul(factor)
+        .map_or(MAX_SCAN_BACKOFF, |backoff| backoff.min(MAX_SCAN_BACKOFF))
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -112,26 +280,119 @@ enum MonitorError {
 }
 
 impl LibraryMonitor {
-    fn start(&self) {
+    /// Scan the library every hour, or earlier when a scan is requested.
+    /// When watching, a change to the library requests a scan through `scan_requester`.
+    fn start(&mut self, scan_requester: Sender<()>, scan_requests: Receiver<()>) {
+        let interval = Duration::from_secs(60 * 60);
+
+        // changes can be missed, e.g. while omzet is not running, so watching does not replace polling
+        if self.mode == MonitorMode::Watch {
+            match file_watcher::watch(&self.library.directory, scan_requester) {
+                Ok(()) => info!("watching library {} for changes", self.library.name),
+                Err(err) => warn!(
+                    "unable to watch library {}, only scanning it periodically: {err}",
+                    self.library.name
+                ),
+            }
+        }
+
+        let mut consecutive_failures = 0;
+
         loop {
-            if let Err(err) = self.tick() {
-                error!("error occurred during library monitoring, see below");
-                error!("{err}");
+            let wait = match self.tick() {
+                Ok(()) => {
+                    if consecutive_failures > 0 {
+                        info!(
+                            "library {} was scanned again after {consecutive_failures} failed scans",
+                            self.library.name
+                        );
+                    }
+                    consecutive_failures = 0;
+                    interval
+                }
+                Err(err) => {
+                    consecutive_failures += 1;
+                    let wait = scan_backoff(interval, consecutive_failures);
+
+                    // the first failure and failures at the longest backoff are worth attention,
+                    // failures in between are likely the same problem persisting
+                    if consecutive_failures == 1 || wait == MAX_SCAN_BACKOFF {
+                        error!("error occurred during library monitoring, see below");
+                        error!("{err}");
+                    } else {
+                        warn!("library monitoring failed again: {err}");
+                    }
+                    warn!(
+                        "{consecutive_failures} consecutive scans of library {} failed, next scan in {} minutes",
+                        self.library.name,
+                        wait.as_secs() / 60
+                    );
+
+                    wait
+                }
+            };
+
+            match scan_requests.recv_timeout(wait) {
+                Ok(()) => {
+                    info!("scan of library {} requested", self.library.name);
+                    // requests that arrived in the meantime are served by this same scan
+                    scan_requests.try_iter().for_each(drop);
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => sleep(wait),
             }
-            sleep(Duration::from_secs(60 * 60));
         }
     }
 
     /// Perform a "monitoring tick" for the library.
-    /// Comes down to scanning all files within
-    fn tick(&self) -> Result<(), MonitorError> {
-        info!("starting library scan");
+    /// Comes down to scanning all files within and dispatching a job for each of them
+    fn tick(&mut self) -> Result<(), MonitorError> {
+        for file_path in self.scan_once()? {
+            self.dispatch_job(
+                self.library.name.clone(),
+                file_path,
+                self.library.workflow.clone(),
+            );
+        }
 
-        let files = scan_library(&self.library.directory, self.get_directory_glob())?;
+        Ok(())
+    }
 
-        info!("library scan completed, found {} files", files.len());
+    /// Scan the library and select the files to dispatch, without dispatching them: only files of
+    /// which the size is stable when that is awaited, and at most the batch of this scan
+    fn scan_once(&mut self) -> Result<Vec<PathBuf>, MonitorError> {
+        let files = self.scan()?;
+
+        let files = if self.library.wait_for_stable_size {
+            let stable_files = retain_stable_files(files, &mut self.previous_sizes);
+            info!("{} files have a stable size", stable_files.len());
+            stable_files
+        } else {
+            files
+        };
+
+        let files = match self.library.max_file_count_per_scan {
+            Some(limit) => {
+                let found = files.len();
+                let batch = take_batch(files, limit, &mut self.scan_offset);
+                if batch.len() < found {
+                    info!(
+                        "dispatching {} files, {} are left for the next scans",
+                        batch.len(),
+                        found - batch.len()
+                    );
+                }
+                batch
+            }
+            None => files,
+        };
+
+        Ok(files)
+    }
 
-        for file_path in files {
+    /// Scan the library once and dispatch every found file, regardless of its size being stable
+    fn dispatch_all(&self) -> Result<(), MonitorError> {
+        for file_path in self.scan()? {
             self.dispatch_job(
                 self.library.name.clone(),
                 file_path,
@@ -142,10 +403,22 @@ impl LibraryMonitor {
         Ok(())
     }
 
+    fn scan(&self) -> Result<Vec<PathBuf>, MonitorError> {
+        info!("starting library scan");
+
+        let files = scan(&self.library)?;
+
+        info!("library scan completed, found {} files", files.len());
+
+        Ok(files)
+    }
+
     /// Dispatches a job so that a [`JobOrchestrator`] can pick it up
     /// and start doing something
     fn dispatch_job(&self, library: String, file_path: PathBuf, workflow: Workflow) {
-        let job = Box::new(JobRequest::new(library, file_path, workflow));
+        let job = Box::new(
+            JobRequest::new(library, file_path, workflow).with_priority(self.library.priority),
+        );
 
         if let Err(err) = self.job_sender.send(job) {
             error!("unable to dispatch job for scanned file\n {err}");
@@ -153,51 +426,263 @@ impl LibraryMonitor {
     }
 }
 
+/// Take at most `limit` files, starting at the offset. The offset is moved past the taken files,
+/// back to the start once every file had its turn, so consecutive scans work through all files.
+fn take_batch(files: Vec<PathBuf>, limit: usize, offset: &mut usize) -> Vec<PathBuf> {
+    // files may have disappeared since the previous scan
+    if *offset >= files.len() {
+        *offset = 0;
+    }
+
+    let batch: Vec<PathBuf> = files.into_iter().skip(*offset).take(limit).collect();
+    *offset += batch.len();
+
+    batch
+}
+
+/// Only keep the files of which the size did not change since the previous scan.
+/// The current sizes are remembered for the next scan, so new files are kept from then on.
+fn retain_stable_files(
+    files: Vec<PathBuf>,
+    previous_sizes: &mut HashMap<PathBuf, u64>,
+) -> Vec<PathBuf> {
+    let current_sizes: HashMap<PathBuf, u64> = files
+        .iter()
+        .filter_map(|file| {
+            fs::metadata(file)
+                .ok()
+                .map(|metadata| (file.clone(), metadata.len()))
+        })
+        .collect();
+
+    let stable_files = files
+        .into_iter()
+        .filter(|file| {
+            let is_stable = current_sizes
+                .get(file)
+                .is_some_and(|size| previous_sizes.get(file) == Some(size));
+
+            if !is_stable {
+                debug!(
+                    "size of {} is not stable yet, not dispatching it",
+                    file.to_string_lossy()
+                );
+            }
+
+            is_stable
+        })
+        .collect();
+
+    *previous_sizes = current_sizes;
+
+    stable_files
+}
+
 #[derive(Debug, thiserror::Error)]
-enum ScanningError {
+pub(crate) enum ScanningError {
     #[error("unable to iterate over library directory \"{1}\": {0}")]
     IterateDirectory(std::io::Error, PathBuf),
     #[error("unable to read entry in library directory \"{1}\": {0}")]
     ReadEntry(std::io::Error, PathBuf),
     #[error("unable to form glob to scan directory: {0}")]
     FormGlob(#[from] globset::Error),
+    #[error("unable to read ignore file \"{1}\": {0}")]
+    ReadIgnoreFile(std::io::Error, PathBuf),
+    #[error("invalid pattern in ignore file \"{1}\": {0}")]
+    InvalidIgnorePattern(globset::Error, PathBuf),
+}
+
+/// Name of the file listing glob patterns to exclude from the scan, relative to its directory
+const IGNORE_FILE_NAME: &str = ".omzetignore";
+
+/// Scan the library for the files its workflow applies to, in the order they are dispatched
+pub(crate) fn scan(library: &Library) -> Result<Vec<PathBuf>, ScanningError> {
+    scan_library(
+        &library.directory,
+        directory_glob(library),
+        &library.workflow.excluded_patterns,
+        library.scan_sort,
+        library.scan_hidden,
+    )
+}
+
+fn directory_glob(library: &Library) -> String {
+    let extensions_part = format!(".{{{}}}", library.workflow.included_extensions.join(","));
+    PathBuf::from(&library.directory)
+        .join(format!("**/*{}", extensions_part))
+        .to_string_lossy()
+        .to_string()
 }
 
-/// Scan the library for matching files
-fn scan_library(path: &Path, glob_pattern: String) -> Result<Vec<PathBuf>, ScanningError> {
+/// Scan the library for matching files, ordered by the sort strategy.
+/// Files are first matched against the glob, after which any file with a name matching one of the
+/// excluded patterns is left out.
+fn scan_library(
+    path: &Path,
+    glob_pattern: String,
+    excluded_patterns: &[String],
+    sort: SortStrategy,
+    scan_hidden: bool,
+) -> Result<Vec<PathBuf>, ScanningError> {
     debug!("scanning library with glob: {glob_pattern}");
 
-    let paths = scan_directory_for_files(path)?;
+    let paths = scan_directory_for_files(path, scan_hidden)?;
 
     let globset = Glob::new(&glob_pattern)?.compile_matcher();
+
+    let mut exclusions = GlobSetBuilder::new();
+    for pattern in excluded_patterns {
+        exclusions.add(Glob::new(pattern)?);
+    }
+    let exclusions = exclusions.build()?;
+
     let mut files: Vec<PathBuf> = paths
         .into_iter()
         .filter(|path| globset.is_match(path))
+        .filter(|path| {
+            !path
+                .file_name()
+                .is_some_and(|file_name| exclusions.is_match(file_name))
+        })
         .collect();
 
-    files.sort();
+    sort_files(&mut files, sort);
 
     Ok(files)
 }
 
-/// Recursively scan the given directory for files
-fn scan_directory_for_files(directory: &Path) -> Result<Vec<PathBuf>, ScanningError> {
-    let mut paths: Vec<PathBuf> = vec![];
+/// Sort the files by the given strategy.
+/// Files of which the metadata cannot be read are sorted as if they are empty and old.
+fn sort_files(files: &mut [PathBuf], sort: SortStrategy) {
+    let modified = |path: &PathBuf| {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    };
+    let size = |path: &PathBuf| {
+        fs::metadata(path)
+            .map(|metadata| metadata.len())
+            .unwrap_or(0)
+    };
+
+    match sort {
+        SortStrategy::Name => files.sort(),
+        SortStrategy::MtimeAsc => files.sort_by_cached_key(modified),
+        SortStrategy::MtimeDesc => files.sort_by_cached_key(|path| Reverse(modified(path))),
+        SortStrategy::SizeAsc => files.sort_by_cached_key(size),
+        SortStrategy::SizeDesc => files.sort_by_cached_key(|path| Reverse(size(path))),
+    }
+}
 
-    for entry in fs::read_dir(directory)
-        .map_err(|err| ScanningError::IterateDirectory(err, directory.to_path_buf()))?
-    {
-        let entry = entry.map_err(|err| ScanningError::ReadEntry(err, directory.to_path_buf()))?;
+/// Recursively scan the given directory for files.
+/// Hidden files and directories, of which the name starts with a ".", are skipped unless requested.
+///
+/// Any directory can contain an `.omzetignore` file with a glob pattern per line, e.g. `*.nfo`
+/// or `extras`. These are matched against paths relative to that directory and exclude matching
+/// files and directories beneath it. This happens before, and independent of, the excluded
+/// patterns of the workflow; a file excluded by either is not processed.
+///
+/// The files are sorted by path, so the result does not depend on the order in which the
+/// filesystem lists them. With the "parallel-scan" feature, directories are scanned in parallel.
+fn scan_directory_for_files(
+    directory: &Path,
+    scan_hidden: bool,
+) -> Result<Vec<PathBuf>, ScanningError> {
+    let mut paths = scan_directory(directory, scan_hidden, &[])?;
+    paths.sort();
 
-        if entry.path().is_dir() {
-            let mut children = scan_directory_for_files(&entry.path())?;
-            paths.append(&mut children);
-        } else {
-            paths.push(entry.path());
-        }
+    Ok(paths)
+}
+
+/// Scan a directory, skipping paths ignored by the `.omzetignore` files of it and its parents
+fn scan_directory(
+    directory: &Path,
+    scan_hidden: bool,
+    ignores: &[(PathBuf, GlobSet)],
+) -> Result<Vec<PathBuf>, ScanningError> {
+    let mut ignores = ignores.to_vec();
+    if let Some(ignore) = read_ignore_file(directory)? {
+        ignores.push((directory.to_path_buf(), ignore));
     }
 
-    Ok(paths)
+    let entries = fs::read_dir(directory)
+        .map_err(|err| ScanningError::IterateDirectory(err, directory.to_path_buf()))?;
+
+    #[cfg(feature = "parallel-scan")]
+    let entries = {
+        use rayon::iter::{ParallelBridge, ParallelIterator};
+
+        entries
+            .par_bridge()
+            .map(|entry| scan_entry(entry, directory, scan_hidden, &ignores))
+            .collect::<Result<Vec<Vec<PathBuf>>, ScanningError>>()?
+    };
+    #[cfg(not(feature = "parallel-scan"))]
+    let entries = entries
+        .map(|entry| scan_entry(entry, directory, scan_hidden, &ignores))
+        .collect::<Result<Vec<Vec<PathBuf>>, ScanningError>>()?;
+
+    Ok(entries.into_iter().flatten().collect())
+}
+
+/// Scan an entry of a directory, resulting in the entry itself or, for a directory, the files below it
+fn scan_entry(
+    entry: io::Result<DirEntry>,
+    directory: &Path,
+    scan_hidden: bool,
+    ignores: &[(PathBuf, GlobSet)],
+) -> Result<Vec<PathBuf>, ScanningError> {
+    let entry = entry.map_err(|err| ScanningError::ReadEntry(err, directory.to_path_buf()))?;
+
+    if !scan_hidden && entry.file_name().to_string_lossy().starts_with('.') {
+        return Ok(vec![]);
+    }
+
+    let path = entry.path();
+
+    let is_ignored = ignores.iter().any(|(ignore_directory, ignore)| {
+        path.strip_prefix(ignore_directory)
+            .is_ok_and(|relative_path| ignore.is_match(relative_path))
+    });
+
+    if is_ignored {
+        return Ok(vec![]);
+    }
+
+    if path.is_dir() {
+        scan_directory(&path, scan_hidden, ignores)
+    } else {
+        Ok(vec![path])
+    }
+}
+
+/// Read the patterns of the directory's ignore file, if it has one.
+/// Empty lines and lines starting with "#" are skipped.
+fn read_ignore_file(directory: &Path) -> Result<Option<GlobSet>, ScanningError> {
+    let ignore_file = directory.join(IGNORE_FILE_NAME);
+
+    let contents = match fs::read_to_string(&ignore_file) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(ScanningError::ReadIgnoreFile(err, ignore_file)),
+    };
+
+    let mut patterns = GlobSetBuilder::new();
+    for line in contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    {
+        patterns.add(
+            Glob::new(line)
+                .map_err(|err| ScanningError::InvalidIgnorePattern(err, ignore_file.clone()))?,
+        );
+    }
+
+    patterns
+        .build()
+        .map(Some)
+        .map_err(|err| ScanningError::InvalidIgnorePattern(err, ignore_file))
 }
 
 #[cfg(test)]
@@ -205,6 +690,18 @@ mod tests {
     use tempdir::TempDir;
 
     use super::*;
+    use crate::workflow::test_workflow;
+
+    #[test]
+    fn scan_backoff_doubles_up_to_a_maximum() {
+        let interval = Duration::from_secs(60 * 60);
+
+        assert_eq!(interval, scan_backoff(interval, 1));
+        assert_eq!(interval * 2, scan_backoff(interval, 2));
+        assert_eq!(interval * 16, scan_backoff(interval, 5));
+        assert_eq!(MAX_SCAN_BACKOFF, scan_backoff(interval, 6));
+        assert_eq!(MAX_SCAN_BACKOFF, scan_backoff(interval, u32::MAX));
+    }
 
     #[test]
     fn directory_is_scanned_properly() {
@@ -224,10 +721,210 @@ mod tests {
             .and(fs::write(test_file_c, "c"))
             .expect("unable to setup test files");
 
-        let files = scan_directory_for_files(&temp_dir_path).unwrap();
+        let files = scan_directory_for_files(&temp_dir_path, false).unwrap();
 
         temp_test_dir.close().unwrap();
 
         assert_eq!(files.len(), 3);
     }
+
+    #[test]
+    fn scan_once_selects_matching_stable_files_in_batches() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let temp_dir_path = temp_test_dir.path().to_path_buf();
+
+        fs::create_dir(temp_dir_path.join("extras"))
+            .and(fs::write(temp_dir_path.join("a.mkv"), "a"))
+            .and(fs::write(temp_dir_path.join("extras/b.mkv"), "b"))
+            .and(fs::write(temp_dir_path.join("sample.mkv"), "c"))
+            .and(fs::write(temp_dir_path.join("notes.txt"), "d"))
+            .expect("unable to setup test files");
+
+        let workflow = Workflow {
+            excluded_patterns: vec![String::from("sample.*")],
+            ..test_workflow("movies", temp_dir_path.join("scratchpad"))
+        };
+        let library = Library::new(
+            String::from("movies"),
+            workflow,
+            temp_dir_path.clone(),
+            SortStrategy::Name,
+            true,
+            false,
+            Some(1),
+        );
+        let (_orchestrator, job_sender) =
+            JobOrchestrator::new(Arc::new(Runner::new()), false, 0, Duration::ZERO, 1).unwrap();
+
+        let mut monitor = LibraryMonitor::new(library, job_sender, MonitorMode::Poll);
+
+        // sizes are only known to be stable from the second scan on
+        assert!(monitor.scan_once().unwrap().is_empty());
+        assert_eq!(
+            vec![temp_dir_path.join("a.mkv")],
+            monitor.scan_once().unwrap()
+        );
+        assert_eq!(
+            vec![temp_dir_path.join("extras/b.mkv")],
+            monitor.scan_once().unwrap()
+        );
+    }
+
+    #[test]
+    fn excluded_patterns_are_left_out_of_scan() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let temp_dir_path = temp_test_dir.path().to_path_buf();
+
+        fs::write(temp_dir_path.join("movie.mkv"), "a")
+            .and(fs::write(temp_dir_path.join("sample.mkv"), "b"))
+            .and(fs::write(temp_dir_path.join("movie.txt"), "c"))
+            .expect("unable to setup test files");
+
+        let files = scan_library(
+            &temp_dir_path,
+            temp_dir_path
+                .join("**/*.{mkv}")
+                .to_string_lossy()
+                .to_string(),
+            &[String::from("sample.*")],
+            SortStrategy::Name,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(vec![temp_dir_path.join("movie.mkv")], files);
+    }
+
+    #[test]
+    fn paths_in_ignore_files_are_left_out_of_scan() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let temp_dir_path = temp_test_dir.path().to_path_buf();
+
+        fs::create_dir_all(temp_dir_path.join("movie/extras"))
+            .and(fs::write(temp_dir_path.join("movie/movie.mkv"), "a"))
+            .and(fs::write(temp_dir_path.join("movie/trailer.mkv"), "b"))
+            .and(fs::write(temp_dir_path.join("movie/extras/scene.mkv"), "c"))
+            .and(fs::write(temp_dir_path.join("other.mkv"), "d"))
+            .and(fs::write(
+                temp_dir_path.join("movie/.omzetignore"),
+                "# not needed\ntrailer.*\n\nextras\n",
+            ))
+            .expect("unable to setup test files");
+
+        let mut files = scan_directory_for_files(&temp_dir_path, false).unwrap();
+        files.sort();
+
+        assert_eq!(
+            vec![
+                temp_dir_path.join("movie/movie.mkv"),
+                temp_dir_path.join("other.mkv")
+            ],
+            files
+        );
+    }
+
+    #[test]
+    fn hidden_files_are_only_scanned_when_requested() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let temp_dir_path = temp_test_dir.path().to_path_buf();
+        let hidden_movie = temp_dir_path.join(".hidden/movie.mkv");
+
+        fs::create_dir(temp_dir_path.join(".hidden"))
+            .and(fs::write(&hidden_movie, "a"))
+            .expect("unable to setup test files");
+
+        let scan = |scan_hidden| {
+            scan_library(
+                &temp_dir_path,
+                temp_dir_path
+                    .join("**/*.{mkv}")
+                    .to_string_lossy()
+                    .to_string(),
+                &[],
+                SortStrategy::Name,
+                scan_hidden,
+            )
+            .unwrap()
+        };
+
+        assert!(scan(false).is_empty());
+        assert_eq!(vec![hidden_movie.clone()], scan(true));
+    }
+
+    #[test]
+    fn files_are_sorted_by_size() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+
+        let small = temp_test_dir.path().join("b.txt");
+        let large = temp_test_dir.path().join("a.txt");
+
+        fs::write(&small, "b")
+            .and(fs::write(&large, "aaaa"))
+            .expect("unable to setup test files");
+
+        let mut files = vec![small.clone(), large.clone()];
+
+        sort_files(&mut files, SortStrategy::SizeDesc);
+        assert_eq!(vec![large.clone(), small.clone()], files);
+
+        sort_files(&mut files, SortStrategy::SizeAsc);
+        assert_eq!(vec![small.clone(), large.clone()], files);
+
+        sort_files(&mut files, SortStrategy::Name);
+        assert_eq!(vec![large, small], files);
+    }
+
+    #[test]
+    fn only_files_with_a_stable_size_are_retained() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+
+        let growing = temp_test_dir.path().join("growing.mkv");
+        let stable = temp_test_dir.path().join("stable.mkv");
+
+        fs::write(&growing, "a")
+            .and(fs::write(&stable, "a"))
+            .expect("unable to setup test files");
+
+        let mut previous_sizes = HashMap::new();
+        let files = vec![growing.clone(), stable.clone()];
+
+        // files are not dispatched on the scan that finds them
+        assert!(retain_stable_files(files.clone(), &mut previous_sizes).is_empty());
+
+        fs::write(&growing, "aa").expect("unable to grow test file");
+
+        assert_eq!(
+            vec![stable],
+            retain_stable_files(files.clone(), &mut previous_sizes)
+        );
+        assert_eq!(2, retain_stable_files(files, &mut previous_sizes).len());
+    }
+
+    #[test]
+    fn limited_scans_work_through_all_files() {
+        let files: Vec<PathBuf> = ["a.mkv", "b.mkv", "c.mkv"]
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+        let mut offset = 0;
+
+        assert_eq!(
+            files[..2].to_vec(),
+            take_batch(files.clone(), 2, &mut offset)
+        );
+        assert_eq!(
+            files[2..].to_vec(),
+            take_batch(files.clone(), 2, &mut offset)
+        );
+        assert_eq!(
+            files[..2].to_vec(),
+            take_batch(files.clone(), 2, &mut offset)
+        );
+
+        // fewer files than before make the offset start over
+        assert_eq!(
+            files[..1].to_vec(),
+            take_batch(files[..1].to_vec(), 2, &mut offset)
+        );
+    }
 }
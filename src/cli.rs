@@ -0,0 +1,193 @@
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::duration::{parse_duration, parse_point_in_time, PointInTime};
+
+/// A task runner for transforming files, mainly media files
+#[derive(Debug, Parser)]
+#[command(name = "omzet", version)]
+pub(crate) struct Cli {
+    /// Run without the SQLite state database; all state is kept in memory.
+    /// Deduplication of already processed files does not work across restarts in this mode.
+    #[arg(long)]
+    pub(crate) no_db: bool,
+
+    /// Read the configuration from this file instead of ~/.config/omzet/omzet.toml, "-" reads it from stdin
+    #[arg(long, global = true, value_name = "PATH")]
+    pub(crate) config: Option<PathBuf>,
+
+    /// Also write logs to this file, which is rotated daily. Overrides the configured log file
+    #[arg(long, global = true)]
+    pub(crate) log_file: Option<PathBuf>,
+
+    /// Only log warnings and errors, instead of the configured levels. Directives in the RUST_LOG
+    /// environment variable still take precedence. With --run-once the summary is only printed when
+    /// a file failed
+    #[arg(long, global = true)]
+    pub(crate) quiet: bool,
+
+    /// Do not color the output, like when the NO_COLOR environment variable is set
+    #[arg(long, global = true)]
+    pub(crate) no_color: bool,
+
+    /// Scan every library once, process the found files and exit instead of monitoring
+    #[arg(long)]
+    pub(crate) run_once: bool,
+
+    /// Scan a library as soon as a file is added to it, instead of only periodically.
+    /// Overrides the configured mode
+    #[arg(long, conflicts_with = "run_once")]
+    pub(crate) watch: bool,
+
+    /// Also print the summary of --run-once as a machine readable line to stdout
+    #[arg(long, requires = "run_once")]
+    pub(crate) machine_summary: bool,
+
+    /// Serve metrics in the Prometheus text format on this address, e.g. "127.0.0.1:9100"
+    #[arg(long, value_name = "ADDRESS", conflicts_with = "run_once")]
+    pub(crate) metrics_addr: Option<SocketAddr>,
+
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum Command {
+    /// Run a workflow once for a single file, without monitoring the libraries
+    RunFile {
+        /// The file to transform
+        file: PathBuf,
+        /// Name of the workflow to run. Defaults to the workflow of the library containing the file
+        #[arg(long)]
+        workflow: Option<String>,
+        /// Directory to use as scratchpad instead of the workflow's configured one
+        #[arg(long)]
+        scratchpad_dir: Option<PathBuf>,
+        /// Only validate the invocation and show which tasks would be considered
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Interactively create a starter configuration
+    GenerateConfig {
+        /// Write the configuration to this file instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Show the outcome of processed files
+    History {
+        /// Only show jobs recorded within this duration, e.g. "24h" or "7d", or since a local date
+        /// and time, e.g. "2024-01-01" or "2024-01-01T18:30"
+        #[arg(long, value_parser = parse_point_in_time)]
+        since: Option<PointInTime>,
+
+        /// Only show jobs that failed
+        #[arg(long)]
+        failed: bool,
+
+        /// Only show jobs in which this task has run, by its id, e.g. "builtin.transcode_to_h265"
+        #[arg(long, value_name = "TASK_ID")]
+        task: Option<String>,
+
+        /// How to print the jobs
+        #[arg(long, value_enum, default_value_t = HistoryFormat::Text)]
+        format: HistoryFormat,
+
+        #[command(subcommand)]
+        action: Option<HistoryCommand>,
+    },
+    /// Manage files that were quarantined because they failed too often
+    Quarantine {
+        #[command(subcommand)]
+        action: QuarantineCommand,
+    },
+    /// List the builtin tasks that can be used in a workflow, with their parameters
+    ListBuiltins,
+    /// Append a custom task to the configuration file, keeping its formatting and comments
+    AddTask {
+        /// Identifier to reference the task by in workflows
+        #[arg(long)]
+        id: String,
+        /// A small description of what the task does
+        #[arg(long)]
+        description: String,
+        /// The script that performs the task
+        #[arg(long)]
+        command: String,
+        /// The script that decides whether the task runs for a file
+        #[arg(long)]
+        probe: Option<String>,
+    },
+    /// Show the tasks of a workflow as they are resolved from the configuration
+    ShowWorkflow {
+        /// Name of the workflow to show
+        workflow: String,
+        /// How to print the workflow
+        #[arg(long, value_enum, default_value_t = WorkflowFormat::Text)]
+        format: WorkflowFormat,
+    },
+    /// Scan the libraries and show per file which tasks would run, without copying files or running
+    /// tasks. Only the probes run, and they see the path of the original file
+    Plan {
+        /// Only plan the library with this name
+        #[arg(long)]
+        library: Option<String>,
+    },
+    /// Forget what is known about the files of a workflow, so the next scan runs it for all of them
+    Reprocess {
+        /// Name of the workflow of which the libraries should be reprocessed
+        #[arg(long)]
+        workflow: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum QuarantineCommand {
+    /// List all quarantined files
+    List,
+    /// Release quarantined files so they are processed again
+    Clear {
+        /// Only release this file instead of all quarantined files
+        file: Option<PathBuf>,
+    },
+}
+
+/// Output format of `omzet history`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum HistoryFormat {
+    /// A line per job, with its fields separated by tabs
+    Text,
+    /// A JSON object per line for every job, e.g. to process with jq
+    Jsonl,
+}
+
+/// Output format of `omzet show-workflow`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum WorkflowFormat {
+    /// A readable overview, in which long scripts are truncated
+    Text,
+    /// The complete workflow as a JSON object
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum HistoryCommand {
+    /// Remove old jobs from the history
+    Prune {
+        /// Remove jobs recorded longer ago than this duration, e.g. "90d"
+        #[arg(long, value_parser = parse_duration)]
+        older_than: Duration,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn cli_definition_is_valid() {
+        Cli::command().debug_assert();
+    }
+}
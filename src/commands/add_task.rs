@@ -0,0 +1,211 @@
+use std::{fs, path::Path};
+
+use toml_edit::{value, ArrayOfTables, DocumentMut, Item, Table, Value};
+use tracing::warn;
+
+use crate::{
+    config::{default_config_file, write_atomically, ConfigError, STDIN_PATH},
+    workflow::{CustomTask, Runnable},
+};
+
+use super::CommandError;
+
+/// A custom task to add to the configuration
+pub(super) struct NewTask {
+    pub(super) id: String,
+    pub(super) description: String,
+    pub(super) command: String,
+    pub(super) probe: Option<String>,
+}
+
+/// Append the task to the configuration file and print the lines that were added.
+/// Nothing is written when a task with the same id already exists.
+pub(super) fn run(task: NewTask, config: Option<&Path>) -> Result<(), CommandError> {
+    validate(&task)?;
+
+    let path = match config {
+        Some(path) if path == Path::new(STDIN_PATH) => {
+            return Err(CommandError::ConfigEdit(String::from(
+                "a configuration read from stdin cannot be edited",
+            )))
+        }
+        Some(path) => path.to_path_buf(),
+        None => default_config_file()?,
+    };
+
+    let original = fs::read_to_string(&path)
+        .map_err(|err| CommandError::InaccessibleFile(err, path.clone()))?;
+
+    let Some(edited) = append_task(&original, &task)? else {
+        warn!(
+            "a task with id \"{}\" already exists, leaving {} unchanged",
+            task.id,
+            path.display()
+        );
+        return Ok(());
+    };
+
+    write_atomically(&path, edited.as_bytes())?;
+
+    for line in added_lines(&original, &edited) {
+        println!("+{line}");
+    }
+
+    Ok(())
+}
+
+/// Check the task the same way tasks are checked when the configuration is read
+fn validate(task: &NewTask) -> Result<(), ConfigError> {
+    if task.command.trim().is_empty() {
+        return Err(ConfigError::EmptyTaskCommand(task.id.clone()));
+    }
+
+    let into_runnable = |script: &str| {
+        Runnable::new(script).map_err(|source| ConfigError::InvalidTaskScript {
+            task_id: task.id.clone(),
+            source,
+        })
+    };
+
+    CustomTask::new(
+        task.id.clone(),
+        task.description.clone(),
+        task.probe.as_deref().map(into_runnable).transpose()?,
+        into_runnable(&task.command)?,
+    )
+    .validate()
+    .map_err(|errors| {
+        ConfigError::InvalidTask(
+            task.id.clone(),
+            errors.iter().map(ToString::to_string).collect(),
+        )
+    })
+}
+
+/// Append the task as a `[[tasks]]` table, keeping the rest of the document as it is. When the
+/// tasks are an inline array, e.g. "tasks = []", the task is appended to it as an inline table.
+/// Absent when a task with the same id already exists.
+fn append_task(document: &str, task: &NewTask) -> Result<Option<String>, CommandError> {
+    let mut document = document
+        .parse::<DocumentMut>()
+        .map_err(|err| CommandError::ConfigEdit(err.to_string()))?;
+
+    let mut table = Table::new();
+    table["id"] = value(&task.id);
+    table["description"] = value(&task.description);
+    if let Some(probe) = &task.probe {
+        table["probe"] = value(probe);
+    }
+    table["command"] = value(&task.command);
+
+    let is_existing_id = |id: Option<&str>| id == Some(task.id.as_str());
+
+    match document.get_mut("tasks") {
+        None => {
+            let mut tasks = ArrayOfTables::new();
+            tasks.push(table);
+            document["tasks"] = Item::ArrayOfTables(tasks);
+        }
+        Some(Item::ArrayOfTables(tasks)) => {
+            if tasks
+                .iter()
+                .any(|existing| is_existing_id(existing.get("id").and_then(Item::as_str)))
+            {
+                return Ok(None);
+            }
+            tasks.push(table);
+        }
+        Some(Item::Value(Value::Array(tasks))) => {
+            if tasks
+                .iter()
+                .filter_map(Value::as_inline_table)
+                .any(|existing| is_existing_id(existing.get("id").and_then(Value::as_str)))
+            {
+                return Ok(None);
+            }
+            tasks.push(table.into_inline_table());
+        }
+        Some(_) => {
+            return Err(CommandError::ConfigEdit(String::from(
+                "\"tasks\" is not a list of tasks",
+            )))
+        }
+    }
+
+    Ok(Some(document.to_string()))
+}
+
+/// The lines of the edited document that are not in the original, which only had lines added in
+/// a single place
+fn added_lines<'a>(original: &str, edited: &'a str) -> Vec<&'a str> {
+    let original: Vec<&str> = original.lines().collect();
+    let edited: Vec<&str> = edited.lines().collect();
+
+    let common_start = original
+        .iter()
+        .zip(&edited)
+        .take_while(|(original, edited)| original == edited)
+        .count();
+    let common_end = original[common_start..]
+        .iter()
+        .rev()
+        .zip(edited[common_start..].iter().rev())
+        .take_while(|(original, edited)| original == edited)
+        .count();
+
+    edited[common_start..edited.len() - common_end].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_task(id: &str) -> NewTask {
+        NewTask {
+            id: id.to_owned(),
+            description: String::from("remuxes the file"),
+            command: String::from("ffmpeg -i \"$OMZET_INPUT\" -c copy \"$OMZET_OUTPUT\""),
+            probe: None,
+        }
+    }
+
+    #[test]
+    fn task_is_appended_keeping_comments() {
+        let original = r#"# comment that must be kept
+[[tasks]]
+id = "probe_codec" # trailing comment
+description = "probes the codec"
+command = "ffprobe \"$OMZET_INPUT\""
+
+[[workflows]]
+name = "movies"
+"#;
+
+        let edited = append_task(original, &new_task("remux")).unwrap().unwrap();
+
+        assert_eq!(
+            vec![
+                "[[tasks]]",
+                "id = \"remux\"",
+                "description = \"remuxes the file\"",
+                r#"command = 'ffmpeg -i "$OMZET_INPUT" -c copy "$OMZET_OUTPUT"'"#,
+                "",
+            ],
+            added_lines(original, &edited)
+        );
+        assert!(edited.starts_with(original.split("\n\n").next().unwrap()));
+        assert!(edited.ends_with("[[workflows]]\nname = \"movies\"\n"));
+
+        assert!(append_task(&edited, &new_task("remux")).unwrap().is_none());
+    }
+
+    #[test]
+    fn task_is_appended_to_inline_tasks() {
+        let original = "# no tasks yet\ntasks = []\n";
+
+        let edited = append_task(original, &new_task("remux")).unwrap().unwrap();
+
+        assert!(edited.starts_with("# no tasks yet\ntasks = [{ id = \"remux\","));
+        assert!(append_task(&edited, &new_task("remux")).unwrap().is_none());
+    }
+}
@@ -0,0 +1,252 @@
+use std::{
+    fmt::Display,
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+use crate::config::write_atomically;
+
+use super::CommandError;
+
+/// A workflow that a generated config can start out with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Preset {
+    TranscodeH265,
+    NormalizeAudio,
+    RemuxMkv,
+}
+
+impl Preset {
+    const ALL: [Preset; 3] = [
+        Preset::TranscodeH265,
+        Preset::NormalizeAudio,
+        Preset::RemuxMkv,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Preset::TranscodeH265 => "transcode-h265",
+            Preset::NormalizeAudio => "normalize-audio",
+            Preset::RemuxMkv => "remux-mkv",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Preset::TranscodeH265 => "re-encode video to H265, keeping all other streams",
+            Preset::NormalizeAudio => "normalize the loudness of the audio",
+            Preset::RemuxMkv => "rewrite the container without re-encoding any stream",
+        }
+    }
+
+    fn default_extensions(&self) -> &'static str {
+        match self {
+            Preset::TranscodeH265 | Preset::NormalizeAudio => "mkv,mp4",
+            Preset::RemuxMkv => "mkv",
+        }
+    }
+
+    /// The task definition of the preset, as TOML
+    fn task(&self) -> &'static str {
+        match self {
+            Preset::TranscodeH265 => {
+                r#"[[tasks]]
+id = "transcode-h265"
+description = "Re-encodes the video to H265, keeping all other streams"
+probe = '''
+    CODEC=$(ffprobe "$OMZET_INPUT" -show_entries stream=codec_name -select_streams v:0 -of compact=p=0:nk=1 -v 0)
+
+    if [ "$CODEC" = "hevc" ]; then
+        exit 1
+    fi
+'''
+command = '''
+    ffmpeg -i "$OMZET_INPUT" -map 0 -c copy -c:v libx265 "$OMZET_OUTPUT"
+'''
+"#
+            }
+            Preset::NormalizeAudio => {
+                r#"[[tasks]]
+id = "normalize-audio"
+description = "Normalizes the loudness of the audio"
+command = '''
+    ffmpeg -i "$OMZET_INPUT" -map 0 -c copy -c:a aac -af loudnorm "$OMZET_OUTPUT"
+'''
+"#
+            }
+            Preset::RemuxMkv => {
+                r#"[[tasks]]
+id = "remux-mkv"
+description = "Rewrites the container without re-encoding any stream"
+command = '''
+    ffmpeg -i "$OMZET_INPUT" -map 0 -c copy -f matroska "$OMZET_OUTPUT"
+'''
+"#
+            }
+        }
+    }
+}
+
+impl Display for Preset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name(), self.description())
+    }
+}
+
+/// The answers to the prompts
+#[derive(Debug, Clone)]
+struct Answers {
+    library_name: String,
+    directory: String,
+    extensions: String,
+    scratchpad_directory: String,
+    preset: Preset,
+}
+
+pub(super) fn run(output: Option<&Path>) -> Result<(), CommandError> {
+    // prompts go to stderr, so the generated config can be redirected from stdout
+    let answers = ask_questions(&mut io::stdin().lock(), &mut io::stderr())?;
+    let config = render_config(&answers);
+
+    match output {
+        Some(output) => {
+            write_atomically(output, config.as_bytes())?;
+            eprintln!("configuration written to {}", output.to_string_lossy());
+        }
+        None => print!("{config}"),
+    }
+
+    Ok(())
+}
+
+fn ask_questions(input: &mut impl BufRead, output: &mut impl Write) -> io::Result<Answers> {
+    let library_name = ask(input, output, "name of the library", "movies")?;
+    let directory = ask(
+        input,
+        output,
+        "directory of the library",
+        "/home/omzet/Movies",
+    )?;
+
+    writeln!(output, "available workflows:")?;
+    for (index, preset) in Preset::ALL.iter().enumerate() {
+        writeln!(output, "  {}. {preset}", index + 1)?;
+    }
+
+    let preset = loop {
+        let choice = ask(input, output, "workflow", Preset::ALL[0].name())?;
+
+        let preset = Preset::ALL
+            .iter()
+            .enumerate()
+            .find(|(index, preset)| choice == (index + 1).to_string() || choice == preset.name());
+
+        match preset {
+            Some((_, preset)) => break *preset,
+            None => writeln!(output, "unknown workflow \"{choice}\"")?,
+        }
+    };
+
+    let extensions = ask(
+        input,
+        output,
+        "extensions to include",
+        preset.default_extensions(),
+    )?;
+    let scratchpad_directory = ask(input, output, "scratchpad directory", "/tmp/omzet")?;
+
+    Ok(Answers {
+        library_name,
+        directory,
+        extensions,
+        scratchpad_directory,
+        preset,
+    })
+}
+
+/// Ask a single question, falling back to the default on an empty answer
+fn ask(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    question: &str,
+    default: &str,
+) -> io::Result<String> {
+    write!(output, "{question} [{default}]: ")?;
+    output.flush()?;
+
+    let mut answer = String::new();
+    if input.read_line(&mut answer)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "input ended before all questions were answered",
+        ));
+    }
+
+    let answer = answer.trim();
+
+    if answer.is_empty() {
+        return Ok(default.to_owned());
+    }
+
+    Ok(answer.to_owned())
+}
+
+/// Render the answers into a TOML config
+fn render_config(answers: &Answers) -> String {
+    let quote = |value: &str| toml::Value::String(value.to_owned()).to_string();
+
+    let extensions: Vec<String> = answers
+        .extensions
+        .split([',', ' '])
+        .map(|extension| extension.trim().trim_start_matches('.'))
+        .filter(|extension| !extension.is_empty())
+        .map(quote)
+        .collect();
+
+    format!(
+        r#"[libraries.{library}]
+directory = {directory}
+workflow = {workflow}
+
+[[workflows]]
+name = {workflow}
+scratchpad_directory = {scratchpad_directory}
+included_extensions = [{extensions}]
+tasks = [{task}]
+
+{task_definition}"#,
+        library = quote(&answers.library_name),
+        directory = quote(&answers.directory),
+        workflow = quote(answers.preset.name()),
+        scratchpad_directory = quote(&answers.scratchpad_directory),
+        extensions = extensions.join(", "),
+        task = quote(answers.preset.name()),
+        task_definition = answers.preset.task(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::TomlConfig;
+
+    use super::*;
+
+    #[test]
+    fn generated_config_is_valid() {
+        let mut input = "series\n/media/series\n3\n\n\n".as_bytes();
+        let answers = ask_questions(&mut input, &mut io::sink()).unwrap();
+
+        assert_eq!(Preset::RemuxMkv, answers.preset);
+        assert_eq!("mkv", answers.extensions);
+
+        for preset in Preset::ALL {
+            let config = render_config(&Answers {
+                preset,
+                ..answers.clone()
+            });
+            let toml_config = toml::from_str::<TomlConfig>(&config).unwrap();
+
+            assert!(toml_config.build_workflow(preset.name()).is_ok());
+        }
+    }
+}
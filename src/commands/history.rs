@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+use rusqlite::Connection;
+
+use crate::{
+    cli::{HistoryCommand, HistoryFormat},
+    db::{self, HistoryEntry},
+    workflow::BuiltinTask,
+};
+
+use super::CommandError;
+
+pub(super) fn run(
+    filter: db::HistoryFilter,
+    format: HistoryFormat,
+    command: Option<HistoryCommand>,
+) -> Result<(), CommandError> {
+    let connection = db::get_connection()?;
+
+    match command {
+        None => list(&connection, filter, format),
+        Some(HistoryCommand::Prune { older_than }) => prune(&connection, older_than),
+    }
+}
+
+/// Print the recorded jobs matching the filter, most recent first
+fn list(
+    connection: &Connection,
+    mut filter: db::HistoryFilter,
+    format: HistoryFormat,
+) -> Result<(), CommandError> {
+    // the history names builtin tasks like the job summary does, rather than by their id
+    if let Some(builtin_task) = filter
+        .task
+        .as_deref()
+        .and_then(|task| BuiltinTask::try_from(task).ok())
+    {
+        filter.task = Some(builtin_task.name().to_owned());
+    }
+
+    let entries = db::list_history(connection, &filter)?;
+
+    if format == HistoryFormat::Jsonl {
+        for entry in entries {
+            println!("{}", to_json_line(&entry));
+        }
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        println!("no jobs have been recorded");
+        return Ok(());
+    }
+
+    for entry in entries {
+        let timing = match (entry.duration_seconds, entry.throughput_bytes_per_second) {
+            (Some(duration), Some(throughput)) => format!(
+                "{duration:.1}s ({:.2} MiB/s)",
+                throughput / (1024.0 * 1024.0)
+            ),
+            (Some(duration), None) => format!("{duration:.1}s"),
+            _ => String::from("-"),
+        };
+        let tasks = entry.task_counts.map_or_else(
+            || String::from("-"),
+            |counts| format!("{} ran, {} skipped", counts.run, counts.skipped),
+        );
+
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            entry.recorded_at,
+            entry.workflow,
+            entry.outcome,
+            tasks,
+            timing,
+            entry.version.as_deref().unwrap_or("-"),
+            entry.source_file_path.to_string_lossy()
+        );
+        for builtin in &entry.builtins {
+            println!("\t{builtin}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Serialize a job as a single line of JSON
+fn to_json_line(entry: &HistoryEntry) -> String {
+    serde_json::to_string(entry).expect("a history entry can always be serialized")
+}
+
+/// Remove recorded jobs that are older than the given duration
+fn prune(connection: &Connection, older_than: Duration) -> Result<(), CommandError> {
+    let pruned = db::prune_reports(connection, older_than)?;
+
+    println!("pruned {pruned} job(s) from the history");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn job_is_serialized_as_a_single_json_line() {
+        let entry = HistoryEntry {
+            source_file_path: PathBuf::from("/movies/movie\nwith newline.mkv"),
+            workflow: String::from("movies"),
+            outcome: String::from("completed"),
+            recorded_at: String::from("2024-01-01 18:30:00"),
+            duration_seconds: Some(4.0),
+            throughput_bytes_per_second: None,
+            task_counts: Some(db::TaskCounts { run: 2, skipped: 1 }),
+            version: Some(String::from("0.1.0")),
+            builtins: vec![String::from("builtin.transcode_to_h265")],
+        };
+
+        assert_eq!(
+            r#"{"source_file_path":"/movies/movie\nwith newline.mkv","workflow":"movies","outcome":"completed","recorded_at":"2024-01-01 18:30:00","duration_seconds":4.0,"throughput_bytes_per_second":null,"task_counts":{"run":2,"skipped":1},"version":"0.1.0","builtins":["builtin.transcode_to_h265"]}"#,
+            to_json_line(&entry)
+        );
+    }
+}
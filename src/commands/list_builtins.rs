@@ -0,0 +1,20 @@
+use crate::workflow::BuiltinTask;
+
+use super::CommandError;
+
+/// Print every builtin task with its description and the parameters it accepts
+pub(super) fn run() -> Result<(), CommandError> {
+    for builtin_task in BuiltinTask::all() {
+        println!("{}", builtin_task.config_key());
+        println!("    {}", builtin_task.description());
+
+        for (parameter, default) in builtin_task.parameters() {
+            println!(
+                "    - {} ({}, default \"{}\"): {}",
+                parameter.name, parameter.kind, default, parameter.description
+            );
+        }
+    }
+
+    Ok(())
+}
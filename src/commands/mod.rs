@@ -0,0 +1,98 @@
+//! Commands that can be run from the CLI besides monitoring the libraries
+
+mod add_task;
+mod generate_config;
+mod history;
+mod list_builtins;
+mod plan;
+mod quarantine;
+mod reprocess;
+mod run_file;
+mod show_workflow;
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    app::ScanningError,
+    cli::Command,
+    config::ConfigError,
+    db::{DbError, HistoryFilter},
+    workflow_runner::RunnerError,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum CommandError {
+    #[error("unable to query the state database: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error(transparent)]
+    DatabaseInit(#[from] DbError),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Runner(#[from] RunnerError),
+    #[error("no configured library uses a workflow named \"{0}\"")]
+    UnknownWorkflow(String),
+    #[error("no library named \"{0}\" is configured")]
+    UnknownLibrary(String),
+    #[error("unable to scan library: {0}")]
+    Scanning(#[from] ScanningError),
+    #[error(
+        "\"{0}\" is not part of any configured library, pass the workflow to use with --workflow"
+    )]
+    NoWorkflowForFile(PathBuf),
+    #[error("unable to access file \"{1}\": {0}")]
+    InaccessibleFile(#[source] std::io::Error, PathBuf),
+    #[error("scratchpad directory \"{0}\" is not writable")]
+    ScratchpadNotWritable(PathBuf),
+    #[error("unable to edit the configuration: {0}")]
+    ConfigEdit(String),
+    #[error("unable to read input or write output: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Run a single command to completion, commands that need the configuration read it from `config`
+pub(crate) fn run(command: Command, config: Option<&Path>) -> Result<(), CommandError> {
+    match command {
+        Command::RunFile {
+            file,
+            workflow,
+            scratchpad_dir,
+            dry_run,
+        } => run_file::run(&file, workflow.as_deref(), scratchpad_dir, dry_run, config),
+        Command::GenerateConfig { output } => generate_config::run(output.as_deref()),
+        Command::History {
+            since,
+            failed,
+            task,
+            format,
+            action,
+        } => history::run(
+            HistoryFilter {
+                since,
+                failed,
+                task,
+            },
+            format,
+            action,
+        ),
+        Command::Quarantine { action } => quarantine::run(action),
+        Command::ListBuiltins => list_builtins::run(),
+        Command::AddTask {
+            id,
+            description,
+            command,
+            probe,
+        } => add_task::run(
+            add_task::NewTask {
+                id,
+                description,
+                command,
+                probe,
+            },
+            config,
+        ),
+        Command::ShowWorkflow { workflow, format } => show_workflow::run(&workflow, format, config),
+        Command::Plan { library } => plan::run(library.as_deref(), config),
+        Command::Reprocess { workflow } => reprocess::run(&workflow, config),
+    }
+}
@@ -0,0 +1,62 @@
+use std::path::Path;
+
+use crate::{
+    app::scan,
+    config::read_config,
+    workflow_runner::{ProbeResult, Runner},
+};
+
+use super::CommandError;
+
+/// Print for every file in the libraries which tasks of its workflow would run. Nothing is copied
+/// and no task runs, but the probes do run, on the original files.
+pub(super) fn run(library_name: Option<&str>, config: Option<&Path>) -> Result<(), CommandError> {
+    let config = read_config(config)?;
+
+    let libraries: Vec<_> = config
+        .libraries
+        .iter()
+        .filter(|library| library_name.is_none_or(|name| library.name == name))
+        .collect();
+
+    if let (Some(library_name), true) = (library_name, libraries.is_empty()) {
+        return Err(CommandError::UnknownLibrary(library_name.to_owned()));
+    }
+
+    // without a probe cache, planning leaves the state database untouched
+    let runner = Runner::new();
+
+    for library in libraries {
+        let files = scan(library)?;
+
+        println!(
+            "library \"{}\" with workflow \"{}\", {} files",
+            library.name,
+            library.workflow.name,
+            files.len()
+        );
+
+        for file in files {
+            println!("{}", file.to_string_lossy());
+
+            match runner.plan(&library.workflow, &file) {
+                Ok(probed_tasks) => {
+                    for task in probed_tasks.to_run {
+                        println!("  run   {}", task.description());
+                    }
+                    for (task, probe_result) in probed_tasks.skipped {
+                        match probe_result {
+                            ProbeResult::Abort => {
+                                println!("  skip  {} (probe aborted)", task.description())
+                            }
+                            _ => println!("  skip  {}", task.description()),
+                        }
+                    }
+                }
+                Err(err) => println!("  not processed: {err}"),
+            }
+        }
+    }
+
+    Ok(())
+}
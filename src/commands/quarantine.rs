@@ -0,0 +1,46 @@
+use std::path::Path;
+
+use rusqlite::Connection;
+
+use crate::{cli::QuarantineCommand, db};
+
+use super::CommandError;
+
+pub(super) fn run(command: QuarantineCommand) -> Result<(), CommandError> {
+    let connection = db::get_connection()?;
+
+    match command {
+        QuarantineCommand::List => list(&connection),
+        QuarantineCommand::Clear { file } => clear(&connection, file.as_deref()),
+    }
+}
+
+/// Print all quarantined files
+fn list(connection: &Connection) -> Result<(), CommandError> {
+    let files = db::list_quarantined(connection)?;
+
+    if files.is_empty() {
+        println!("no files are quarantined");
+        return Ok(());
+    }
+
+    for file in files {
+        println!(
+            "{}\t{} failures\tlast failed at {}",
+            file.source_file_path.to_string_lossy(),
+            file.failure_count,
+            file.last_failed_at
+        );
+    }
+
+    Ok(())
+}
+
+/// Release one or all files from the quarantine
+fn clear(connection: &Connection, file: Option<&Path>) -> Result<(), CommandError> {
+    let released = db::clear_quarantine(connection, file)?;
+
+    println!("released {released} file(s) from quarantine");
+
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use std::path::Path;
+
+use crate::{config::read_config, db, workflow::Task};
+
+use super::CommandError;
+
+/// Clear the cached probe results of the workflow's tasks and the output fingerprints of the
+/// files in its libraries. Without those, the next scan runs the workflow for every file again.
+pub(super) fn run(workflow_name: &str, config: Option<&Path>) -> Result<(), CommandError> {
+    let config = read_config(config)?;
+
+    let libraries: Vec<_> = config
+        .libraries
+        .iter()
+        .filter(|library| library.workflow.name == workflow_name)
+        .collect();
+
+    let Some(workflow) = libraries.first().map(|library| &library.workflow) else {
+        return Err(CommandError::UnknownWorkflow(workflow_name.to_owned()));
+    };
+
+    let task_ids: Vec<&str> = workflow
+        .tasks
+        .iter()
+        .filter_map(|task| match task {
+            Task::Custom(custom_task) => Some(custom_task.id.as_str()),
+            Task::Builtin(_) => None,
+        })
+        .collect();
+
+    let connection = db::get_connection()?;
+
+    let cleared_probes = db::clear_probe_cache(&connection, &task_ids)?;
+
+    let mut cleared_fingerprints = 0;
+    for library in libraries.iter() {
+        cleared_fingerprints += db::clear_output_fingerprints(&connection, &library.directory)?;
+    }
+
+    println!(
+        "cleared {cleared_probes} cached probe results and {cleared_fingerprints} fingerprints, \
+        the next scan reprocesses {} libraries using workflow \"{workflow_name}\"",
+        libraries.len()
+    );
+
+    Ok(())
+}
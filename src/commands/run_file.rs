@@ -0,0 +1,102 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use tracing::info;
+
+use crate::{
+    config::read_config,
+    workflow_runner::{Runner, WorkflowRunner},
+    Workflow,
+};
+
+use super::CommandError;
+
+pub(super) fn run(
+    file: &Path,
+    workflow_name: Option<&str>,
+    scratchpad_directory: Option<PathBuf>,
+    dry_run: bool,
+    config: Option<&Path>,
+) -> Result<(), CommandError> {
+    let file = file
+        .canonicalize()
+        .map_err(|err| CommandError::InaccessibleFile(err, file.to_path_buf()))?;
+
+    let mut workflow = find_workflow(&file, workflow_name, config)?;
+
+    if let Some(scratchpad_directory) = scratchpad_directory {
+        workflow.scratchpad_directory = scratchpad_directory;
+    }
+
+    if dry_run {
+        let scratchpad_directory = &workflow.scratchpad_directory;
+
+        if !is_writable_directory(scratchpad_directory) {
+            return Err(CommandError::ScratchpadNotWritable(
+                scratchpad_directory.clone(),
+            ));
+        }
+
+        println!(
+            "would run workflow \"{}\" for {} in scratchpad {}, considering tasks:",
+            workflow.name,
+            file.to_string_lossy(),
+            scratchpad_directory.to_string_lossy()
+        );
+        for task in workflow.tasks.iter() {
+            println!("- {}", task.description());
+        }
+
+        return Ok(());
+    }
+
+    let report = Runner::new().run_workflow(&workflow, file.clone())?;
+
+    info!("finished file {}", file.to_string_lossy());
+    println!("{report}");
+
+    Ok(())
+}
+
+/// Find the workflow by name, or otherwise the workflow of the library that contains the file
+fn find_workflow(
+    file: &Path,
+    workflow_name: Option<&str>,
+    config: Option<&Path>,
+) -> Result<Workflow, CommandError> {
+    let config = read_config(config)?;
+
+    let library = match workflow_name {
+        Some(workflow_name) => config
+            .libraries
+            .iter()
+            .find(|library| library.workflow.name == workflow_name)
+            .ok_or_else(|| CommandError::UnknownWorkflow(workflow_name.to_owned()))?,
+        None => config
+            .libraries
+            .iter()
+            .find(|library| {
+                library
+                    .directory
+                    .canonicalize()
+                    .is_ok_and(|directory| file.starts_with(directory))
+            })
+            .ok_or_else(|| CommandError::NoWorkflowForFile(file.to_path_buf()))?,
+    };
+
+    Ok(library.workflow.clone())
+}
+
+/// Whether the directory is writable, or could be created in a writable parent.
+/// Nothing is created while checking.
+fn is_writable_directory(directory: &Path) -> bool {
+    let Some(existing) = directory.ancestors().find(|path| path.exists()) else {
+        return false;
+    };
+
+    fs::metadata(existing)
+        .map(|metadata| metadata.is_dir() && !metadata.permissions().readonly())
+        .unwrap_or(false)
+}
@@ -0,0 +1,192 @@
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::{
+    cli::WorkflowFormat,
+    config::read_config,
+    workflow::{ProbeAbortPolicy, Task},
+    Workflow,
+};
+
+use super::CommandError;
+
+/// Characters of a script that are shown as text, longer scripts are truncated
+const SCRIPT_PREVIEW_LENGTH: usize = 60;
+
+/// Print the workflow as it is resolved from the configuration, with every task and its scripts
+pub(super) fn run(
+    workflow_name: &str,
+    format: WorkflowFormat,
+    config: Option<&Path>,
+) -> Result<(), CommandError> {
+    let config = read_config(config)?;
+
+    let workflow = config
+        .libraries
+        .iter()
+        .map(|library| &library.workflow)
+        .find(|workflow| workflow.name == workflow_name)
+        .ok_or_else(|| CommandError::UnknownWorkflow(workflow_name.to_owned()))?;
+
+    let overview = WorkflowOverview::from(workflow);
+
+    match format {
+        WorkflowFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&overview).expect("a workflow can always be serialized")
+        ),
+        WorkflowFormat::Text => print_text(&overview),
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct WorkflowOverview<'a> {
+    name: &'a str,
+    scratchpad_directory: String,
+    included_extensions: &'a [String],
+    excluded_patterns: &'a [String],
+    read_only: bool,
+    tasks: Vec<TaskOverview<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum TaskOverview<'a> {
+    Custom {
+        id: &'a str,
+        description: &'a str,
+        probe: Option<&'a str>,
+        command: &'a str,
+        on_probe_abort: ProbeAbortPolicy,
+        applies_to_extensions: Option<&'a [String]>,
+        container: Option<&'a str>,
+    },
+    Builtin {
+        /// The reference with the value of every parameter
+        id: String,
+        description: &'a str,
+    },
+}
+
+impl<'a> From<&'a Workflow> for WorkflowOverview<'a> {
+    fn from(workflow: &'a Workflow) -> Self {
+        let tasks = workflow
+            .tasks
+            .iter()
+            .map(|task| match task {
+                Task::Custom(custom_task) => TaskOverview::Custom {
+                    id: &custom_task.id,
+                    description: &custom_task.description,
+                    probe: custom_task.probe.as_ref().map(|probe| probe.as_str()),
+                    command: custom_task.command.as_str(),
+                    on_probe_abort: custom_task.on_probe_abort,
+                    applies_to_extensions: custom_task.applies_to_extensions.as_deref(),
+                    container: custom_task
+                        .container
+                        .as_ref()
+                        .map(|container| container.image.as_str()),
+                },
+                Task::Builtin(builtin_task) => TaskOverview::Builtin {
+                    id: builtin_task.reference(),
+                    description: builtin_task.description(),
+                },
+            })
+            .collect();
+
+        Self {
+            name: &workflow.name,
+            scratchpad_directory: workflow.scratchpad_directory.to_string_lossy().into_owned(),
+            included_extensions: &workflow.included_extensions,
+            excluded_patterns: &workflow.excluded_patterns,
+            read_only: workflow.read_only,
+            tasks,
+        }
+    }
+}
+
+fn print_text(overview: &WorkflowOverview) {
+    println!("workflow \"{}\"", overview.name);
+    println!("  scratchpad directory: {}", overview.scratchpad_directory);
+    println!(
+        "  included extensions: {}",
+        overview.included_extensions.join(", ")
+    );
+    if !overview.excluded_patterns.is_empty() {
+        println!(
+            "  excluded patterns: {}",
+            overview.excluded_patterns.join(", ")
+        );
+    }
+    if overview.read_only {
+        println!("  read-only, the source files are never replaced");
+    }
+
+    println!("tasks, in the order they run:");
+    for (number, task) in overview.tasks.iter().enumerate() {
+        match task {
+            TaskOverview::Custom {
+                id,
+                description,
+                probe,
+                command,
+                on_probe_abort,
+                applies_to_extensions,
+                container,
+            } => {
+                println!("  {}. {id}: {description}", number + 1);
+                if let Some(probe) = probe {
+                    println!("     probe: {}", preview(probe));
+                    println!("     on probe abort: {}", policy_name(*on_probe_abort));
+                }
+                println!("     command: {}", preview(command));
+                if let Some(extensions) = applies_to_extensions {
+                    println!("     applies to: {}", extensions.join(", "));
+                }
+                if let Some(image) = container {
+                    println!("     runs in container: {image}");
+                }
+            }
+            TaskOverview::Builtin { id, description } => {
+                println!("  {}. {id}: {description}", number + 1);
+            }
+        }
+    }
+}
+
+fn policy_name(policy: ProbeAbortPolicy) -> &'static str {
+    match policy {
+        ProbeAbortPolicy::Abort => "abort",
+        ProbeAbortPolicy::Run => "run",
+        ProbeAbortPolicy::Skip => "skip",
+    }
+}
+
+/// The script on a single line, truncated when it is too long to show
+fn preview(script: &str) -> String {
+    let line = script.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    match line.char_indices().nth(SCRIPT_PREVIEW_LENGTH) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_scripts_are_previewed_on_a_single_line() {
+        assert_eq!("exit 1", preview("exit 1"));
+        assert_eq!(
+            "echo \"$OMZET_INPUT\" ffmpeg -i",
+            preview("\n    echo \"$OMZET_INPUT\"\n\n    ffmpeg -i\n")
+        );
+
+        let preview = preview(&"é".repeat(SCRIPT_PREVIEW_LENGTH + 1));
+        assert_eq!(format!("{}...", "é".repeat(SCRIPT_PREVIEW_LENGTH)), preview);
+    }
+}
@@ -1,15 +1,29 @@
 use std::{
     collections::{HashMap, HashSet},
     env,
+    fmt::Display,
     fs::{self, create_dir, exists},
+    io::{self, Read, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
     string::FromUtf8Error,
+    time::Duration,
 };
 
-use serde::Deserialize;
-use tracing::{debug, error, info};
+use globset::Glob;
+use serde::{Deserialize, Deserializer};
+use tempfile::NamedTempFile;
+use tracing::{debug, error, info, level_filters::LevelFilter, warn};
 
 use crate::{
-    workflow::{BuiltinTask, CustomTask, Library, Task, UnknownBuiltinTask},
+    duration::{parse_duration, InvalidDuration},
+    workflow::{
+        BuiltinTask, ContainerRuntime, CustomTask, InvalidBuiltinTask, InvalidRunnable, Library,
+        LinkCollisionPolicy, ProbeAbortPolicy, ResourceLimits, Runnable, SortStrategy, Task,
+        TaskContainer, UnchangedOutputCheck, UndefinedVariable, DEFAULT_OUTPUT_CAPTURE_BYTES,
+    },
     Workflow,
 };
 
@@ -17,6 +31,8 @@ use crate::{
 pub(crate) enum ConfigError {
     #[error("no HOME environment variable is set -- cannot know where configuration lives")]
     MissingHomeEnvironmentVariable,
+    #[error("unable to expand \"~\" in \"{0}\", the home directory is unknown")]
+    TildeExpansionFailed(String),
     #[error("unable to access directory of configuration: {0}")]
     UnableToAccessDirectory(std::io::Error),
     #[error("unable to create directory for configuration: {0}")]
@@ -29,21 +45,226 @@ pub(crate) enum ConfigError {
     UnableToReadConfiguration(std::io::Error),
     #[error("unable to deserialize config toml: {0}")]
     UnableToDeserialize(toml::de::Error),
+    #[error("{location}: missing field \"{field}\"{}", example_hint(.example))]
+    MissingField {
+        location: ConfigLocation,
+        field: String,
+        example: Option<String>,
+    },
+    #[error("{location}: invalid value for \"{field}\", {message}{}", example_hint(.example))]
+    InvalidValue {
+        location: ConfigLocation,
+        field: String,
+        message: String,
+        example: Option<String>,
+    },
+    #[error("{location}: {message}")]
+    InvalidToml {
+        location: ConfigLocation,
+        message: String,
+    },
     #[error("workflow with name \"{0}\", referenced in config, does not exist")]
     UnknownWorkflow(String),
     #[error(transparent)]
-    UnknownBuiltinTask(#[from] UnknownBuiltinTask),
+    InvalidBuiltinTask(#[from] InvalidBuiltinTask),
     #[error("custom task with id \"{0}\" was referenced, but it is not configured")]
     UnknownCustomTask(String),
+    #[error("task \"{task_id}\" depends on \"{dependency}\", which is not a task of workflow \"{workflow}\"")]
+    UnknownTaskDependency {
+        workflow: String,
+        task_id: String,
+        dependency: String,
+    },
+    #[error("tasks of workflow \"{workflow}\" cannot be ordered, their dependencies form a cycle: {}", .tasks.join(", "))]
+    TaskCycle {
+        workflow: String,
+        tasks: Vec<String>,
+    },
+    #[error("invalid extension \"{extension}\" in included_extensions of workflow \"{workflow}\", only letters, digits, \"-\", \"_\" and \".\" are allowed")]
+    InvalidExtension { workflow: String, extension: String },
+    #[error("invalid extension \"{extension}\" in included_extensions of library \"{library}\", only letters, digits, \"-\", \"_\" and \".\" are allowed")]
+    InvalidLibraryExtension { library: String, extension: String },
+    #[error("invalid output_file_mode {mode:#o} of workflow \"{workflow}\", only permission bits up to 0o7777 are allowed")]
+    InvalidFileMode { workflow: String, mode: u32 },
+    #[error(
+        "task \"{task_id}\" references an undefined variable in workflow \"{workflow}\": {source}"
+    )]
+    UndefinedVariable {
+        workflow: String,
+        task_id: String,
+        source: UndefinedVariable,
+    },
+    #[error("invalid exclude pattern \"!{0}\" in included_extensions: {1}")]
+    InvalidExcludePattern(String, #[source] globset::Error),
+    #[error(transparent)]
+    InvalidDuration(#[from] InvalidDuration),
+    #[error("invalid log level \"{0}\" in logging configuration")]
+    InvalidLogLevel(String),
+    #[error("task \"{0}\" is invalid: {errors}", errors = .1.join(", "))]
+    InvalidTask(String, Vec<String>),
+    #[error("task \"{0}\" has an empty command")]
+    EmptyTaskCommand(String),
+    #[error("task \"{task_id}\" has an invalid probe or command: {source}")]
+    InvalidTaskScript {
+        task_id: String,
+        source: InvalidRunnable,
+    },
+    #[error("task \"{task_id}\" has an invalid command: {syntax_error}")]
+    InvalidTaskCommand {
+        task_id: String,
+        syntax_error: String,
+    },
 }
 
 const EXAMPLE_CONFIG: &str = include_str!("../example/config.toml");
 
 pub(crate) struct Config {
     pub(crate) libraries: Vec<Library>,
+    /// Amount of failures after which a file is quarantined, 0 disables the quarantine
+    pub(crate) quarantine_threshold: u32,
+    /// How long a job is deferred when its file is still being written to by another process
+    pub(crate) defer_duration: Duration,
+    /// How long a file that was just processed is not queued again
+    pub(crate) completion_cooldown: Duration,
+    /// Amount of jobs that run at the same time, over all libraries
+    pub(crate) max_concurrent_jobs: usize,
+    /// Jobs in the history older than this are pruned on startup
+    pub(crate) prune_history_older_than: Option<Duration>,
+    /// Write the full output of each task to a log file in the state directory
+    pub(crate) write_task_logs: bool,
+    /// How the libraries find out about new files
+    pub(crate) monitor_mode: MonitorMode,
+    pub(crate) logging: LoggingConfig,
+}
+
+/// Path that makes omzet read its configuration from stdin, e.g. when it is generated by a pipeline
+pub(crate) const STDIN_PATH: &str = "-";
+
+/// Read the configuration from the given file, or from stdin when the path is "-".
+/// Without a path the default configuration file is read, an example is written there when it does not exist yet.
+pub(crate) fn read_config(path: Option<&Path>) -> Result<Config, ConfigError> {
+    let data = match path {
+        Some(path) if path == Path::new(STDIN_PATH) => {
+            debug!("reading configuration from stdin");
+            let mut data = String::new();
+            io::stdin()
+                .read_to_string(&mut data)
+                .map_err(ConfigError::UnableToReadConfiguration)?;
+            data
+        }
+        Some(path) => read_config_file(path)?,
+        None => read_config_file(&default_config_file()?)?,
+    };
+
+    parse_config(&data)
+}
+
+/// Where in the configuration a problem was found
+#[derive(Debug)]
+pub(crate) struct ConfigLocation {
+    line: usize,
+    /// The table the problem is in, e.g. `[[workflows]] "movies"`, absent at the top level
+    block: Option<String>,
+}
+
+impl Display for ConfigLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.block {
+            Some(block) => write!(f, "line {}, in {block}", self.line),
+            None => write!(f, "line {}, at the top level", self.line),
+        }
+    }
+}
+
+fn example_hint(example: &Option<String>) -> String {
+    example
+        .as_ref()
+        .map(|example| format!(", e.g. `{example}`"))
+        .unwrap_or_default()
+}
+
+/// Turn the terse error of deserializing the configuration into one that tells where the problem is.
+/// The error of a field that is missing or invalid also gets an example from the example configuration.
+fn describe_toml_error(data: &str, err: toml::de::Error) -> ConfigError {
+    let Some(span) = err.span() else {
+        return ConfigError::UnableToDeserialize(err);
+    };
+
+    let line = data[..span.start].matches('\n').count() + 1;
+    let location = ConfigLocation {
+        line,
+        block: enclosing_block(data, line),
+    };
+    // syntax errors span multiple lines, e.g. "invalid table header\nexpected `.`, `]]`"
+    let message = err.message().replace('\n', ", ");
+
+    if let Some(field) = message
+        .strip_prefix("missing field `")
+        .and_then(|field| field.strip_suffix('`'))
+    {
+        return ConfigError::MissingField {
+            location,
+            example: example_of(field),
+            field: field.to_owned(),
+        };
+    }
+
+    // the span of an invalid value only covers the value, so its key is on the same line
+    let key = data
+        .lines()
+        .nth(line - 1)
+        .and_then(|line| line.split_once('='))
+        .map(|(key, _)| key.trim().trim_matches('"'))
+        .filter(|key| !key.is_empty() && !key.starts_with('['));
+
+    match key {
+        Some(field) if message.starts_with("invalid") => ConfigError::InvalidValue {
+            location,
+            example: example_of(field),
+            field: field.to_owned(),
+            message,
+        },
+        _ => ConfigError::InvalidToml { location, message },
+    }
+}
+
+/// Describe the table the given line is in, using the name or id of the entry when it has one
+fn enclosing_block(data: &str, line: usize) -> Option<String> {
+    let lines: Vec<&str> = data.lines().collect();
+    let header_index = lines
+        .iter()
+        .take(line)
+        .rposition(|line| line.trim_start().starts_with('['))?;
+    let header = lines[header_index].trim();
+
+    let name = lines[header_index + 1..]
+        .iter()
+        .map(|line| line.trim())
+        .take_while(|line| !line.starts_with('['))
+        .filter_map(|line| line.split_once('='))
+        .find(|(key, _)| matches!(key.trim(), "name" | "id"))
+        .map(|(_, value)| value.trim().trim_matches('"'));
+
+    Some(match name {
+        Some(name) if header.starts_with("[[") => format!("{header} \"{name}\""),
+        _ => header.to_owned(),
+    })
 }
 
-pub(crate) fn read_config() -> Result<Config, ConfigError> {
+/// The line of the example configuration that sets the field, also when it is commented out there
+fn example_of(field: &str) -> Option<String> {
+    EXAMPLE_CONFIG
+        .lines()
+        .map(|line| line.trim_start_matches(['#', ' ']))
+        .find(|line| {
+            line.split_once('=')
+                .is_some_and(|(key, _)| key.trim() == field)
+        })
+        .map(String::from)
+}
+
+/// The default configuration file, which is created with the example configuration when missing
+pub(crate) fn default_config_file() -> Result<PathBuf, ConfigError> {
     let home_dir = env::var_os("HOME")
         .ok_or(ConfigError::MissingHomeEnvironmentVariable)?
         .to_string_lossy()
@@ -60,36 +281,295 @@ pub(crate) fn read_config() -> Result<Config, ConfigError> {
             .map_err(ConfigError::UnableToCreateDirectory)
             .and_then(|_| {
                 debug!("writing example config because none exists");
-                fs::write(&config_file_path, EXAMPLE_CONFIG)
+                write_atomically(Path::new(&config_file_path), EXAMPLE_CONFIG.as_bytes())
                     .map_err(ConfigError::UnableToWriteExampleConfiguration)
             })?;
     }
 
-    let toml_config = fs::read(config_file_path)
+    Ok(PathBuf::from(config_file_path))
+}
+
+fn read_config_file(path: &Path) -> Result<String, ConfigError> {
+    fs::read(path)
         .map_err(ConfigError::UnableToReadConfiguration)
         .and_then(|bytes| String::from_utf8(bytes).map_err(ConfigError::UnableToReadConfigAsUtf8))
-        .and_then(|data| {
-            toml::from_str::<TomlConfig>(&data).map_err(ConfigError::UnableToDeserialize)
-        })?;
+}
+
+/// Deserialize and validate the configuration, wherever it was read from
+fn parse_config(data: &str) -> Result<Config, ConfigError> {
+    let mut toml_config =
+        toml::from_str::<TomlConfig>(data).map_err(|err| describe_toml_error(data, err))?;
+
+    toml_config.expand_home_directories()?;
+
+    if toml_config.validate_commands {
+        validate_task_commands(&toml_config.tasks)?;
+    }
+
+    toml_config.logging.validate()?;
 
     let config = Config {
+        quarantine_threshold: toml_config.quarantine_threshold,
+        defer_duration: Duration::from_secs(toml_config.defer_seconds),
+        completion_cooldown: Duration::from_secs(toml_config.completion_cooldown_seconds),
+        max_concurrent_jobs: toml_config.max_concurrent_jobs.get(),
+        prune_history_older_than: toml_config
+            .prune_history_older_than
+            .as_deref()
+            .map(parse_duration)
+            .transpose()?,
+        write_task_logs: toml_config.write_task_logs,
+        monitor_mode: toml_config.mode,
+        logging: toml_config.logging.clone(),
         libraries: denormalize_config(toml_config)?,
     };
 
     Ok(config)
 }
 
+/// Expand a leading `~/` or `~username/` into the home directory of the current or given user
+pub(crate) fn expand_home(path: &str) -> Result<String, ConfigError> {
+    let Some(rest) = path.strip_prefix('~') else {
+        return Ok(path.to_owned());
+    };
+
+    let (user, remainder) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+
+    let home_directory = if user.is_empty() {
+        env::var("HOME").ok()
+    } else {
+        home_directory_of(user)
+    };
+
+    home_directory
+        .map(|home_directory| format!("{home_directory}{remainder}"))
+        .ok_or_else(|| ConfigError::TildeExpansionFailed(path.to_owned()))
+}
+
+/// Look up the home directory of a user in the passwd database
+fn home_directory_of(user: &str) -> Option<String> {
+    fs::read_to_string("/etc/passwd")
+        .ok()?
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<&str>>())
+        .find(|fields| fields.first() == Some(&user))
+        .and_then(|fields| {
+            fields
+                .get(5)
+                .map(|home_directory| home_directory.to_string())
+        })
+}
+
+/// Write the contents to a file by writing a temporary file in the same directory and renaming it.
+/// An interrupted write therefore never leaves a partially written file behind.
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    write_atomically_with(path, contents, |_| Ok(()))
+}
+
+/// Like [`write_atomically`], calling `before_rename` with the written temporary file right
+/// before it replaces the file. An error it returns stops the write.
+fn write_atomically_with(
+    path: &Path,
+    contents: &[u8],
+    before_rename: impl FnOnce(&Path) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    // a symlink is kept, the file it points to is replaced instead
+    let path = match fs::canonicalize(path) {
+        Ok(target) => target,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => path.to_path_buf(),
+        Err(err) => return Err(err),
+    };
+    let directory = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    let mut temp_file = NamedTempFile::new_in(directory)?;
+    // the temporary file is only accessible by its owner, a replaced file keeps its permissions
+    if let Ok(metadata) = fs::metadata(&path) {
+        temp_file
+            .as_file()
+            .set_permissions(metadata.permissions())?;
+    }
+    temp_file.write_all(contents)?;
+    temp_file.as_file().sync_all()?;
+    before_rename(temp_file.path())?;
+    temp_file.persist(&path).map_err(|err| err.error)?;
+
+    Ok(())
+}
+
 #[derive(Debug, Deserialize)]
 pub struct TomlConfig {
     pub(crate) libraries: HashMap<String, LibraryConfig>,
     pub(crate) workflows: Vec<WorkflowConfig>,
     pub(crate) tasks: Vec<TaskConfig>,
+    /// Check the task commands and probes for shell syntax errors when loading the config
+    #[serde(default)]
+    pub(crate) validate_commands: bool,
+    /// Amount of failures after which a file is quarantined, 0 disables the quarantine
+    #[serde(default = "default_quarantine_threshold")]
+    pub(crate) quarantine_threshold: u32,
+    /// Seconds a job is deferred when its file is still being written to by another process
+    #[serde(default = "default_defer_seconds")]
+    pub(crate) defer_seconds: u64,
+    /// Seconds a file that was just processed is not queued again, 0 disables the cooldown
+    #[serde(default = "default_completion_cooldown_seconds")]
+    pub(crate) completion_cooldown_seconds: u64,
+    /// Amount of jobs that run at the same time, over all libraries
+    #[serde(default = "default_max_concurrent_jobs")]
+    pub(crate) max_concurrent_jobs: NonZeroUsize,
+    /// Prune jobs older than this duration (e.g. "90d") from the history on startup
+    pub(crate) prune_history_older_than: Option<String>,
+    /// Write the full output of each task to a log file in the state directory
+    #[serde(default)]
+    pub(crate) write_task_logs: bool,
+    /// Whether the libraries are only scanned periodically, or also watched for changes
+    #[serde(default)]
+    pub(crate) mode: MonitorMode,
+    #[serde(default)]
+    pub(crate) logging: LoggingConfig,
+}
+
+/// How a library monitor finds out about new files
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum MonitorMode {
+    /// Only scan periodically, which works on every filesystem
+    #[default]
+    Poll,
+    /// Also scan as soon as a file is added, falls back to polling when changes cannot be watched
+    Watch,
+}
+
+impl TomlConfig {
+    /// Expand "~" in the configured paths, which is not done by the filesystem itself
+    fn expand_home_directories(&mut self) -> Result<(), ConfigError> {
+        for library in self.libraries.values_mut() {
+            library.directory = expand_home(&library.directory)?;
+        }
+
+        for workflow in self.workflows.iter_mut() {
+            workflow.scratchpad_directory = expand_home(&workflow.scratchpad_directory)?;
+            for destination in workflow.link_destinations.iter_mut() {
+                *destination = expand_home(destination)?;
+            }
+        }
+
+        if let Some(file) = &self.logging.file {
+            self.logging.file = Some(PathBuf::from(expand_home(&file.to_string_lossy())?));
+        }
+
+        Ok(())
+    }
+}
+
+fn default_quarantine_threshold() -> u32 {
+    3
+}
+
+fn default_max_concurrent_jobs() -> NonZeroUsize {
+    NonZeroUsize::MIN
+}
+
+fn default_defer_seconds() -> u64 {
+    30
+}
+
+fn default_completion_cooldown_seconds() -> u64 {
+    300
+}
+
+fn default_parallel_probes() -> NonZeroUsize {
+    NonZeroUsize::MIN
+}
+
+/// Configures the verbosity of the logs. The `RUST_LOG` environment variable takes precedence.
+#[derive(Debug, Deserialize, Clone)]
+pub(crate) struct LoggingConfig {
+    /// The default log level, e.g. "info" or "debug"
+    #[serde(default = "default_log_level")]
+    pub(crate) level: String,
+    /// Log levels per module, e.g. `"omzet::workflow_runner" = "debug"`
+    #[serde(default)]
+    pub(crate) module_levels: HashMap<String, String>,
+    /// Also write logs to this file, rotated daily. A date suffix is added to its name.
+    pub(crate) file: Option<PathBuf>,
+}
+
+fn default_log_level() -> String {
+    String::from("info")
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: default_log_level(),
+            module_levels: HashMap::new(),
+            file: None,
+        }
+    }
+}
+
+impl LoggingConfig {
+    /// Ensure all configured levels are valid
+    fn validate(&self) -> Result<(), ConfigError> {
+        let levels = std::iter::once(&self.level).chain(self.module_levels.values());
+
+        for level in levels {
+            LevelFilter::from_str(level)
+                .map_err(|_| ConfigError::InvalidLogLevel(level.clone()))?;
+        }
+
+        Ok(())
+    }
+
+    /// The same configuration, but only logging warnings and errors
+    pub(crate) fn quiet(&self) -> Self {
+        Self {
+            level: String::from("warn"),
+            module_levels: HashMap::new(),
+            file: self.file.clone(),
+        }
+    }
+
+    /// The configuration as filter directives, in the same syntax as `RUST_LOG`
+    pub(crate) fn directives(&self) -> String {
+        let mut directives = vec![self.level.clone()];
+
+        directives.extend(
+            self.module_levels
+                .iter()
+                .map(|(module, level)| format!("{module}={level}")),
+        );
+
+        directives.join(",")
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub(crate) struct LibraryConfig {
     pub(crate) directory: String,
     pub(crate) workflow: String,
+    #[serde(default)]
+    pub(crate) scan_sort: SortStrategy,
+    /// Only process files of which the size did not change between two consecutive scans
+    #[serde(default)]
+    pub(crate) wait_for_stable_size: bool,
+    /// Also scan files and directories of which the name starts with a "."
+    #[serde(default)]
+    pub(crate) scan_hidden: bool,
+    /// Dispatch at most this many files per scan, unlimited by default
+    pub(crate) max_file_count_per_scan: Option<usize>,
+    /// Run at most this many jobs of the library at the same time, on top of the global limit
+    pub(crate) max_concurrent_jobs: Option<NonZeroUsize>,
+    /// Jobs of libraries with a higher priority are started first, 0 by default
+    #[serde(default)]
+    pub(crate) priority: u8,
+    /// Replaces the extensions of the workflow for this library, patterns starting with "!" are
+    /// excluded on top of the workflow's
+    #[serde(default, deserialize_with = "deserialize_optional_extensions")]
+    pub(crate) included_extensions: Option<HashSet<String>>,
 }
 
 impl TomlConfig {
@@ -99,52 +579,225 @@ impl TomlConfig {
             .find(|workflow_config| workflow_config.name == name)
             .ok_or(ConfigError::UnknownWorkflow(name.to_string()))
             .and_then(|workflow_config| {
-                let tasks = self.build_tasks(&workflow_config.tasks)?;
+                let tasks = self.build_tasks(workflow_config)?;
+
+                let (included_extensions, excluded_patterns) =
+                    parse_extensions(&workflow_config.included_extensions, |extension| {
+                        ConfigError::InvalidExtension {
+                            workflow: workflow_config.name.clone(),
+                            extension,
+                        }
+                    })?;
+
+                if let Some(mode) = workflow_config
+                    .output_file_mode
+                    .filter(|mode| *mode > 0o7777)
+                {
+                    return Err(ConfigError::InvalidFileMode {
+                        workflow: workflow_config.name.clone(),
+                        mode,
+                    });
+                }
 
                 Ok(Workflow {
                     name: workflow_config.name.clone(),
-                    scratchpad_directory: workflow_config.scratchpad_directory.clone(),
-                    included_extensions: workflow_config
-                        .included_extensions
-                        .clone()
-                        .into_iter()
-                        .collect(),
+                    scratchpad_directory: PathBuf::from(&workflow_config.scratchpad_directory),
+                    included_extensions,
+                    excluded_patterns,
                     tasks,
+                    temp_prefix: workflow_config.temp_prefix.clone(),
+                    max_source_size_bytes: workflow_config.max_source_size_bytes,
+                    suppress_extension_warnings: workflow_config
+                        .suppress_extension_warnings
+                        .clone(),
+                    resource_limits: ResourceLimits {
+                        cpu_quota_percent: workflow_config.cpu_quota_percent,
+                        memory_limit_bytes: workflow_config.memory_limit_bytes,
+                    },
+                    read_only: workflow_config.read_only,
+                    io_retries: workflow_config.io_retries,
+                    output_file_mode: workflow_config.output_file_mode,
+                    link_destinations: workflow_config
+                        .link_destinations
+                        .iter()
+                        .map(PathBuf::from)
+                        .collect(),
+                    on_link_collision: workflow_config.on_link_collision,
+                    verify_container: workflow_config.verify_container,
+                    unchanged_output_check: workflow_config.unchanged_output_check,
+                    parallel_probes: workflow_config.parallel_probes,
                 })
             })
     }
 
-    pub fn build_tasks(&self, ids: &[TaskId]) -> Result<Vec<Task>, ConfigError> {
-        let mut tasks = Vec::with_capacity(ids.len());
+    fn build_tasks(&self, workflow_config: &WorkflowConfig) -> Result<Vec<Task>, ConfigError> {
+        let mut tasks = Vec::with_capacity(workflow_config.tasks.len());
         // loop over names to ensure order
-        for id in ids {
+        for id in &workflow_config.tasks {
             if id.0.starts_with("builtin.") {
                 let builtin_task = BuiltinTask::try_from(id.0.as_str())?;
 
-                tasks.push(Task::Builtin(builtin_task));
+                tasks.push((Task::Builtin(builtin_task), &[][..]));
             } else {
-                let custom_task = self
+                let custom_task_config = self
                     .tasks
                     .iter()
                     .find(|t| t.id == *id)
                     .ok_or(ConfigError::UnknownCustomTask(id.0.clone()))?;
 
-                tasks.push(Task::Custom(custom_task.into()));
+                let mut custom_task = CustomTask::try_from(custom_task_config)?;
+                custom_task
+                    .substitute_variables(&workflow_config.vars)
+                    .map_err(|source| ConfigError::UndefinedVariable {
+                        workflow: workflow_config.name.clone(),
+                        task_id: id.0.clone(),
+                        source,
+                    })?;
+
+                custom_task.validate().map_err(|errors| {
+                    ConfigError::InvalidTask(
+                        id.0.clone(),
+                        errors.iter().map(ToString::to_string).collect(),
+                    )
+                })?;
+
+                tasks.push((
+                    Task::Custom(custom_task),
+                    &custom_task_config.depends_on[..],
+                ));
             }
         }
 
-        Ok(tasks)
+        order_by_dependencies(workflow_config, tasks)
     }
 }
 
-impl From<&TaskConfig> for CustomTask {
-    fn from(value: &TaskConfig) -> Self {
-        Self {
+/// Order the tasks so each runs after the tasks it depends on, otherwise keeping the order of the
+/// workflow. Every task works on the output of the task before it, so they always run one by one.
+fn order_by_dependencies(
+    workflow_config: &WorkflowConfig,
+    tasks: Vec<(Task, &[TaskId])>,
+) -> Result<Vec<Task>, ConfigError> {
+    let ids = &workflow_config.tasks;
+
+    let mut dependencies: Vec<Vec<usize>> = Vec::with_capacity(tasks.len());
+    for (id, (_, depends_on)) in ids.iter().zip(&tasks) {
+        let positions = depends_on
+            .iter()
+            .map(|dependency| {
+                ids.iter().position(|id| id == dependency).ok_or_else(|| {
+                    ConfigError::UnknownTaskDependency {
+                        workflow: workflow_config.name.clone(),
+                        task_id: id.0.clone(),
+                        dependency: dependency.0.clone(),
+                    }
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        dependencies.push(positions);
+    }
+
+    let mut is_ordered = vec![false; tasks.len()];
+    let mut order = Vec::with_capacity(tasks.len());
+    while order.len() < tasks.len() {
+        // the first task of the workflow of which every dependency is ordered goes next
+        let next = (0..tasks.len()).find(|&index| {
+            !is_ordered[index]
+                && dependencies[index]
+                    .iter()
+                    .all(|&dependency| is_ordered[dependency])
+        });
+
+        let Some(next) = next else {
+            return Err(ConfigError::TaskCycle {
+                workflow: workflow_config.name.clone(),
+                tasks: (0..tasks.len())
+                    .filter(|&index| !is_ordered[index])
+                    .map(|index| ids[index].0.clone())
+                    .collect(),
+            });
+        };
+
+        is_ordered[next] = true;
+        order.push(next);
+    }
+
+    let mut tasks: Vec<Option<Task>> = tasks.into_iter().map(|(task, _)| Some(task)).collect();
+
+    Ok(order
+        .into_iter()
+        .filter_map(|index| tasks[index].take())
+        .collect())
+}
+
+impl TryFrom<&TaskConfig> for CustomTask {
+    type Error = ConfigError;
+
+    fn try_from(value: &TaskConfig) -> Result<Self, Self::Error> {
+        let into_runnable = |script: &str| {
+            Runnable::new(script).map_err(|source| ConfigError::InvalidTaskScript {
+                task_id: value.id.0.clone(),
+                source,
+            })
+        };
+
+        let probe = match value.probe.as_deref() {
+            Some(probe) if probe.trim().is_empty() => {
+                warn!(
+                    "probe of task \"{}\" is empty, the task runs without a probe",
+                    value.id.0
+                );
+                None
+            }
+            probe => probe.map(into_runnable).transpose()?,
+        };
+
+        if value.command.trim().is_empty() {
+            return Err(ConfigError::EmptyTaskCommand(value.id.0.clone()));
+        }
+        let command = into_runnable(&value.command)?;
+
+        // scripts without a shebang are run by the default interpreter, which is written in front
+        // of them, so multi-line scripts work regardless
+        for script in probe.iter().chain([&command]) {
+            if !script.as_str().starts_with("#!") && script.as_str().contains('\n') {
+                debug!(
+                    "task \"{}\" has a multi-line script without an interpreter, it runs with /bin/sh",
+                    value.id.0
+                );
+            }
+        }
+
+        if !command.references_omzet_variables() {
+            debug!(
+                "command of task \"{}\" does not reference any OMZET_ variables",
+                value.id.0
+            );
+        }
+
+        Ok(Self {
             id: value.id.0.clone(),
             description: value.description.clone(),
-            probe: value.probe.clone(),
-            command: value.command.clone(),
-        }
+            probe,
+            command,
+            on_probe_abort: value.on_probe_abort,
+            applies_to_extensions: value.applies_to_extensions.as_ref().map(|extensions| {
+                extensions
+                    .iter()
+                    .map(|extension| extension.trim_start_matches('.').to_owned())
+                    .collect()
+            }),
+            probe_cache_ttl: value
+                .cacheable_probe
+                .then(|| Duration::from_secs(value.probe_cache_ttl_seconds)),
+            probe_timeout: (value.probe_timeout_seconds > 0)
+                .then(|| Duration::from_secs(value.probe_timeout_seconds)),
+            output_capture_bytes: value.output_capture_bytes,
+            container: value.container.as_ref().map(|image| TaskContainer {
+                image: image.clone(),
+                runtime: value.container_runtime,
+            }),
+        })
     }
 }
 
@@ -152,8 +805,159 @@ impl From<&TaskConfig> for CustomTask {
 struct WorkflowConfig {
     name: String,
     scratchpad_directory: String,
+    #[serde(deserialize_with = "deserialize_extensions")]
     included_extensions: HashSet<String>,
     tasks: Vec<TaskId>,
+    #[serde(default = "default_temp_prefix")]
+    temp_prefix: String,
+    /// Refuse to run for source files larger than this, e.g. to protect the scratchpad disk
+    max_source_size_bytes: Option<u64>,
+    /// Included extensions that are intentionally not a known media extension
+    #[serde(default)]
+    suppress_extension_warnings: Vec<String>,
+    /// Share of a single CPU each task may use, e.g. 200 for two full CPUs
+    cpu_quota_percent: Option<u32>,
+    /// Amount of memory each task may use
+    memory_limit_bytes: Option<u64>,
+    /// Tasks only inspect the source file, it is given to them directly and never replaced
+    #[serde(default)]
+    read_only: bool,
+    /// Retries of copying files from and to the library on transient errors, e.g. of a network mount
+    #[serde(default = "default_io_retries")]
+    io_retries: u32,
+    /// Permission bits of the transformed file, written in octal like `0o644`
+    output_file_mode: Option<u32>,
+    /// Directories the transformed file is hardlinked into as well
+    #[serde(default)]
+    link_destinations: Vec<String>,
+    #[serde(default)]
+    on_link_collision: LinkCollisionPolicy,
+    /// Check the first bytes of each file before processing it, skipping mislabeled files
+    #[serde(default)]
+    verify_container: bool,
+    #[serde(default)]
+    unchanged_output_check: UnchangedOutputCheck,
+    /// Amount of probes that run at the same time for a file
+    #[serde(default = "default_parallel_probes")]
+    parallel_probes: NonZeroUsize,
+    /// Values substituted for references like `${crf}` in the probes and commands of the tasks
+    #[serde(default, deserialize_with = "deserialize_variables")]
+    vars: HashMap<String, String>,
+}
+
+/// Variables can be configured as strings, or as numbers and booleans for convenience
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VariableValue {
+    Text(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+}
+
+fn deserialize_variables<'de, D>(deserializer: D) -> Result<HashMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let variables = HashMap::<String, VariableValue>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(name, value)| {
+            let value = match value {
+                VariableValue::Text(text) => text,
+                VariableValue::Integer(integer) => integer.to_string(),
+                VariableValue::Float(float) => float.to_string(),
+                VariableValue::Boolean(boolean) => boolean.to_string(),
+            };
+
+            (name, value)
+        })
+        .collect();
+
+    Ok(variables)
+}
+
+/// Extensions can be configured as an array or as a single comma or space separated string
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Extensions {
+    List(Vec<String>),
+    Separated(String),
+}
+
+fn deserialize_extensions<'de, D>(deserializer: D) -> Result<HashSet<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let extensions = match Extensions::deserialize(deserializer)? {
+        Extensions::List(extensions) => extensions.into_iter().collect(),
+        Extensions::Separated(extensions) => extensions
+            .split([',', ' '])
+            .map(str::trim)
+            .filter(|extension| !extension.is_empty())
+            .map(String::from)
+            .collect(),
+    };
+
+    Ok(extensions)
+}
+
+fn deserialize_optional_extensions<'de, D>(
+    deserializer: D,
+) -> Result<Option<HashSet<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_extensions(deserializer).map(Some)
+}
+
+/// Split the entries of included_extensions into the extensions and the patterns of files to
+/// exclude, which are the entries starting with "!". An invalid extension is turned into an error
+/// by `invalid_extension`.
+fn parse_extensions(
+    entries: &HashSet<String>,
+    invalid_extension: impl FnOnce(String) -> ConfigError,
+) -> Result<(Vec<String>, Vec<String>), ConfigError> {
+    let (excluded_patterns, included_extensions): (Vec<String>, Vec<String>) = entries
+        .iter()
+        .cloned()
+        .partition(|extension| extension.starts_with('!'));
+
+    // extensions end up in a glob alternation, which characters like "," would break
+    if let Some(extension) = included_extensions
+        .iter()
+        .find(|extension| !is_valid_extension(extension))
+    {
+        return Err(invalid_extension(extension.clone()));
+    }
+
+    let excluded_patterns = excluded_patterns
+        .into_iter()
+        .map(|pattern| {
+            let pattern = pattern.trim_start_matches('!').to_owned();
+
+            Glob::new(&pattern)
+                .map(|_| pattern.clone())
+                .map_err(|err| ConfigError::InvalidExcludePattern(pattern, err))
+        })
+        .collect::<Result<Vec<String>, ConfigError>>()?;
+
+    Ok((included_extensions, excluded_patterns))
+}
+
+/// Whether the extension only consists of characters that are safe in file names and globs
+fn is_valid_extension(extension: &str) -> bool {
+    !extension.is_empty()
+        && extension
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+fn default_temp_prefix() -> String {
+    String::from("omzet-")
+}
+
+fn default_io_retries() -> u32 {
+    3
 }
 
 #[derive(Debug, Deserialize, Eq, PartialEq)]
@@ -165,6 +969,92 @@ struct TaskConfig {
     description: String,
     probe: Option<String>,
     command: String,
+    #[serde(default)]
+    on_probe_abort: ProbeAbortPolicy,
+    applies_to_extensions: Option<Vec<String>>,
+    /// Cache the probe's result for unchanged files, only for probes without side effects
+    #[serde(default)]
+    cacheable_probe: bool,
+    #[serde(default = "default_probe_cache_ttl_seconds")]
+    probe_cache_ttl_seconds: u64,
+    /// Kill the probe when it runs longer than this, 0 disables the timeout
+    #[serde(default = "default_probe_timeout_seconds")]
+    probe_timeout_seconds: u64,
+    /// Bytes of each output stream that are kept whole, beyond it only the start and end are kept
+    #[serde(default = "default_output_capture_bytes")]
+    output_capture_bytes: usize,
+    /// Image of the container the command runs in, it runs directly on the host when absent
+    container: Option<String>,
+    #[serde(default)]
+    container_runtime: ContainerRuntime,
+    /// Tasks of the workflow that have to run before this one, whatever their place in it
+    #[serde(default)]
+    depends_on: Vec<TaskId>,
+}
+
+fn default_probe_timeout_seconds() -> u64 {
+    30
+}
+
+fn default_output_capture_bytes() -> usize {
+    DEFAULT_OUTPUT_CAPTURE_BYTES
+}
+
+fn default_probe_cache_ttl_seconds() -> u64 {
+    7 * 24 * 3600
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum CommandValidationError {
+    #[error("unable to run shell to validate command: {0}")]
+    UnableToRunShell(#[source] std::io::Error),
+    #[error("{0}")]
+    SyntaxError(String),
+}
+
+/// Check a command for shell syntax errors, without executing it
+pub(crate) fn validate_command(cmd: &str) -> Result<(), CommandValidationError> {
+    let mut child = Command::new("sh")
+        .arg("-n")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(CommandValidationError::UnableToRunShell)?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(cmd.as_bytes())
+            .map_err(CommandValidationError::UnableToRunShell)?;
+    }
+
+    let output = child
+        .wait_with_output()
+        .map_err(CommandValidationError::UnableToRunShell)?;
+
+    if !output.status.success() {
+        return Err(CommandValidationError::SyntaxError(
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Validate the commands and probes of all configured tasks
+fn validate_task_commands(tasks: &[TaskConfig]) -> Result<(), ConfigError> {
+    for task in tasks {
+        let scripts = task.probe.iter().chain(std::iter::once(&task.command));
+
+        for script in scripts {
+            validate_command(script).map_err(|err| ConfigError::InvalidTaskCommand {
+                task_id: task.id.0.clone(),
+                syntax_error: err.to_string(),
+            })?;
+        }
+    }
+
+    Ok(())
 }
 
 /// Denormalize the config into libraries configured with their workflows
@@ -172,13 +1062,459 @@ fn denormalize_config(config: TomlConfig) -> Result<Vec<Library>, ConfigError> {
     let mut libraries = Vec::with_capacity(config.libraries.len());
 
     for (name, library_config) in config.libraries.iter() {
-        libraries.push(Library::new(
+        let mut workflow = config.build_workflow(&library_config.workflow)?;
+
+        // libraries with different kinds of files can share a workflow, apart from its extensions
+        if let Some(extensions) = &library_config.included_extensions {
+            let (included_extensions, excluded_patterns) =
+                parse_extensions(extensions, |extension| {
+                    ConfigError::InvalidLibraryExtension {
+                        library: name.clone(),
+                        extension,
+                    }
+                })?;
+            workflow.included_extensions = included_extensions;
+            workflow.excluded_patterns.extend(excluded_patterns);
+        }
+
+        let mut library = Library::new(
             name.clone(),
-            config.build_workflow(&library_config.workflow)?,
+            workflow,
             (&library_config.directory).into(),
-        ));
+            library_config.scan_sort,
+            library_config.wait_for_stable_size,
+            library_config.scan_hidden,
+            library_config.max_file_count_per_scan,
+        );
+        library.max_concurrent_jobs = library_config.max_concurrent_jobs.map(NonZeroUsize::get);
+        library.priority = library_config.priority;
+
+        libraries.push(library);
     }
 
     info!("{:?}", libraries);
     Ok(libraries)
 }
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn interrupted_atomic_write_leaves_file_untouched() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let path = temp_test_dir.path().join("omzet.toml");
+
+        write_atomically(&path, EXAMPLE_CONFIG.as_bytes()).unwrap();
+        assert_eq!(EXAMPLE_CONFIG, fs::read_to_string(&path).unwrap());
+
+        // interrupted once the new contents are completely written, right before the rename
+        let result = write_atomically_with(&path, b"[libraries]", |temp_file| {
+            assert_eq!("[libraries]", fs::read_to_string(temp_file).unwrap());
+            assert_eq!(EXAMPLE_CONFIG, fs::read_to_string(&path).unwrap());
+            Err(io::Error::from(io::ErrorKind::Interrupted))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(EXAMPLE_CONFIG, fs::read_to_string(&path).unwrap());
+        // the temporary file is cleaned up as well
+        assert_eq!(1, fs::read_dir(temp_test_dir.path()).unwrap().count());
+
+        write_atomically(&path, b"validate_commands = true").unwrap();
+        assert_eq!(
+            "validate_commands = true",
+            fs::read_to_string(&path).unwrap()
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn atomic_write_keeps_symlink_and_permissions() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let target = temp_test_dir.path().join("omzet.toml");
+        let link = temp_test_dir.path().join("link.toml");
+        fs::write(&target, "validate_commands = false").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o644)).unwrap();
+        symlink(&target, &link).unwrap();
+
+        write_atomically(&link, b"validate_commands = true").unwrap();
+
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(
+            "validate_commands = true",
+            fs::read_to_string(&target).unwrap()
+        );
+        assert_eq!(
+            0o644,
+            fs::metadata(&target).unwrap().permissions().mode() & 0o7777
+        );
+    }
+
+    #[test]
+    fn home_directory_is_expanded() {
+        assert_eq!("/media/movies", expand_home("/media/movies").unwrap());
+        assert_eq!("media/~movies", expand_home("media/~movies").unwrap());
+
+        if let Ok(home_directory) = env::var("HOME") {
+            assert_eq!(
+                format!("{home_directory}/movies"),
+                expand_home("~/movies").unwrap()
+            );
+        }
+
+        #[cfg(target_os = "linux")]
+        assert_eq!("/root/movies", expand_home("~root/movies").unwrap());
+
+        assert!(matches!(
+            expand_home("~no-such-user/movies"),
+            Err(ConfigError::TildeExpansionFailed(_))
+        ));
+    }
+
+    #[test]
+    fn example_config_is_valid() {
+        let toml_config = toml::from_str::<TomlConfig>(EXAMPLE_CONFIG).unwrap();
+
+        assert!(toml_config.logging.validate().is_ok());
+        assert_eq!(1, denormalize_config(toml_config).unwrap().len());
+    }
+
+    #[test]
+    fn library_can_override_extensions_of_its_workflow() {
+        let toml_config = toml::from_str::<TomlConfig>(
+            r#"
+            tasks = []
+
+            [libraries.movies]
+            directory = "/movies"
+            workflow = "transcode"
+
+            [libraries.clips]
+            directory = "/clips"
+            workflow = "transcode"
+            included_extensions = "mp4, avi, !preview.*"
+
+            [[workflows]]
+            name = "transcode"
+            scratchpad_directory = "/tmp"
+            included_extensions = ["mkv", "!sample.*"]
+            tasks = []
+            "#,
+        )
+        .unwrap();
+
+        let libraries = denormalize_config(toml_config).unwrap();
+        let library = |name: &str| {
+            libraries
+                .iter()
+                .find(|library| library.name == name)
+                .unwrap()
+        };
+
+        assert_eq!(vec!["mkv"], library("movies").workflow.included_extensions);
+        assert_eq!(
+            vec!["sample.*"],
+            library("movies").workflow.excluded_patterns
+        );
+
+        let mut extensions = library("clips").workflow.included_extensions.clone();
+        extensions.sort();
+        assert_eq!(vec!["avi", "mp4"], extensions);
+        assert_eq!(
+            vec!["sample.*", "preview.*"],
+            library("clips").workflow.excluded_patterns
+        );
+    }
+
+    #[test]
+    fn config_is_read_from_given_file() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let path = temp_test_dir.path().join("omzet.toml");
+        write_atomically(&path, EXAMPLE_CONFIG.as_bytes()).unwrap();
+
+        assert_eq!(1, read_config(Some(&path)).unwrap().libraries.len());
+        assert!(matches!(
+            read_config(Some(&temp_test_dir.path().join("missing.toml"))),
+            Err(ConfigError::UnableToReadConfiguration(_))
+        ));
+    }
+
+    #[test]
+    fn included_extensions_can_be_an_array_or_a_string() {
+        let from_array = toml::from_str::<WorkflowConfig>(
+            r#"
+            name = "movies"
+            scratchpad_directory = "/tmp"
+            included_extensions = ["mkv", "mp4", "avi"]
+            tasks = []
+            "#,
+        )
+        .unwrap();
+
+        let from_string = toml::from_str::<WorkflowConfig>(
+            r#"
+            name = "movies"
+            scratchpad_directory = "/tmp"
+            included_extensions = "mkv, mp4 avi"
+            tasks = []
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(3, from_array.included_extensions.len());
+        assert_eq!(
+            from_array.included_extensions,
+            from_string.included_extensions
+        );
+    }
+
+    #[test]
+    fn deserialization_errors_are_located() {
+        let error_of = |data: &str| match parse_config(data) {
+            Err(err) => err.to_string(),
+            Ok(_) => panic!("configuration should be invalid"),
+        };
+        let config = r#"
+quarantine_threshold = 3
+tasks = []
+
+[libraries.movies]
+directory = "/media/movies"
+workflow = "movies"
+
+[[workflows]]
+name = "movies"
+scratchpad_directory = "/tmp/omzet/cache"
+included_extensions = ["mkv"]
+tasks = []
+"#;
+
+        assert_eq!(
+            "line 9, in [[workflows]] \"movies\": missing field \"scratchpad_directory\", e.g. `scratchpad_directory = \"/tmp/omzet/cache\"`",
+            error_of(&config.replace("scratchpad_directory =", "scratchpad ="))
+        );
+        assert_eq!(
+            "line 2, at the top level: invalid value for \"quarantine_threshold\", invalid type: string \"often\", expected u32, e.g. `quarantine_threshold = 3`",
+            error_of(&config.replace("= 3", "= \"often\""))
+        );
+        assert!(error_of(&config.replace("[[workflows]]", "[[workflows]\n"))
+            .starts_with("line 9, in [[workflows] \"movies\": invalid table header, expected"));
+    }
+
+    #[test]
+    fn extensions_with_glob_characters_are_rejected() {
+        let config_with_extensions = |extensions: &str| {
+            toml::from_str::<TomlConfig>(&format!(
+                r#"
+                tasks = []
+                libraries = {{}}
+
+                [[workflows]]
+                name = "movies"
+                scratchpad_directory = "/tmp"
+                included_extensions = {extensions}
+                tasks = []
+                "#
+            ))
+            .unwrap()
+        };
+
+        assert!(config_with_extensions(r#"["mkv", "tar.gz", "!sample.*"]"#)
+            .build_workflow("movies")
+            .is_ok());
+
+        for extensions in [r#"["mk,v"]"#, r#"["{mkv}"]"#, r#"["mk*"]"#] {
+            assert!(matches!(
+                config_with_extensions(extensions).build_workflow("movies"),
+                Err(ConfigError::InvalidExtension { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn workflow_variables_are_substituted_in_tasks() {
+        let config_with_vars = |vars: &str| {
+            toml::from_str::<TomlConfig>(&format!(
+                r#"
+                libraries = {{}}
+
+                [[tasks]]
+                id = "encode"
+                description = "encode"
+                command = "ffmpeg -i \"$OMZET_INPUT\" -crf ${{crf}} \"$OMZET_OUTPUT\""
+
+                [[workflows]]
+                name = "movies"
+                scratchpad_directory = "/tmp"
+                included_extensions = ["mkv"]
+                tasks = ["encode"]
+                {vars}
+                "#
+            ))
+            .unwrap()
+        };
+
+        let workflow = config_with_vars("vars = { crf = 23 }")
+            .build_workflow("movies")
+            .unwrap();
+        let Task::Custom(task) = &workflow.tasks[0] else {
+            panic!("expected a custom task");
+        };
+        assert_eq!(
+            "ffmpeg -i \"$OMZET_INPUT\" -crf 23 \"$OMZET_OUTPUT\"",
+            task.command.as_str()
+        );
+
+        assert!(matches!(
+            config_with_vars("").build_workflow("movies"),
+            Err(ConfigError::UndefinedVariable { .. })
+        ));
+    }
+
+    #[test]
+    fn empty_scripts_of_tasks_are_rejected_or_ignored() {
+        let task_config = |probe: &str, command: &str| {
+            toml::from_str::<TaskConfig>(&format!(
+                r#"
+                id = "encode"
+                description = "encode"
+                probe = "{probe}"
+                command = "{command}"
+                "#
+            ))
+            .unwrap()
+        };
+
+        let task = CustomTask::try_from(&task_config("  ", "echo done")).unwrap();
+        assert!(task.probe.is_none());
+
+        assert!(matches!(
+            CustomTask::try_from(&task_config("exit 0", " \\n ")),
+            Err(ConfigError::EmptyTaskCommand(task_id)) if task_id == "encode"
+        ));
+    }
+
+    #[test]
+    fn tasks_are_ordered_by_their_dependencies() {
+        let config_with_tasks = |tasks: &str| {
+            toml::from_str::<TomlConfig>(&format!(
+                r#"
+                libraries = {{}}
+
+                [[tasks]]
+                id = "subtitles"
+                description = "extracts subtitles"
+                command = "echo subtitles"
+                depends_on = ["remux"]
+
+                [[tasks]]
+                id = "remux"
+                description = "remuxes"
+                command = "echo remux"
+
+                [[tasks]]
+                id = "tag"
+                description = "tags"
+                command = "echo tag"
+                depends_on = ["subtitles"]
+
+                [[workflows]]
+                name = "movies"
+                scratchpad_directory = "/tmp"
+                included_extensions = ["mkv"]
+                tasks = {tasks}
+                "#
+            ))
+            .unwrap()
+        };
+
+        let workflow = config_with_tasks(r#"["tag", "subtitles", "remux"]"#)
+            .build_workflow("movies")
+            .unwrap();
+        let order: Vec<_> = workflow.tasks.iter().map(Task::description).collect();
+        assert_eq!(vec!["remux", "subtitles", "tag"], order);
+
+        assert!(matches!(
+            config_with_tasks(r#"["subtitles", "tag"]"#).build_workflow("movies"),
+            Err(ConfigError::UnknownTaskDependency { dependency, .. }) if dependency == "remux"
+        ));
+    }
+
+    #[test]
+    fn cyclic_task_dependencies_are_rejected() {
+        let config = toml::from_str::<TomlConfig>(
+            r#"
+            libraries = {}
+
+            [[tasks]]
+            id = "a"
+            description = "a"
+            command = "echo a"
+            depends_on = ["b"]
+
+            [[tasks]]
+            id = "b"
+            description = "b"
+            command = "echo b"
+            depends_on = ["a"]
+
+            [[tasks]]
+            id = "c"
+            description = "c"
+            command = "echo c"
+
+            [[workflows]]
+            name = "movies"
+            scratchpad_directory = "/tmp"
+            included_extensions = ["mkv"]
+            tasks = ["a", "b", "c"]
+            "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            config.build_workflow("movies"),
+            Err(ConfigError::TaskCycle { tasks, .. }) if tasks == ["a", "b"]
+        ));
+    }
+
+    #[test]
+    fn command_syntax_is_validated() {
+        assert!(validate_command("echo \"$OMZET_INPUT\"").is_ok());
+        assert!(matches!(
+            validate_command("echo \"unterminated"),
+            Err(CommandValidationError::SyntaxError(_))
+        ));
+    }
+
+    #[test]
+    fn logging_config_is_turned_into_directives() {
+        let logging = LoggingConfig {
+            level: String::from("warn"),
+            module_levels: HashMap::from([(
+                String::from("omzet::workflow_runner"),
+                String::from("debug"),
+            )]),
+            file: None,
+        };
+
+        assert!(logging.validate().is_ok());
+        assert_eq!("warn,omzet::workflow_runner=debug", logging.directives());
+        assert_eq!("warn", logging.quiet().directives());
+
+        let logging = LoggingConfig {
+            level: String::from("loud"),
+            module_levels: HashMap::new(),
+            file: None,
+        };
+
+        assert!(matches!(
+            logging.validate(),
+            Err(ConfigError::InvalidLogLevel(_))
+        ));
+    }
+}
@@ -1,43 +1,903 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use dirs::data_dir;
-use rusqlite::Connection;
+use rusqlite::{Connection, OptionalExtension};
 use rusqlite_migration::{Migrations, M};
+use serde::Serialize;
+
+use crate::duration::PointInTime;
 
 const DB_FILE_NAME: &str = "state.db";
 
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum DbError {
+    #[error("unable to determine the data directory of the current user")]
+    MissingDataDirectory,
+    #[error("unable to create state directory \"{}\": {source}", .path.display())]
+    CannotCreateStateDirectory {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("unable to open state database: {0}")]
+    CannotOpen(#[source] rusqlite::Error),
+    #[error("unable to migrate state database: {0}")]
+    CannotMigrate(#[source] rusqlite_migration::Error),
+}
+
 /// Will create a connection to the local DB.
-pub(crate) fn get_connection() -> Connection {
-    let db_file = get_state_directory().join(DB_FILE_NAME);
-    let mut connection = Connection::open(db_file.to_string_lossy().to_string()).unwrap();
+pub(crate) fn get_connection() -> Result<Connection, DbError> {
+    open_connection(&state_directory()?)
+}
 
-    let migrations = get_migrations();
+/// Directory where omzet keeps its state, such as the DB and the task logs
+pub(crate) fn state_directory() -> Result<PathBuf, DbError> {
+    data_dir()
+        .map(|path| path.join("omzet"))
+        .ok_or(DbError::MissingDataDirectory)
+}
 
-    migrations
+/// Open the DB in the given state directory and bring it up to date.
+/// Ensures that the directory exists if it does not yet exist.
+fn open_connection(state_directory: &Path) -> Result<Connection, DbError> {
+    fs::create_dir_all(state_directory).map_err(|source| DbError::CannotCreateStateDirectory {
+        path: state_directory.to_path_buf(),
+        source,
+    })?;
+
+    let mut connection =
+        Connection::open(state_directory.join(DB_FILE_NAME)).map_err(DbError::CannotOpen)?;
+
+    get_migrations()
         .to_latest(&mut connection)
-        .expect("unable to run migrations");
+        .map_err(DbError::CannotMigrate)?;
+
+    Ok(connection)
+}
+
+/// How many of the tasks of a job have run, and how many were skipped by their probe
+#[derive(Debug, Clone, Copy, Serialize)]
+pub(crate) struct TaskCounts {
+    pub(crate) run: usize,
+    pub(crate) skipped: usize,
+}
+
+/// Outcome recorded for jobs that failed
+pub(crate) const FAILED_OUTCOME: &str = "failed";
+
+/// The outcome of a job for a source file, to record in the history
+#[derive(Debug)]
+pub(crate) struct JobRecord<'a> {
+    pub(crate) source_file_path: &'a Path,
+    pub(crate) workflow: &'a str,
+    pub(crate) outcome: &'a str,
+    pub(crate) duration: Option<Duration>,
+    pub(crate) throughput_bytes_per_second: Option<f64>,
+    pub(crate) task_counts: Option<TaskCounts>,
+    /// Descriptions of the tasks that have run
+    pub(crate) tasks: Vec<String>,
+    /// References to the builtin tasks of the workflow with the parameters they used
+    pub(crate) builtins: Vec<String>,
+}
+
+/// Record the outcome of a job, along with the version of omzet that ran it
+pub(crate) fn record_job_history(connection: &Connection, job: &JobRecord) -> rusqlite::Result<()> {
+    connection.execute(
+        r#"
+        INSERT INTO job_history
+            (source_file_path, workflow, outcome, duration_seconds, throughput_bytes_per_second,
+                tasks_run, tasks_skipped, version, builtins, tasks)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+        "#,
+        (
+            job.source_file_path.to_string_lossy(),
+            job.workflow,
+            job.outcome,
+            job.duration.map(|duration| duration.as_secs_f64()),
+            job.throughput_bytes_per_second,
+            job.task_counts.map(|counts| counts.run),
+            job.task_counts.map(|counts| counts.skipped),
+            env!("CARGO_PKG_VERSION"),
+            join_lines(&job.builtins),
+            join_lines(&job.tasks),
+        ),
+    )?;
+
+    Ok(())
+}
+
+/// The recorded outcome of a job
+#[derive(Debug, Serialize)]
+pub(crate) struct HistoryEntry {
+    #[serde(serialize_with = "serialize_path_lossy")]
+    pub(crate) source_file_path: PathBuf,
+    pub(crate) workflow: String,
+    pub(crate) outcome: String,
+    pub(crate) recorded_at: String,
+    /// Seconds it took to process the file, absent for skipped files
+    pub(crate) duration_seconds: Option<f64>,
+    pub(crate) throughput_bytes_per_second: Option<f64>,
+    /// Amount of tasks that have run and that were skipped, absent for jobs recorded before it was
+    pub(crate) task_counts: Option<TaskCounts>,
+    /// Version of omzet that ran the job, absent for jobs recorded before it was
+    pub(crate) version: Option<String>,
+    /// References to the builtin tasks of the workflow with the parameters they used
+    pub(crate) builtins: Vec<String>,
+}
+
+/// Serialize a path as a string, replacing what is not valid UTF-8 as it is when printed
+fn serialize_path_lossy<S: serde::Serializer>(
+    path: &Path,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+/// Lists of names are stored one per line, or as NULL when there are none
+fn join_lines(names: &[String]) -> Option<String> {
+    (!names.is_empty()).then(|| names.join("\n"))
+}
+
+/// Which recorded jobs to list, every filter that is given must match
+#[derive(Debug, Default)]
+pub(crate) struct HistoryFilter {
+    /// Only jobs recorded since this point in time
+    pub(crate) since: Option<PointInTime>,
+    /// Only jobs that failed
+    pub(crate) failed: bool,
+    /// Only jobs in which the task with this description has run
+    pub(crate) task: Option<String>,
+}
+
+/// List the recorded jobs that match the filter, most recent first
+pub(crate) fn list_history(
+    connection: &Connection,
+    filter: &HistoryFilter,
+) -> rusqlite::Result<Vec<HistoryEntry>> {
+    let mut statement = connection.prepare(
+        r#"
+        SELECT source_file_path, workflow, outcome, recorded_at, duration_seconds,
+            throughput_bytes_per_second, tasks_run, tasks_skipped, version, builtins
+        FROM job_history
+        WHERE (?1 IS NULL OR recorded_at >= datetime(?1, ?2))
+            AND (NOT ?3 OR outcome = ?4)
+            AND (?5 IS NULL OR instr(char(10) || tasks || char(10), char(10) || ?5 || char(10)) > 0)
+        ORDER BY recorded_at DESC, id DESC
+        "#,
+    )?;
+
+    let (since, since_modifier) = filter.since.as_ref().map(sqlite_datetime).unzip();
+
+    let entries = statement
+        .query_map(
+            (
+                since,
+                since_modifier,
+                filter.failed,
+                FAILED_OUTCOME,
+                filter.task.as_deref(),
+            ),
+            |row| {
+                Ok(HistoryEntry {
+                    source_file_path: PathBuf::from(row.get::<_, String>(0)?),
+                    workflow: row.get(1)?,
+                    outcome: row.get(2)?,
+                    recorded_at: row.get(3)?,
+                    duration_seconds: row.get(4)?,
+                    throughput_bytes_per_second: row.get(5)?,
+                    task_counts: match (row.get(6)?, row.get(7)?) {
+                        (Some(run), Some(skipped)) => Some(TaskCounts { run, skipped }),
+                        _ => None,
+                    },
+                    version: row.get(8)?,
+                    builtins: row
+                        .get::<_, Option<String>>(9)?
+                        .map(|builtins| builtins.lines().map(String::from).collect())
+                        .unwrap_or_default(),
+                })
+            },
+        )?
+        .collect();
+
+    entries
+}
+
+/// Remove the recorded jobs that are older than the given duration.
+/// Returns the amount of removed jobs.
+pub(crate) fn prune_reports(
+    connection: &Connection,
+    older_than: Duration,
+) -> rusqlite::Result<usize> {
+    connection.execute(
+        "DELETE FROM job_history WHERE recorded_at < datetime('now', ?1)",
+        (sqlite_offset(older_than),),
+    )
+}
+
+/// Express a point in time as an SQLite time value and modifier, as the times are stored in UTC
+fn sqlite_datetime(since: &PointInTime) -> (String, String) {
+    match since {
+        PointInTime::Ago(duration) => (String::from("now"), sqlite_offset(*duration)),
+        PointInTime::Local(datetime) => (datetime.clone(), String::from("utc")),
+    }
+}
+
+/// Format a duration as an SQLite date modifier that goes back in time by that duration
+fn sqlite_offset(duration: Duration) -> String {
+    format!("-{} seconds", duration.as_secs())
+}
+
+/// A file that has been quarantined because it failed too often
+#[derive(Debug)]
+pub(crate) struct QuarantinedFile {
+    pub(crate) source_file_path: PathBuf,
+    pub(crate) failure_count: u32,
+    pub(crate) last_failed_at: String,
+}
+
+/// Record a failed job for a source file.
+/// Returns whether the file is quarantined, which happens once it reached the threshold.
+/// A threshold of 0 disables the quarantine.
+pub(crate) fn record_failure(
+    connection: &Connection,
+    source_file_path: &Path,
+    quarantine_threshold: u32,
+) -> rusqlite::Result<bool> {
+    let source_file_path = source_file_path.to_string_lossy();
+
+    connection.execute(
+        r#"
+        INSERT INTO file_failure (source_file_path, failure_count) VALUES (?1, 1)
+        ON CONFLICT (source_file_path) DO UPDATE SET
+            failure_count = failure_count + 1,
+            last_failed_at = CURRENT_TIMESTAMP
+        "#,
+        (&source_file_path,),
+    )?;
+
+    connection.execute(
+        r#"
+        UPDATE file_failure SET quarantined = 1
+        WHERE source_file_path = ?1 AND ?2 > 0 AND failure_count >= ?2
+        "#,
+        (&source_file_path, quarantine_threshold),
+    )?;
+
+    is_quarantined(connection, Path::new(source_file_path.as_ref()))
+}
+
+/// Forget earlier failures of a source file, e.g. because it has been processed successfully
+pub(crate) fn clear_failures(
+    connection: &Connection,
+    source_file_path: &Path,
+) -> rusqlite::Result<()> {
+    connection.execute(
+        "DELETE FROM file_failure WHERE source_file_path = ?1",
+        (source_file_path.to_string_lossy(),),
+    )?;
+
+    Ok(())
+}
+
+/// Whether the source file is quarantined
+pub(crate) fn is_quarantined(
+    connection: &Connection,
+    source_file_path: &Path,
+) -> rusqlite::Result<bool> {
+    let quarantined = connection
+        .query_row(
+            "SELECT quarantined FROM file_failure WHERE source_file_path = ?1",
+            (source_file_path.to_string_lossy(),),
+            |row| row.get::<_, bool>(0),
+        )
+        .optional()?;
+
+    Ok(quarantined.unwrap_or(false))
+}
+
+/// List all quarantined files
+pub(crate) fn list_quarantined(connection: &Connection) -> rusqlite::Result<Vec<QuarantinedFile>> {
+    let mut statement = connection.prepare(
+        r#"
+        SELECT source_file_path, failure_count, last_failed_at FROM file_failure
+        WHERE quarantined = 1
+        ORDER BY source_file_path
+        "#,
+    )?;
+
+    let files = statement
+        .query_map((), |row| {
+            Ok(QuarantinedFile {
+                source_file_path: PathBuf::from(row.get::<_, String>(0)?),
+                failure_count: row.get(1)?,
+                last_failed_at: row.get(2)?,
+            })
+        })?
+        .collect();
+
+    files
+}
+
+/// Release files from the quarantine, either a single file or all of them.
+/// Returns the amount of released files.
+pub(crate) fn clear_quarantine(
+    connection: &Connection,
+    source_file_path: Option<&Path>,
+) -> rusqlite::Result<usize> {
+    match source_file_path {
+        Some(path) => connection.execute(
+            "DELETE FROM file_failure WHERE quarantined = 1 AND source_file_path = ?1",
+            (path.to_string_lossy(),),
+        ),
+        None => connection.execute("DELETE FROM file_failure WHERE quarantined = 1", ()),
+    }
+}
+
+/// A job that was persisted in the queue
+#[derive(Debug)]
+pub(crate) struct PersistedJob {
+    pub(crate) library: String,
+    pub(crate) source_file_path: PathBuf,
+    /// Whether the job was running when it was last persisted
+    pub(crate) was_running: bool,
+}
+
+/// Persist a job that has been queued
+pub(crate) fn persist_queued_job(
+    connection: &Connection,
+    library: &str,
+    source_file_path: &Path,
+) -> rusqlite::Result<()> {
+    connection.execute(
+        r#"
+        INSERT INTO job_queue (library, source_file_path) VALUES (?1, ?2)
+        ON CONFLICT (source_file_path) DO NOTHING
+        "#,
+        (library, source_file_path.to_string_lossy()),
+    )?;
+
+    Ok(())
+}
+
+/// Mark a queued job as running
+pub(crate) fn mark_queued_job_running(
+    connection: &Connection,
+    source_file_path: &Path,
+) -> rusqlite::Result<()> {
+    connection.execute(
+        "UPDATE job_queue SET running = 1 WHERE source_file_path = ?1",
+        (source_file_path.to_string_lossy(),),
+    )?;
+
+    Ok(())
+}
+
+/// Remove a job from the persisted queue
+pub(crate) fn remove_queued_job(
+    connection: &Connection,
+    source_file_path: &Path,
+) -> rusqlite::Result<()> {
+    connection.execute(
+        "DELETE FROM job_queue WHERE source_file_path = ?1",
+        (source_file_path.to_string_lossy(),),
+    )?;
+
+    Ok(())
+}
+
+/// Load all persisted jobs, those that were running first and then in the order they were queued
+pub(crate) fn load_queued_jobs(connection: &Connection) -> rusqlite::Result<Vec<PersistedJob>> {
+    let mut statement = connection.prepare(
+        "SELECT library, source_file_path, running FROM job_queue ORDER BY running DESC, id",
+    )?;
 
+    let jobs = statement
+        .query_map((), |row| {
+            Ok(PersistedJob {
+                library: row.get(0)?,
+                source_file_path: PathBuf::from(row.get::<_, String>(1)?),
+                was_running: row.get(2)?,
+            })
+        })?
+        .collect();
+
+    jobs
+}
+
+/// Get the cached result of a task's probe for a file fingerprint,
+/// unless it was cached longer than `ttl` ago
+pub(crate) fn get_cached_probe_result(
+    connection: &Connection,
+    task_id: &str,
+    fingerprint: &str,
+    ttl: Duration,
+) -> rusqlite::Result<Option<String>> {
     connection
+        .query_row(
+            r#"
+            SELECT result FROM probe_cache
+            WHERE task_id = ?1 AND fingerprint = ?2 AND cached_at >= datetime('now', ?3)
+            "#,
+            (task_id, fingerprint, sqlite_offset(ttl)),
+            |row| row.get(0),
+        )
+        .optional()
 }
 
-/// Get the directory that stores the sqlite DB file
-/// Ensures that the directory exists if it does not yet exist.
-fn get_state_directory() -> PathBuf {
-    let directory = data_dir().map(|path| path.join("omzet")).unwrap();
+/// Cache the result of a task's probe for a file fingerprint, replacing any earlier result
+pub(crate) fn cache_probe_result(
+    connection: &Connection,
+    task_id: &str,
+    fingerprint: &str,
+    result: &str,
+) -> rusqlite::Result<()> {
+    connection.execute(
+        r#"
+        INSERT INTO probe_cache (task_id, fingerprint, result) VALUES (?1, ?2, ?3)
+        ON CONFLICT (task_id, fingerprint) DO UPDATE SET
+            result = excluded.result,
+            cached_at = CURRENT_TIMESTAMP
+        "#,
+        (task_id, fingerprint, result),
+    )?;
+
+    Ok(())
+}
 
-    fs::create_dir_all(&directory).unwrap();
+/// Amount of recent runs of a task that its expected duration is based on
+const TASK_DURATION_SAMPLE_SIZE: u32 = 50;
 
-    directory
+/// Record how long a task took to run
+pub(crate) fn record_task_duration(
+    connection: &Connection,
+    task_id: &str,
+    duration: Duration,
+) -> rusqlite::Result<()> {
+    connection.execute(
+        "INSERT INTO task_duration (task_id, duration_ms) VALUES (?1, ?2)",
+        (task_id, duration.as_millis() as u64),
+    )?;
+
+    Ok(())
 }
 
-fn get_migrations<'m>() -> Migrations<'m> {
-    Migrations::new(vec![M::up(
+/// The mean duration in milliseconds of the recent runs of a task, absent when it never ran
+pub(crate) fn mean_task_duration(
+    connection: &Connection,
+    task_id: &str,
+) -> rusqlite::Result<Option<u64>> {
+    let mean: Option<f64> = connection.query_row(
         r#"
+        SELECT AVG(duration_ms) FROM (
+            SELECT duration_ms FROM task_duration WHERE task_id = ?1 ORDER BY id DESC LIMIT ?2
+        )
+        "#,
+        (task_id, TASK_DURATION_SAMPLE_SIZE),
+        |row| row.get(0),
+    )?;
+
+    Ok(mean.map(|mean| mean.round() as u64))
+}
+
+/// Remove the cached probe results of the given tasks, returning how many were removed
+pub(crate) fn clear_probe_cache(
+    connection: &Connection,
+    task_ids: &[&str],
+) -> rusqlite::Result<usize> {
+    let mut cleared = 0;
+
+    for task_id in task_ids {
+        cleared += connection.execute("DELETE FROM probe_cache WHERE task_id = ?1", (task_id,))?;
+    }
+
+    Ok(cleared)
+}
+
+/// Remove the output fingerprints of all files within the directory, returning how many were removed
+pub(crate) fn clear_output_fingerprints(
+    connection: &Connection,
+    directory: &Path,
+) -> rusqlite::Result<usize> {
+    // the separator is added so a directory does not match its siblings with a longer name
+    let prefix = directory.join("").to_string_lossy().to_string();
+
+    connection.execute(
+        "DELETE FROM job_report WHERE substr(source_file_path, 1, length(?1)) = ?1",
+        (prefix,),
+    )
+}
+
+fn get_migrations<'m>() -> Migrations<'m> {
+    Migrations::new(vec![
+        M::up(
+            r#"
         CREATE TABLE job_report (
             id INTEGER PRIMARY KEY,
             source_file_path TEXT,
             output_file_fingerprint TEXT
         )
         "#,
-    )])
+        ),
+        M::up(
+            r#"
+        CREATE TABLE job_history (
+            id INTEGER PRIMARY KEY,
+            source_file_path TEXT NOT NULL,
+            workflow TEXT NOT NULL,
+            outcome TEXT NOT NULL,
+            recorded_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+        ),
+        M::up(
+            r#"
+        CREATE TABLE file_failure (
+            source_file_path TEXT PRIMARY KEY,
+            failure_count INTEGER NOT NULL,
+            quarantined INTEGER NOT NULL DEFAULT 0,
+            last_failed_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+        ),
+        M::up(
+            r#"
+        CREATE TABLE job_queue (
+            id INTEGER PRIMARY KEY,
+            library TEXT NOT NULL,
+            source_file_path TEXT NOT NULL UNIQUE,
+            running INTEGER NOT NULL DEFAULT 0
+        )
+        "#,
+        ),
+        M::up(
+            r#"
+        CREATE TABLE probe_cache (
+            task_id TEXT NOT NULL,
+            fingerprint TEXT NOT NULL,
+            result TEXT NOT NULL,
+            cached_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (task_id, fingerprint)
+        )
+        "#,
+        ),
+        M::up(
+            r#"
+        ALTER TABLE job_history ADD COLUMN duration_seconds REAL;
+        ALTER TABLE job_history ADD COLUMN throughput_bytes_per_second REAL;
+        "#,
+        ),
+        M::up(
+            r#"
+        ALTER TABLE job_history ADD COLUMN tasks_run INTEGER;
+        ALTER TABLE job_history ADD COLUMN tasks_skipped INTEGER;
+        "#,
+        ),
+        M::up(
+            r#"
+        ALTER TABLE job_history ADD COLUMN version TEXT;
+        ALTER TABLE job_history ADD COLUMN builtins TEXT;
+        "#,
+        ),
+        M::up("ALTER TABLE job_history ADD COLUMN tasks TEXT;"),
+        M::up(
+            r#"
+        CREATE TABLE task_duration (
+            id INTEGER PRIMARY KEY,
+            task_id TEXT NOT NULL,
+            duration_ms INTEGER NOT NULL,
+            recorded_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE INDEX task_duration_task_id ON task_duration (task_id);
+        "#,
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn get_test_connection() -> Connection {
+        let mut connection = Connection::open_in_memory().unwrap();
+        get_migrations().to_latest(&mut connection).unwrap();
+
+        connection
+    }
+
+    #[test]
+    fn unusable_state_directory_is_reported() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+
+        // a file in place of the data directory, permissions alone do not stop root
+        let data_directory = temp_test_dir.path().join("data");
+        fs::write(&data_directory, "").unwrap();
+
+        assert!(matches!(
+            open_connection(&data_directory.join("omzet")),
+            Err(DbError::CannotCreateStateDirectory { .. })
+        ));
+
+        assert!(open_connection(&temp_test_dir.path().join("omzet")).is_ok());
+    }
+
+    #[test]
+    fn file_is_quarantined_after_reaching_threshold() {
+        let connection = get_test_connection();
+        let path = Path::new("/library/movie.mkv");
+
+        assert!(!record_failure(&connection, path, 2).unwrap());
+        assert!(record_failure(&connection, path, 2).unwrap());
+        assert!(is_quarantined(&connection, path).unwrap());
+        assert_eq!(1, list_quarantined(&connection).unwrap().len());
+
+        assert_eq!(1, clear_quarantine(&connection, None).unwrap());
+        assert!(!is_quarantined(&connection, path).unwrap());
+    }
+
+    #[test]
+    fn mean_task_duration_is_based_on_recorded_runs() {
+        let connection = get_test_connection();
+
+        assert_eq!(None, mean_task_duration(&connection, "encode").unwrap());
+
+        record_task_duration(&connection, "encode", Duration::from_secs(2)).unwrap();
+        record_task_duration(&connection, "encode", Duration::from_secs(3)).unwrap();
+        record_task_duration(&connection, "remux", Duration::from_secs(60)).unwrap();
+
+        assert_eq!(
+            Some(2500),
+            mean_task_duration(&connection, "encode").unwrap()
+        );
+    }
+
+    #[test]
+    fn queued_jobs_are_restored_with_running_jobs_first() {
+        let connection = get_test_connection();
+
+        persist_queued_job(&connection, "movies", Path::new("/movies/a.mkv")).unwrap();
+        persist_queued_job(&connection, "movies", Path::new("/movies/b.mkv")).unwrap();
+        persist_queued_job(&connection, "movies", Path::new("/movies/b.mkv")).unwrap();
+        persist_queued_job(&connection, "movies", Path::new("/movies/c.mkv")).unwrap();
+        mark_queued_job_running(&connection, Path::new("/movies/b.mkv")).unwrap();
+        remove_queued_job(&connection, Path::new("/movies/c.mkv")).unwrap();
+
+        let jobs = load_queued_jobs(&connection).unwrap();
+
+        assert_eq!(2, jobs.len());
+        assert_eq!(Path::new("/movies/b.mkv"), jobs[0].source_file_path);
+        assert!(jobs[0].was_running);
+        assert_eq!(Path::new("/movies/a.mkv"), jobs[1].source_file_path);
+        assert!(!jobs[1].was_running);
+    }
+
+    #[test]
+    fn history_can_be_filtered_and_pruned() {
+        let connection = get_test_connection();
+
+        record_job_history(
+            &connection,
+            &JobRecord {
+                source_file_path: Path::new("/movies/new.mkv"),
+                workflow: "movies",
+                outcome: "completed",
+                duration: Some(Duration::from_secs(4)),
+                throughput_bytes_per_second: Some(250.0),
+                task_counts: Some(TaskCounts { run: 2, skipped: 3 }),
+                tasks: vec![String::from("remux"), String::from("transcode")],
+                builtins: vec![
+                    String::from("builtin.transcode_to_h265"),
+                    String::from("builtin.transcode_audio(codec=aac,bitrate=256k)"),
+                ],
+            },
+        )
+        .unwrap();
+        connection
+            .execute(
+                r#"
+                INSERT INTO job_history (source_file_path, workflow, outcome, recorded_at)
+                VALUES ('/movies/old.mkv', 'movies', 'completed', datetime('now', '-10 days'))
+                "#,
+                (),
+            )
+            .unwrap();
+
+        let since = |since| HistoryFilter {
+            since: Some(since),
+            ..HistoryFilter::default()
+        };
+
+        assert_eq!(
+            2,
+            list_history(&connection, &HistoryFilter::default())
+                .unwrap()
+                .len()
+        );
+
+        let recent = list_history(
+            &connection,
+            &since(PointInTime::Ago(Duration::from_secs(24 * 3600))),
+        )
+        .unwrap();
+        assert_eq!(1, recent.len());
+        assert_eq!(Path::new("/movies/new.mkv"), recent[0].source_file_path);
+        assert_eq!(Some(250.0), recent[0].throughput_bytes_per_second);
+        assert!(matches!(
+            recent[0].task_counts,
+            Some(TaskCounts { run: 2, skipped: 3 })
+        ));
+        assert_eq!(
+            Some(env!("CARGO_PKG_VERSION")),
+            recent[0].version.as_deref()
+        );
+        assert_eq!(2, recent[0].builtins.len());
+        assert_eq!(
+            2,
+            list_history(
+                &connection,
+                &since(PointInTime::Local(String::from("2000-01-01 00:00:00")))
+            )
+            .unwrap()
+            .len()
+        );
+        assert!(list_history(
+            &connection,
+            &since(PointInTime::Local(String::from("9999-01-01 00:00:00")))
+        )
+        .unwrap()
+        .is_empty());
+
+        assert_eq!(
+            1,
+            prune_reports(&connection, Duration::from_secs(7 * 24 * 3600)).unwrap()
+        );
+        assert_eq!(
+            1,
+            list_history(&connection, &HistoryFilter::default())
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[test]
+    fn history_can_be_filtered_on_failures_and_tasks() {
+        let connection = get_test_connection();
+
+        for (file, outcome, tasks) in [
+            ("/movies/a.mkv", "completed", vec!["remux", "transcode"]),
+            ("/movies/b.mkv", FAILED_OUTCOME, vec!["transcode"]),
+            ("/movies/c.mkv", FAILED_OUTCOME, vec!["remux"]),
+        ] {
+            record_job_history(
+                &connection,
+                &JobRecord {
+                    source_file_path: Path::new(file),
+                    workflow: "movies",
+                    outcome,
+                    duration: None,
+                    throughput_bytes_per_second: None,
+                    task_counts: None,
+                    tasks: tasks.into_iter().map(String::from).collect(),
+                    builtins: vec![],
+                },
+            )
+            .unwrap();
+        }
+
+        let files = |filter: HistoryFilter| -> Vec<PathBuf> {
+            list_history(&connection, &filter)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.source_file_path)
+                .rev()
+                .collect()
+        };
+
+        assert_eq!(
+            vec![
+                PathBuf::from("/movies/b.mkv"),
+                PathBuf::from("/movies/c.mkv")
+            ],
+            files(HistoryFilter {
+                failed: true,
+                ..HistoryFilter::default()
+            })
+        );
+        assert_eq!(
+            vec![
+                PathBuf::from("/movies/a.mkv"),
+                PathBuf::from("/movies/b.mkv")
+            ],
+            files(HistoryFilter {
+                task: Some(String::from("transcode")),
+                ..HistoryFilter::default()
+            })
+        );
+        assert_eq!(
+            vec![PathBuf::from("/movies/c.mkv")],
+            files(HistoryFilter {
+                since: Some(PointInTime::Ago(Duration::from_secs(3600))),
+                failed: true,
+                task: Some(String::from("remux")),
+            })
+        );
+        assert!(files(HistoryFilter {
+            task: Some(String::from("trans")),
+            ..HistoryFilter::default()
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn cached_probe_result_expires() {
+        let connection = get_test_connection();
+        let ttl = Duration::from_secs(3600);
+
+        assert_eq!(
+            None,
+            get_cached_probe_result(&connection, "h265", "abc", ttl).unwrap()
+        );
+
+        cache_probe_result(&connection, "h265", "abc", "skip").unwrap();
+        assert_eq!(
+            Some(String::from("skip")),
+            get_cached_probe_result(&connection, "h265", "abc", ttl).unwrap()
+        );
+        assert_eq!(
+            None,
+            get_cached_probe_result(&connection, "h265", "def", ttl).unwrap()
+        );
+
+        connection
+            .execute(
+                "UPDATE probe_cache SET cached_at = datetime('now', '-2 hours')",
+                (),
+            )
+            .unwrap();
+        assert_eq!(
+            None,
+            get_cached_probe_result(&connection, "h265", "abc", ttl).unwrap()
+        );
+    }
+
+    #[test]
+    fn state_of_reprocessed_workflow_is_cleared() {
+        let connection = get_test_connection();
+
+        cache_probe_result(&connection, "h265", "abc", "skip").unwrap();
+        cache_probe_result(&connection, "subtitles", "abc", "run").unwrap();
+        connection
+            .execute(
+                r#"
+                INSERT INTO job_report (source_file_path, output_file_fingerprint)
+                VALUES ('/movies/a.mkv', 'abc'), ('/movies-4k/b.mkv', 'def')
+                "#,
+                (),
+            )
+            .unwrap();
+
+        assert_eq!(1, clear_probe_cache(&connection, &["h265"]).unwrap());
+        assert_eq!(
+            1,
+            clear_output_fingerprints(&connection, Path::new("/movies")).unwrap()
+        );
+        assert_eq!(
+            1,
+            clear_output_fingerprints(&connection, Path::new("/movies-4k")).unwrap()
+        );
+    }
+
+    #[test]
+    fn zero_threshold_never_quarantines() {
+        let connection = get_test_connection();
+        let path = Path::new("/library/movie.mkv");
+
+        for _ in 0..5 {
+            assert!(!record_failure(&connection, path, 0).unwrap());
+        }
+    }
 }
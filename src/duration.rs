@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid duration \"{0}\", expected a number followed by s, m, h, d or w (e.g. \"90d\")")]
+pub(crate) struct InvalidDuration(String);
+
+/// Parse a human duration such as "30m", "24h" or "90d"
+pub(crate) fn parse_duration(value: &str) -> Result<Duration, InvalidDuration> {
+    let invalid = || InvalidDuration(value.to_owned());
+
+    let value = value.trim();
+    let unit_start = value
+        .find(|character: char| !character.is_ascii_digit())
+        .ok_or_else(invalid)?;
+
+    let (amount, unit) = value.split_at(unit_start);
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+
+    let unit_seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+
+    amount
+        .checked_mul(unit_seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(invalid)
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid point in time \"{0}\", expected a duration ago like \"7d\", a date like \"2024-01-01\" or a local date and time like \"2024-01-01T18:30\"")]
+pub(crate) struct InvalidPointInTime(String);
+
+/// A moment to filter on, either relative to now or as a local date and time
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PointInTime {
+    /// This long before now
+    Ago(Duration),
+    /// A local date and time, formatted as "YYYY-MM-DD HH:MM:SS"
+    Local(String),
+}
+
+/// Parse a human duration such as "7d", or an ISO 8601 date with an optional time in local time,
+/// such as "2024-01-01", "2024-01-01T18:30" or "2024-01-01 18:30:15"
+pub(crate) fn parse_point_in_time(value: &str) -> Result<PointInTime, InvalidPointInTime> {
+    if let Ok(duration) = parse_duration(value) {
+        return Ok(PointInTime::Ago(duration));
+    }
+
+    let invalid = || InvalidPointInTime(value.to_owned());
+
+    let value = value.trim();
+    let (date, time) = match value.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+
+    let date = parse_numbers(date, '-', &[(1, 9999), (1, 12), (1, 31)]).ok_or_else(invalid)?;
+    let time = match time {
+        Some(time) => parse_numbers(time, ':', &[(0, 23), (0, 59), (0, 59)])
+            .filter(|time| time.len() >= 2)
+            .ok_or_else(invalid)?,
+        None => vec![],
+    };
+    if date.len() != 3 {
+        return Err(invalid());
+    }
+
+    let number = |numbers: &[u32], index: usize| numbers.get(index).copied().unwrap_or(0);
+    Ok(PointInTime::Local(format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        date[0],
+        date[1],
+        date[2],
+        number(&time, 0),
+        number(&time, 1),
+        number(&time, 2)
+    )))
+}
+
+/// Parse separated numbers that must each be within their range, e.g. the parts of a date
+fn parse_numbers(value: &str, separator: char, ranges: &[(u32, u32)]) -> Option<Vec<u32>> {
+    let parts: Vec<&str> = value.split(separator).collect();
+    if parts.len() > ranges.len() {
+        return None;
+    }
+
+    parts
+        .into_iter()
+        .zip(ranges)
+        .map(|(part, (min, max))| {
+            part.bytes()
+                .all(|byte| byte.is_ascii_digit())
+                .then(|| part.parse().ok())
+                .flatten()
+                .filter(|number| (min..=max).contains(&number))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_are_parsed() {
+        assert_eq!(Duration::from_secs(90), parse_duration("90s").unwrap());
+        assert_eq!(
+            Duration::from_secs(24 * 3600),
+            parse_duration("24h").unwrap()
+        );
+        assert_eq!(
+            Duration::from_secs(90 * 24 * 3600),
+            parse_duration("90d").unwrap()
+        );
+
+        assert!(parse_duration("90").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("5 days").is_err());
+    }
+
+    #[test]
+    fn points_in_time_are_parsed() {
+        assert_eq!(
+            PointInTime::Ago(Duration::from_secs(7 * 24 * 3600)),
+            parse_point_in_time("7d").unwrap()
+        );
+        assert_eq!(
+            PointInTime::Local(String::from("2024-01-01 00:00:00")),
+            parse_point_in_time("2024-01-01").unwrap()
+        );
+        assert_eq!(
+            PointInTime::Local(String::from("2024-01-01 18:30:00")),
+            parse_point_in_time("2024-01-01T18:30").unwrap()
+        );
+        assert_eq!(
+            PointInTime::Local(String::from("2024-01-01 18:30:15")),
+            parse_point_in_time("2024-01-01 18:30:15").unwrap()
+        );
+
+        assert!(parse_point_in_time("2024-13-01").is_err());
+        assert!(parse_point_in_time("2024-01").is_err());
+        assert!(parse_point_in_time("2024-01-01T18").is_err());
+        assert!(parse_point_in_time("2024-01-01T25:00").is_err());
+        assert!(parse_point_in_time("yesterday").is_err());
+    }
+}
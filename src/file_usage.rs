@@ -0,0 +1,110 @@
+//! Detection of files that are in use by other processes, e.g. because they are still being
+//! downloaded or copied.
+
+use std::path::Path;
+
+/// Whether any process has the file opened for writing.
+/// When this cannot be determined, the file is assumed not to be in use.
+pub(crate) fn is_opened_for_writing(path: &Path) -> bool {
+    let Ok(path) = path.canonicalize() else {
+        return false;
+    };
+
+    platform::is_opened_for_writing(&path)
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::{fs, path::Path};
+
+    const O_ACCMODE: u32 = 0o3;
+    const O_WRONLY: u32 = 0o1;
+    const O_RDWR: u32 = 0o2;
+
+    /// Look through the file descriptors of all processes in `/proc`.
+    /// Processes of which the file descriptors cannot be read are ignored.
+    pub(super) fn is_opened_for_writing(path: &Path) -> bool {
+        let Ok(processes) = fs::read_dir("/proc") else {
+            return false;
+        };
+
+        processes.flatten().any(|process| {
+            let Ok(descriptors) = fs::read_dir(process.path().join("fd")) else {
+                return false;
+            };
+
+            descriptors.flatten().any(|descriptor| {
+                let opens_path = fs::read_link(descriptor.path())
+                    .map(|target| target == path)
+                    .unwrap_or(false);
+
+                opens_path
+                    && has_write_access(&process.path(), &descriptor.file_name().to_string_lossy())
+            })
+        })
+    }
+
+    /// Read the access mode of a file descriptor from its `fdinfo`
+    fn has_write_access(process_path: &Path, descriptor: &str) -> bool {
+        let Ok(fdinfo) = fs::read_to_string(process_path.join("fdinfo").join(descriptor)) else {
+            return false;
+        };
+
+        fdinfo
+            .lines()
+            .find_map(|line| line.strip_prefix("flags:"))
+            .and_then(|flags| u32::from_str_radix(flags.trim(), 8).ok())
+            .map(|flags| matches!(flags & O_ACCMODE, O_WRONLY | O_RDWR))
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::{path::Path, process::Command};
+
+    /// Ask `lsof` for the access mode of every process that has the file open
+    pub(super) fn is_opened_for_writing(path: &Path) -> bool {
+        let Ok(output) = Command::new("lsof").arg("-Fa").arg("--").arg(path).output() else {
+            return false;
+        };
+
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.strip_prefix('a'))
+            .any(|access| access.contains('w') || access.contains('u'))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+mod platform {
+    use std::path::Path;
+
+    pub(super) fn is_opened_for_writing(_path: &Path) -> bool {
+        false
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::fs::{self, File};
+
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn file_opened_for_writing_is_detected() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let path = temp_test_dir.path().join("download.mkv");
+
+        fs::write(&path, "a").unwrap();
+        assert!(!is_opened_for_writing(&path));
+
+        let file = File::options().append(true).open(&path).unwrap();
+        assert!(is_opened_for_writing(&path));
+
+        drop(file);
+        assert!(!is_opened_for_writing(&path));
+    }
+}
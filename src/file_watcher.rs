@@ -0,0 +1,302 @@
+//! Watching library directories for changes, so a library is scanned as soon as a file is added
+//! instead of at the next scan interval. Changes are reported by the OS on Linux. Elsewhere, and on
+//! network shares, the directory is polled for changes instead.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use notify::{
+    event::{AccessKind, AccessMode, CreateKind, ModifyKind, RenameMode},
+    Config, Event, EventKind, PollWatcher, RecursiveMode, Watcher,
+};
+use tracing::{debug, warn};
+
+/// Time to wait after a change before requesting a scan, so a burst of changes leads to one scan
+const SETTLE_DURATION: Duration = Duration::from_secs(2);
+
+/// Time between two polls of a directory of which changes are not reported
+const POLL_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum WatchError {
+    #[error("unable to watch \"{}\": {source}", .path.display())]
+    CannotWatch {
+        path: PathBuf,
+        source: notify::Error,
+    },
+}
+
+/// How changes to a directory are noticed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Strategy {
+    /// The OS reports the changes
+    Notify,
+    /// The directory is compared with how it was at the previous poll
+    Poll(Duration),
+}
+
+/// Watch the directory and everything below it in a separate thread.
+/// A scan is requested through `scan_requester` whenever a file is written or moved into it.
+pub(crate) fn watch(directory: &Path, scan_requester: Sender<()>) -> Result<(), WatchError> {
+    let strategy = if !cfg!(target_os = "linux") {
+        warn!(
+            "changes are only reported on Linux, polling {} for changes every {} minutes",
+            directory.display(),
+            POLL_INTERVAL.as_secs() / 60
+        );
+        Strategy::Poll(POLL_INTERVAL)
+    } else if is_network_filesystem(directory) {
+        warn!(
+            "the filesystem of {} does not report changes made by other machines, polling it for changes every {} minutes",
+            directory.display(),
+            POLL_INTERVAL.as_secs() / 60
+        );
+        Strategy::Poll(POLL_INTERVAL)
+    } else {
+        Strategy::Notify
+    };
+
+    watch_with(directory, strategy, scan_requester)
+}
+
+fn watch_with(
+    directory: &Path,
+    strategy: Strategy,
+    scan_requester: Sender<()>,
+) -> Result<(), WatchError> {
+    let cannot_watch = |source| WatchError::CannotWatch {
+        path: directory.to_path_buf(),
+        source,
+    };
+
+    let (event_sender, events) = channel();
+    let mut watcher: Box<dyn Watcher + Send> = match strategy {
+        Strategy::Notify => {
+            Box::new(notify::recommended_watcher(event_sender).map_err(cannot_watch)?)
+        }
+        Strategy::Poll(interval) => Box::new(
+            PollWatcher::new(event_sender, Config::default().with_poll_interval(interval))
+                .map_err(cannot_watch)?,
+        ),
+    };
+    watcher
+        .watch(directory, RecursiveMode::Recursive)
+        .map_err(cannot_watch)?;
+    debug!("watching {} using {strategy:?}", directory.display());
+
+    thread::Builder::new()
+        .name(format!("file-watcher({})", directory.display()))
+        .spawn(move || request_scans(watcher, events, scan_requester))
+        .map_err(|err| cannot_watch(notify::Error::io(err)))?;
+
+    Ok(())
+}
+
+/// Request a scan for every batch of changes, until the library monitor is gone
+fn request_scans(
+    // the watcher stops watching once it is dropped
+    _watcher: Box<dyn Watcher + Send>,
+    events: Receiver<notify::Result<Event>>,
+    scan_requester: Sender<()>,
+) {
+    for event in &events {
+        match event {
+            Ok(event) if requests_scan(&event) => {}
+            Ok(_) => continue,
+            Err(err) => {
+                warn!("error while watching for changes: {err}");
+                continue;
+            }
+        }
+
+        // changes that arrive while settling are part of the same scan
+        thread::sleep(SETTLE_DURATION);
+        events.try_iter().for_each(drop);
+
+        if scan_requester.send(()).is_err() {
+            return;
+        }
+    }
+}
+
+/// Whether the event means a file is ready to be scanned. A file that is only created is still
+/// being written, it is reported again once closed. Events that were lost, e.g. because too many
+/// changes happened at once, can only be made up for by a scan.
+fn requests_scan(event: &Event) -> bool {
+    event.need_rescan()
+        || matches!(
+            event.kind,
+            EventKind::Access(AccessKind::Close(AccessMode::Write))
+                | EventKind::Modify(ModifyKind::Name(
+                    RenameMode::To | RenameMode::Both | RenameMode::Any
+                ))
+                | EventKind::Create(CreateKind::Folder)
+                // polling only notices a file once it exists, not when it is created
+                | EventKind::Create(CreateKind::Any)
+        )
+}
+
+/// Whether the path is on a filesystem that does not report changes made by other machines
+fn is_network_filesystem(path: &Path) -> bool {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let Ok(mounts) = fs::read_to_string("/proc/self/mounts") else {
+        return false;
+    };
+
+    filesystem_type(&mounts, &path).is_some_and(|filesystem| {
+        matches!(
+            filesystem,
+            "nfs" | "nfs4" | "cifs" | "smb3" | "smbfs" | "9p" | "afs" | "ceph"
+        ) || filesystem.starts_with("fuse")
+    })
+}
+
+/// Type of the filesystem of the mount point closest to the path, from the contents of
+/// `/proc/self/mounts`
+fn filesystem_type<'a>(mounts: &'a str, path: &Path) -> Option<&'a str> {
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ');
+            let mount_point = unescape_mount_point(fields.nth(1)?);
+            let filesystem = fields.next()?;
+            path.starts_with(&mount_point)
+                .then_some((mount_point, filesystem))
+        })
+        // a later mount on the same mount point hides the earlier one
+        .max_by_key(|(mount_point, _)| mount_point.components().count())
+        .map(|(_, filesystem)| filesystem)
+}
+
+/// Mount points are written with spaces, tabs, newlines and backslashes as octal escapes
+fn unescape_mount_point(escaped: &str) -> PathBuf {
+    let mut unescaped = String::with_capacity(escaped.len());
+    let mut rest = escaped;
+
+    while let Some(position) = rest.find('\\') {
+        unescaped.push_str(&rest[..position]);
+        let code = rest
+            .get(position + 1..position + 4)
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match code {
+            Some(code) => {
+                unescaped.push(char::from(code));
+                rest = &rest[position + 4..];
+            }
+            None => {
+                unescaped.push('\\');
+                rest = &rest[position + 1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+
+    PathBuf::from(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{DataChange, Flag};
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn scan_is_requested_when_file_is_added_to_new_directory() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let (scan_requester, scan_requests) = channel();
+
+        watch_with(temp_test_dir.path(), Strategy::Notify, scan_requester).unwrap();
+
+        let directory = temp_test_dir.path().join("movie");
+        fs::create_dir(&directory).unwrap();
+        // the creation of the directory is reported first, so it is watched before this settles
+        assert!(scan_requests.recv_timeout(SETTLE_DURATION * 5).is_ok());
+
+        fs::write(directory.join("movie.mkv"), "movie").unwrap();
+        assert!(scan_requests.recv_timeout(SETTLE_DURATION * 5).is_ok());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn directory_moved_into_library_is_watched() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let library = temp_test_dir.path().join("library");
+        let downloads = temp_test_dir.path().join("downloads/show");
+        fs::create_dir(&library).unwrap();
+        fs::create_dir_all(&downloads).unwrap();
+        fs::write(downloads.join("episode-1.mkv"), "episode").unwrap();
+        let (scan_requester, scan_requests) = channel();
+
+        watch_with(&library, Strategy::Notify, scan_requester).unwrap();
+
+        fs::rename(&downloads, library.join("show")).unwrap();
+        assert!(scan_requests.recv_timeout(SETTLE_DURATION * 5).is_ok());
+
+        fs::write(library.join("show/episode-2.mkv"), "episode").unwrap();
+        assert!(scan_requests.recv_timeout(SETTLE_DURATION * 5).is_ok());
+    }
+
+    #[test]
+    fn polling_requests_scan_for_added_file() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let (scan_requester, scan_requests) = channel();
+
+        watch_with(
+            temp_test_dir.path(),
+            Strategy::Poll(Duration::from_millis(100)),
+            scan_requester,
+        )
+        .unwrap();
+
+        fs::write(temp_test_dir.path().join("movie.mkv"), "movie").unwrap();
+        assert!(scan_requests.recv_timeout(SETTLE_DURATION * 5).is_ok());
+    }
+
+    #[test]
+    fn only_complete_files_and_lost_events_request_scan() {
+        // the kernel dropped events because its queue overflowed
+        let overflow = Event::new(EventKind::Other).set_flag(Flag::Rescan);
+        assert!(requests_scan(&overflow));
+
+        assert!(requests_scan(&Event::new(EventKind::Access(
+            AccessKind::Close(AccessMode::Write)
+        ))));
+        assert!(requests_scan(&Event::new(EventKind::Modify(
+            ModifyKind::Name(RenameMode::To)
+        ))));
+
+        assert!(!requests_scan(&Event::new(EventKind::Create(
+            CreateKind::File
+        ))));
+        assert!(!requests_scan(&Event::new(EventKind::Modify(
+            ModifyKind::Data(DataChange::Any)
+        ))));
+        assert!(!requests_scan(&Event::new(EventKind::Other)));
+    }
+
+    #[test]
+    fn filesystem_type_is_that_of_closest_mount_point() {
+        let mounts = "\
+/dev/sda1 / ext4 rw,relatime 0 0
+nas:/media /mnt/media nfs4 rw,relatime 0 0
+/dev/sdb1 /mnt/media/local ext4 rw,relatime 0 0
+//nas/tv\\040shows /mnt/tv\\040shows cifs rw 0 0
+";
+
+        let filesystem_of = |path: &str| filesystem_type(mounts, Path::new(path));
+
+        assert_eq!(Some("ext4"), filesystem_of("/home/movies"));
+        assert_eq!(Some("nfs4"), filesystem_of("/mnt/media/movies"));
+        assert_eq!(Some("ext4"), filesystem_of("/mnt/media/local/movies"));
+        // not a mount point of its own, only sharing a prefix
+        assert_eq!(Some("ext4"), filesystem_of("/mnt/media2"));
+        assert_eq!(Some("cifs"), filesystem_of("/mnt/tv shows/series"));
+    }
+}
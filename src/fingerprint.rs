@@ -0,0 +1,79 @@
+//! Cheap fingerprints of (large) files, to recognize a file that has not changed
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// Amount of bytes read from both the start and the end of a file
+const SAMPLE_SIZE: u64 = 1024 * 1024;
+
+/// Fingerprint a file by its size and the content at its start and end.
+/// This avoids reading complete media files, which can be many gigabytes.
+pub(crate) fn fingerprint_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+
+    let mut hash = Fnv1a::new();
+    hash.write(&size.to_le_bytes());
+
+    let mut buffer = Vec::new();
+    file.by_ref().take(SAMPLE_SIZE).read_to_end(&mut buffer)?;
+
+    if size > SAMPLE_SIZE {
+        file.seek(SeekFrom::Start(
+            size.saturating_sub(SAMPLE_SIZE).max(SAMPLE_SIZE),
+        ))?;
+        file.read_to_end(&mut buffer)?;
+    }
+
+    hash.write(&buffer);
+
+    Ok(format!("{size:x}-{:016x}", hash.finish()))
+}
+
+/// 64-bit FNV-1a, used because its output is stable across builds and platforms
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    fn new() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn fingerprint_changes_with_content() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let path = temp_test_dir.path().join("movie.mkv");
+
+        fs::write(&path, "a").unwrap();
+        let first = fingerprint_file(&path).unwrap();
+        assert_eq!(first, fingerprint_file(&path).unwrap());
+
+        fs::write(&path, "b").unwrap();
+        assert_ne!(first, fingerprint_file(&path).unwrap());
+    }
+}
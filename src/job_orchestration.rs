@@ -2,23 +2,36 @@
 //! need to be queued and actually queueing and starting them.
 //!
 
-use crate::workflow_runner::{Runner, RunnerError};
+use crate::workflow_runner::{ProbeResult, RunnerError, WorkflowRunner};
 use std::{
-    collections::VecDeque,
+    cmp,
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    fs, io,
     ops::Deref,
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::Output,
-    sync::mpsc::{channel, Receiver, Sender},
-    thread::{self, sleep, JoinHandle},
-    time::Duration,
+    string::FromUtf8Error,
+    sync::{
+        mpsc::{channel, Receiver, SendError, Sender},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
 use rusqlite::Connection;
-use tracing::{debug, warn};
+use tracing::{debug, error, info, warn};
 
-use crate::{db, Workflow};
+use crate::{
+    db::{self, DbError},
+    file_usage,
+    metrics::Metrics,
+    workflow::Library,
+    Workflow,
+};
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Debug)]
 pub(crate) struct JobRequest {
     /// The absolute path to the file for this job
     file_path: PathBuf,
@@ -28,6 +41,9 @@ pub(crate) struct JobRequest {
 
     /// The workflow that is requested for this job
     workflow: Workflow,
+
+    /// Jobs with a higher priority are started before those that were queued earlier
+    priority: u8,
 }
 
 impl JobRequest {
@@ -37,40 +53,182 @@ impl JobRequest {
             library,
             file_path,
             workflow,
+            priority: 0,
         }
     }
+
+    /// Give the job the priority of its library
+    pub(crate) fn with_priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+/// Wakes the orchestrator as soon as there is something to do, instead of at its next tick
+#[derive(Debug, Default)]
+struct Wakeup {
+    pending: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl Wakeup {
+    fn notify(&self) {
+        *self.pending.lock().unwrap_or_else(|err| err.into_inner()) = true;
+        self.condvar.notify_one();
+    }
+
+    /// Wait until notified, or until the timeout passed. A notification that arrived while the
+    /// orchestrator was busy returns immediately, so it is never missed.
+    fn wait(&self, timeout: Duration) {
+        let pending = self.pending.lock().unwrap_or_else(|err| err.into_inner());
+        let (mut pending, _) = self
+            .condvar
+            .wait_timeout_while(pending, timeout, |pending| !*pending)
+            .unwrap_or_else(|err| err.into_inner());
+        *pending = false;
+    }
+}
+
+/// Sends job requests to a [`JobOrchestrator`] and wakes it up to handle them
+#[derive(Debug, Clone)]
+pub(crate) struct JobSender {
+    sender: Sender<Box<JobRequest>>,
+    wakeup: Arc<Wakeup>,
+}
+
+impl JobSender {
+    pub(crate) fn send(&self, job: Box<JobRequest>) -> Result<(), SendError<Box<JobRequest>>> {
+        self.sender.send(job)?;
+        self.wakeup.notify();
+
+        Ok(())
+    }
 }
 
 /// A Runnable Job is created once a [`JobRequest`] is determined to be valid and needed
-#[derive(PartialEq, Eq, Debug)]
-struct RunnableJob(JobRequest);
+#[derive(Debug)]
+struct RunnableJob {
+    request: JobRequest,
+    /// The job cannot be started before this moment, e.g. because its file was still in use
+    deferred_until: Option<Instant>,
+    /// Size of the source file when the job was queued, 0 when it could not be read
+    source_file_size_bytes: u64,
+    /// Copied from the request, jobs with a higher priority are started first
+    priority: u8,
+    /// Jobs of the same priority are started in the order they were queued
+    enqueued_at: Instant,
+}
+
+impl RunnableJob {
+    fn new(request: JobRequest) -> Self {
+        let source_file_size_bytes = fs::metadata(&request.file_path)
+            .map(|metadata| metadata.len())
+            .unwrap_or_else(|err| {
+                warn!(
+                    "unable to read the size of {}: {err}",
+                    request.file_path.to_string_lossy()
+                );
+                0
+            });
+
+        Self {
+            priority: request.priority,
+            request,
+            deferred_until: None,
+            source_file_size_bytes,
+            enqueued_at: Instant::now(),
+        }
+    }
+
+    /// Whether the job can be started at the given moment
+    fn is_startable_at(&self, moment: Instant) -> bool {
+        self.deferred_until.is_none_or(|until| until <= moment)
+    }
+}
+
+/// Two runnable jobs are the same when they are for the same file, queued at the same moment with
+/// the same priority. Use the requests to find out whether two jobs are requested for the same thing.
+impl PartialEq for RunnableJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == cmp::Ordering::Equal
+    }
+}
+
+impl Eq for RunnableJob {}
+
+impl PartialOrd for RunnableJob {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The greater job is started first: the one with the higher priority, or of the same priority the
+/// one that was queued earlier. The file and library only keep the order consistent with [`Eq`].
+impl Ord for RunnableJob {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.enqueued_at.cmp(&self.enqueued_at))
+            .then_with(|| self.request.file_path.cmp(&other.request.file_path))
+            .then_with(|| self.request.library.cmp(&other.request.library))
+    }
+}
 
 #[derive(Debug)]
-#[allow(dead_code)]
 struct RunningJob(JobRequest);
 
+/// Outcome of trying to start the next job in the queue
+#[derive(Debug, PartialEq, Eq)]
+enum JobStart {
+    Started,
+    /// The file of the job was in use, another job may be startable
+    Deferred,
+    NothingStartable,
+}
+
 impl Deref for RunnableJob {
     type Target = JobRequest;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.request
     }
 }
 
 /// A job outputs a report that contains information about the tasks that were executed
 /// and the logs of those processes, per task.
 #[derive(Debug)]
-#[allow(dead_code)]
 pub(crate) struct WorkflowReport {
     workflow: Workflow,
     task_reports: Vec<TaskReport>,
+    /// Tasks that did not run because of their probe, with the result of that probe
+    skipped_tasks: Vec<(String, ProbeResult)>,
+    reason: SkipReason,
+    /// How long it took to process the file, only known for completed runs
+    duration: Option<Duration>,
+    throughput_bytes_per_second: Option<f64>,
+    source_file_size_bytes: Option<u64>,
+    /// Size of the file that replaced the source file, only known when it was replaced
+    output_file_size_bytes: Option<u64>,
+    /// How long the tasks were expected to take before they ran, when it could be estimated
+    estimated_duration_ms: Option<u64>,
+    /// None of the tasks changed the file, so the source file was left as it was
+    source_file_unchanged: bool,
 }
 
 impl WorkflowReport {
-    pub(crate) fn new(workflow: Workflow) -> Self {
+    /// Create a report for a workflow that did not run any tasks
+    pub(crate) fn new(workflow: Workflow, reason: SkipReason) -> Self {
         Self {
             workflow,
             task_reports: vec![],
+            skipped_tasks: vec![],
+            reason,
+            duration: None,
+            throughput_bytes_per_second: None,
+            source_file_size_bytes: None,
+            output_file_size_bytes: None,
+            estimated_duration_ms: None,
+            source_file_unchanged: false,
         }
     }
 
@@ -78,17 +236,200 @@ impl WorkflowReport {
         Self {
             workflow,
             task_reports,
+            skipped_tasks: vec![],
+            reason: SkipReason::Completed,
+            duration: None,
+            throughput_bytes_per_second: None,
+            source_file_size_bytes: None,
+            output_file_size_bytes: None,
+            estimated_duration_ms: None,
+            source_file_unchanged: false,
         }
     }
+
+    /// Record the tasks that were skipped by their probe
+    pub(crate) fn with_skipped_tasks(mut self, skipped_tasks: Vec<(String, ProbeResult)>) -> Self {
+        self.skipped_tasks = skipped_tasks;
+        self
+    }
+
+    /// Record how long it took to process a source file of the given size
+    pub(crate) fn with_timing(mut self, source_file_size_bytes: u64, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self.source_file_size_bytes = Some(source_file_size_bytes);
+        self.throughput_bytes_per_second = Some(duration.as_secs_f64())
+            .filter(|seconds| *seconds > 0.0)
+            .map(|seconds| source_file_size_bytes as f64 / seconds);
+        self
+    }
+
+    /// Record how long the tasks were expected to take before they ran
+    pub(crate) fn with_estimated_duration(mut self, estimated_duration_ms: Option<u64>) -> Self {
+        self.estimated_duration_ms = estimated_duration_ms;
+        self
+    }
+
+    pub(crate) fn estimated_duration_ms(&self) -> Option<u64> {
+        self.estimated_duration_ms
+    }
+
+    /// Record the size of the file that replaced the source file
+    pub(crate) fn with_output_size(mut self, output_file_size_bytes: u64) -> Self {
+        self.output_file_size_bytes = Some(output_file_size_bytes);
+        self
+    }
+
+    /// Record that the tasks did not change the file, so the source file was not replaced
+    pub(crate) fn with_unchanged_source_file(mut self) -> Self {
+        self.source_file_unchanged = true;
+        self
+    }
+
+    /// Size of the source file minus the size of the file that replaced it, negative when it grew
+    pub(crate) fn bytes_saved(&self) -> Option<i64> {
+        Some(self.source_file_size_bytes? as i64 - self.output_file_size_bytes? as i64)
+    }
+
+    pub(crate) fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    /// Size of the source file divided by the time it took to process it
+    pub(crate) fn throughput_bytes_per_second(&self) -> Option<f64> {
+        self.throughput_bytes_per_second
+    }
+
+    pub(crate) fn reason(&self) -> SkipReason {
+        self.reason
+    }
+
+    /// Name of the workflow that produced this report
+    pub(crate) fn workflow_name(&self) -> &str {
+        &self.workflow.name
+    }
+
+    /// Amount of tasks that have run
+    pub(crate) fn task_count(&self) -> usize {
+        self.task_reports.len()
+    }
+
+    /// Amount of tasks that were skipped by their probe
+    pub(crate) fn skipped_task_count(&self) -> usize {
+        self.skipped_tasks.len()
+    }
+
+    /// Reports of the tasks that have run, in the order they ran
+    pub(crate) fn task_reports(&self) -> &[TaskReport] {
+        &self.task_reports
+    }
+
+    /// Whether every task that has run succeeded
+    pub(crate) fn was_successful(&self) -> bool {
+        self.task_reports.iter().all(TaskReport::succeeded)
+    }
 }
 
-/// Contains information about the execution of a single task. Its full output to stderr and stdout is collected.
+impl Display for WorkflowReport {
+    /// A summary of the run, followed by a line per task that has run or was skipped
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.reason == SkipReason::Completed {
+            write!(
+                f,
+                "Workflow '{}' completed: {} tasks run, {} skipped",
+                self.workflow_name(),
+                self.task_count(),
+                self.skipped_task_count()
+            )?;
+        } else {
+            write!(
+                f,
+                "Workflow '{}' skipped: {}",
+                self.workflow_name(),
+                self.reason
+            )?;
+        }
+        if self.source_file_unchanged {
+            write!(f, ", file unchanged")?;
+        }
+        if let Some(duration) = self.duration {
+            write!(f, ", total {:.1} s", duration.as_secs_f64())?;
+        }
+
+        for task_report in &self.task_reports {
+            write!(f, "\n  {task_report}")?;
+        }
+        for (task, probe_result) in &self.skipped_tasks {
+            let reason = match probe_result {
+                ProbeResult::Abort => "probe aborted",
+                _ => "probe skipped",
+            };
+            write!(f, "\n  [-] {task} ({reason})")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Describes why a workflow did or did not process a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SkipReason {
+    /// Every probe requested its task to be skipped
+    AllProbesSkipped,
+    /// The workflow has no tasks at all
+    NoTasksConfigured,
+    /// The content of the file does not match the container its extension claims
+    MislabeledContainer,
+    /// The file was not skipped, the tasks have completed
+    Completed,
+}
+
+impl SkipReason {
+    /// Stable identifier of the reason, used when persisting it
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            SkipReason::AllProbesSkipped => "all_probes_skipped",
+            SkipReason::NoTasksConfigured => "no_tasks_configured",
+            SkipReason::MislabeledContainer => "mislabeled_container",
+            SkipReason::Completed => "completed",
+        }
+    }
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::AllProbesSkipped => write!(f, "all probes requested to skip their task"),
+            SkipReason::NoTasksConfigured => write!(f, "the workflow has no tasks configured"),
+            SkipReason::MislabeledContainer => {
+                write!(
+                    f,
+                    "the content does not match the container of its extension"
+                )
+            }
+            SkipReason::Completed => write!(f, "all tasks completed"),
+        }
+    }
+}
+
+/// Amount of bytes of a task's stdout and stderr that is kept once its full output is in a log file
+const TASK_OUTPUT_TAIL_BYTES: usize = 4096;
+
+/// Contains information about the execution of a single task. Its output to stderr and stdout is collected as
+/// captured, unless it was written to a log file, in which case only the tail of the output is kept.
 #[derive(Debug)]
-#[allow(dead_code)]
 pub(crate) struct TaskReport {
     exit_code: Option<i32>,
     stdout: String,
     stderr: String,
+    log_file: Option<PathBuf>,
+    /// Description of the task, known once the runner has attributed the report to it
+    task: Option<String>,
+    /// How long the task ran
+    duration: Option<Duration>,
+    /// Bytes of output that were not captured, because the task wrote too much
+    omitted_output_bytes: usize,
+    /// The task output a file that did not differ from its input, so it was discarded
+    unchanged_output: bool,
 }
 
 impl TaskReport {
@@ -97,40 +438,358 @@ impl TaskReport {
             exit_code,
             stdout,
             stderr,
+            log_file: None,
+            task: None,
+            duration: None,
+            omitted_output_bytes: 0,
+            unchanged_output: false,
+        }
+    }
+
+    /// Note that part of the output was not captured, it is left out of stdout and stderr
+    pub(crate) fn with_omitted_output(mut self, omitted_bytes: usize) -> Self {
+        self.omitted_output_bytes = omitted_bytes;
+        self
+    }
+
+    /// Note that the file the task output did not differ from its input
+    pub(crate) fn with_unchanged_output(mut self) -> Self {
+        self.unchanged_output = true;
+        self
+    }
+
+    /// Description of the task that produced the report
+    pub(crate) fn task(&self) -> Option<&str> {
+        self.task.as_deref()
+    }
+
+    /// Attribute the report to the task that produced it, which ran for the given duration
+    pub(crate) fn for_task(mut self, task: &str, duration: Duration) -> Self {
+        self.task = Some(task.to_owned());
+        self.duration = Some(duration);
+        self
+    }
+
+    /// How long the task ran, known once the runner has attributed the report to it
+    pub(crate) fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    /// Report of a task that succeeded without producing any output
+    #[cfg_attr(not(feature = "builtin-tasks"), allow(dead_code))]
+    pub(crate) fn success() -> Self {
+        Self::new(Some(0), String::new(), String::new())
+    }
+
+    /// Report of a task that failed, the message is reported as its stderr
+    pub(crate) fn failure(message: String) -> Self {
+        Self::new(Some(1), String::new(), message)
+    }
+
+    /// The exit code of the task, absent when it was terminated by a signal
+    pub(crate) fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    pub(crate) fn stdout(&self) -> &str {
+        &self.stdout
+    }
+
+    pub(crate) fn stderr(&self) -> &str {
+        &self.stderr
+    }
+
+    /// Whether the task exited with code 0. A task without an exit code was killed by a signal.
+    pub(crate) fn succeeded(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    /// The file containing the full output of the task, if it was written to one
+    pub(crate) fn log_file(&self) -> Option<&Path> {
+        self.log_file.as_deref()
+    }
+
+    /// Write the full output of the task to the given file.
+    /// Afterwards the report only holds the tail of the output and a reference to the file.
+    pub(crate) fn write_to_log_file(&mut self, path: &Path) -> io::Result<()> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
         }
+
+        let exit_code = self
+            .exit_code
+            .map_or_else(|| String::from("none"), |code| code.to_string());
+
+        fs::write(
+            path,
+            format!(
+                "exit code: {exit_code}\n\n--- stdout ---\n{}\n--- stderr ---\n{}",
+                self.stdout, self.stderr
+            ),
+        )?;
+
+        truncate_to_tail(&mut self.stdout, TASK_OUTPUT_TAIL_BYTES);
+        truncate_to_tail(&mut self.stderr, TASK_OUTPUT_TAIL_BYTES);
+        self.log_file = Some(path.to_path_buf());
+
+        Ok(())
     }
 }
 
-impl From<Output> for TaskReport {
-    fn from(value: Output) -> Self {
-        Self {
-            exit_code: value.status.code(),
-            stdout: String::from_utf8(value.stdout).expect("cannot get out of task"),
-            stderr: String::from_utf8(value.stderr).expect("cannot get out of task"),
+impl Display for TaskReport {
+    /// A single line, e.g. "[✓] my-task (2.1 s)" or "[✗] my-task (exit code 1)"
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let task = self.task.as_deref().unwrap_or("unknown task");
+
+        match (self.succeeded(), self.exit_code, self.duration) {
+            (false, Some(exit_code), _) => write!(f, "[✗] {task} (exit code {exit_code})")?,
+            (false, None, _) => write!(f, "[✗] {task} (killed by a signal)")?,
+            (_, _, Some(duration)) => write!(f, "[✓] {task} ({:.1} s)", duration.as_secs_f64())?,
+            _ => write!(f, "[✓] {task}")?,
+        }
+
+        if self.unchanged_output {
+            write!(f, " [no change]")?;
+        }
+        if self.omitted_output_bytes > 0 {
+            write!(
+                f,
+                " [{} bytes of output omitted]",
+                self.omitted_output_bytes
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Only keep the last `max_bytes` of the output, without splitting a character
+fn truncate_to_tail(output: &mut String, max_bytes: usize) {
+    if output.len() <= max_bytes {
+        return;
+    }
+
+    let mut start = output.len() - max_bytes;
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+
+    output.drain(..start);
+}
+
+impl TryFrom<Output> for TaskReport {
+    type Error = FromUtf8Error;
+
+    fn try_from(value: Output) -> Result<Self, Self::Error> {
+        Ok(Self::new(
+            value.status.code(),
+            String::from_utf8(value.stdout)?,
+            String::from_utf8(value.stderr)?,
+        ))
+    }
+}
+
+/// Counts of what happened to the files dispatched to the orchestrator
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RunSummary {
+    /// Files that were dispatched by the library monitors
+    pub(crate) dispatched: usize,
+    /// Files of which all tasks completed
+    pub(crate) processed: usize,
+    /// Files for which no task needed to run
+    pub(crate) skipped: usize,
+    /// Files for which the workflow failed
+    pub(crate) failed: usize,
+    /// Tasks that have run over all files
+    pub(crate) tasks_run: usize,
+}
+
+impl RunSummary {
+    /// A single `key=value` line, for scripts consuming the result of a run
+    pub(crate) fn machine_line(&self) -> String {
+        format!(
+            "dispatched={} processed={} skipped={} failed={} tasks_run={}",
+            self.dispatched, self.processed, self.skipped, self.failed, self.tasks_run
+        )
+    }
+
+    fn record_report(&mut self, report: &WorkflowReport) {
+        match report.reason() {
+            SkipReason::Completed => self.processed += 1,
+            SkipReason::AllProbesSkipped
+            | SkipReason::NoTasksConfigured
+            | SkipReason::MislabeledContainer => self.skipped += 1,
         }
+        self.tasks_run += report.task_count();
+    }
+}
+
+impl Display for RunSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "processed {}, skipped {}, failed {} ({} tasks run for {} dispatched files)",
+            self.processed, self.skipped, self.failed, self.tasks_run, self.dispatched
+        )
     }
 }
 
 pub(crate) struct JobOrchestrator {
     job_receiver: Receiver<Box<JobRequest>>,
-    connection: Connection,
+    /// Connection to the state database. When absent the orchestrator works purely in memory,
+    /// meaning fingerprint deduplication and report persistence are disabled.
+    connection: Option<Connection>,
+    /// Amount of failures after which a file is quarantined, 0 disables the quarantine
+    quarantine_threshold: u32,
+    /// How long a job is deferred when its file is still being written to
+    defer_duration: Duration,
+    /// How long a file that was just processed is not queued again, 0 disables the cooldown
+    completion_cooldown: Duration,
+    /// Files of which the workflow completed, with the moment it did
+    recently_completed: HashMap<PathBuf, Instant>,
     queue: VecDeque<RunnableJob>,
-    current_running_job: Option<(RunningJob, JoinHandle<Result<WorkflowReport, RunnerError>>)>,
+    running_jobs: Vec<(RunningJob, JoinHandle<Result<WorkflowReport, RunnerError>>)>,
+    /// Amount of jobs that run at the same time, over all libraries
+    max_concurrent_jobs: usize,
+    /// Amount of jobs of a library that run at the same time, so one library cannot occupy every slot
+    max_concurrent_jobs_per_library: HashMap<String, usize>,
+    /// Runs the workflow of each job, shared with the worker thread of the running job
+    runner: Arc<dyn WorkflowRunner>,
+    summary: RunSummary,
+    /// Shared with the metrics server, when it is enabled
+    metrics: Arc<Metrics>,
+    /// Notified when a job is sent or a running job finishes
+    wakeup: Arc<Wakeup>,
 }
 
+/// Longest time the orchestrator waits for a notification, e.g. before starting a deferred job
+const TICK_DURATION: Duration = Duration::from_secs(5);
+
 impl JobOrchestrator {
-    /// Create a new orchestrator and a sender to be used to communicate with it
-    pub(crate) fn new() -> (Self, Sender<Box<JobRequest>>) {
+    /// Create a new orchestrator and a sender to be used to communicate with it.
+    /// When `use_db` is false, no state database is created or opened.
+    pub(crate) fn new(
+        runner: Arc<dyn WorkflowRunner>,
+        use_db: bool,
+        quarantine_threshold: u32,
+        defer_duration: Duration,
+        max_concurrent_jobs: usize,
+    ) -> Result<(Self, JobSender), DbError> {
         let (sender, receiver) = channel::<Box<JobRequest>>();
-        (
+        let wakeup = Arc::new(Wakeup::default());
+
+        let connection = if use_db {
+            Some(db::get_connection()?)
+        } else {
+            debug!("running without state database, state will not survive a restart");
+            None
+        };
+
+        Ok((
             Self {
                 job_receiver: receiver,
-                connection: db::get_connection(),
+                connection,
+                quarantine_threshold,
+                defer_duration,
+                completion_cooldown: Duration::ZERO,
+                recently_completed: HashMap::new(),
                 queue: VecDeque::new(),
-                current_running_job: None,
+                running_jobs: Vec::new(),
+                max_concurrent_jobs,
+                max_concurrent_jobs_per_library: HashMap::new(),
+                runner,
+                summary: RunSummary::default(),
+                metrics: Arc::new(Metrics::default()),
+                wakeup: Arc::clone(&wakeup),
             },
-            sender,
-        )
+            JobSender { sender, wakeup },
+        ))
+    }
+
+    /// The metrics of the orchestrator, which are kept up to date while it runs
+    pub(crate) fn metrics(&self) -> Arc<Metrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    /// Limit the amount of jobs that run at the same time for the libraries that configure it
+    pub(crate) fn limit_concurrent_jobs_per_library(&mut self, libraries: &[Library]) {
+        self.max_concurrent_jobs_per_library = libraries
+            .iter()
+            .filter_map(|library| {
+                library
+                    .max_concurrent_jobs
+                    .map(|max_concurrent_jobs| (library.name.clone(), max_concurrent_jobs))
+            })
+            .collect();
+    }
+
+    /// Ignore files for a while after their workflow completed. Replacing the source file changes
+    /// it, so the next scan could otherwise dispatch it again straight away.
+    pub(crate) fn set_completion_cooldown(&mut self, completion_cooldown: Duration) {
+        self.completion_cooldown = completion_cooldown;
+    }
+
+    /// Restore the jobs that were queued before omzet was stopped.
+    /// A job that was running at that time was interrupted before its source file was replaced,
+    /// so it is queued again in front of the others.
+    pub(crate) fn restore_queue(&mut self, libraries: &[Library]) {
+        let Some(connection) = &self.connection else {
+            return;
+        };
+
+        let mut persisted_jobs = match db::load_queued_jobs(connection) {
+            Ok(persisted_jobs) => persisted_jobs,
+            Err(err) => {
+                error!("unable to restore queued jobs: {err}");
+                return;
+            }
+        };
+
+        // jobs of the same priority start in the order they are queued, interrupted ones first
+        persisted_jobs.sort_by_key(|persisted_job| !persisted_job.was_running);
+
+        for persisted_job in persisted_jobs {
+            let file_path = persisted_job.source_file_path;
+
+            let Some(library) = libraries
+                .iter()
+                .find(|library| library.name == persisted_job.library)
+            else {
+                warn!(
+                    "dropping queued job for {} because library \"{}\" is no longer configured",
+                    file_path.to_string_lossy(),
+                    persisted_job.library
+                );
+                if let Err(err) = db::remove_queued_job(connection, &file_path) {
+                    error!("unable to remove queued job: {err}");
+                }
+                continue;
+            };
+
+            let job = RunnableJob::new(
+                JobRequest::new(library.name.clone(), file_path, library.workflow.clone())
+                    .with_priority(library.priority),
+            );
+
+            if persisted_job.was_running {
+                warn!(
+                    "job for {} was interrupted, queueing it again",
+                    job.file_path.to_string_lossy()
+                );
+            }
+            self.queue.push_back(job);
+        }
+
+        info!("restored {} queued jobs", self.queue.len());
+    }
+
+    /// Remove jobs from the history that are older than the given duration
+    pub(crate) fn prune_history(&self, older_than: Duration) {
+        if let Some(pruned) = self.with_connection("prune job history", |connection| {
+            db::prune_reports(connection, older_than)
+        }) {
+            info!("pruned {pruned} jobs from the history");
+        }
     }
 
     pub(crate) fn start(&mut self) {
@@ -139,7 +798,22 @@ impl JobOrchestrator {
             self.handle_incoming_job_requests();
             self.handle_runner();
 
-            sleep(Duration::from_secs(5));
+            self.wakeup.wait(TICK_DURATION);
+        }
+    }
+
+    /// Process jobs until the queue is empty and nothing is running anymore.
+    /// Only job requests sent before or during the run are considered.
+    pub(crate) fn run_until_idle(mut self) -> RunSummary {
+        loop {
+            self.handle_incoming_job_requests();
+            self.handle_runner();
+
+            if self.queue.is_empty() && self.running_jobs.is_empty() {
+                return self.summary;
+            }
+
+            self.wakeup.wait(Duration::from_secs(1));
         }
     }
 
@@ -147,79 +821,907 @@ impl JobOrchestrator {
     fn handle_incoming_job_requests(&mut self) {
         // handle items that have been dispatched, queue them up
 
+        let queue_length = self.queue.len();
+        let now = Instant::now();
+        let completion_cooldown = self.completion_cooldown;
+        self.recently_completed
+            .retain(|_, completed_at| now.duration_since(*completed_at) < completion_cooldown);
+
         for incoming_job in self.job_receiver.try_iter() {
-            let queueable = RunnableJob(*incoming_job);
+            self.summary.dispatched += 1;
 
-            if self.queue.contains(&queueable) {
+            if self.queue.iter().any(|job| job.request == *incoming_job) {
+                continue;
+            }
+
+            // its job would replace the file again with what the running job produces
+            if self
+                .running_jobs
+                .iter()
+                .any(|(RunningJob(running), _)| running.file_path == incoming_job.file_path)
+            {
+                debug!(
+                    "file {} is being processed, not enqueueing",
+                    incoming_job.file_path.to_string_lossy()
+                );
+                continue;
+            }
+
+            if self
+                .recently_completed
+                .contains_key(&incoming_job.file_path)
+            {
+                debug!(
+                    "file {} was processed moments ago, not enqueueing",
+                    incoming_job.file_path.to_string_lossy()
+                );
+                continue;
+            }
+
+            if self.is_quarantined(&incoming_job.file_path) {
+                debug!(
+                    "file {} is quarantined, not enqueueing",
+                    incoming_job.file_path.to_string_lossy()
+                );
                 continue;
             }
 
             // @todo check file fingerprint to see if it was already done by us
 
+            // the size is read once, as the file is not expected to change while it is queued
+            let queueable = RunnableJob::new(*incoming_job);
+
             debug!("enqueueing new item {queueable:?}");
+            self.with_connection("persist queued job", |connection| {
+                db::persist_queued_job(connection, &queueable.library, &queueable.file_path)
+            });
             self.queue.push_back(queueable);
         }
-    }
 
-    /// Handle the runner.
-    /// Starts a new job if nothing is running and jobs are queued.
-    /// If something is running, check the status and finish it when it has completed
-    fn handle_runner(&mut self) {
-        // nothing is running
-        if self.current_running_job.is_none() {
-            self.start_job();
-            return;
+        if self.queue.len() > queue_length {
+            info!(
+                "{} jobs queued, {:.1} GB in total",
+                self.queue.len(),
+                self.total_queued_bytes() as f64 / 1e9
+            );
         }
+    }
 
-        // something is running but not finished yet
-        if let Some((_, handle)) = &self.current_running_job {
-            if !handle.is_finished() {
-                return;
+    /// Total size of the source files of the queued jobs, as it was when they were queued
+    pub(crate) fn total_queued_bytes(&self) -> u64 {
+        self.queue
+            .iter()
+            .map(|job| job.source_file_size_bytes)
+            .sum()
+    }
+
+    /// Handle the runners.
+    /// Finishes the jobs that have completed, then starts queued jobs while there is room for them
+    fn handle_runner(&mut self) {
+        let mut index = 0;
+        while index < self.running_jobs.len() {
+            if self.running_jobs[index].1.is_finished() {
+                let (running_job, handle) = self.running_jobs.remove(index);
+                self.finish_job(running_job, handle);
+            } else {
+                index += 1;
             }
         }
 
-        // something has finished
-        // we know that the job has finished so we can take ownership of the job and handle
-        let (running_job, handle) = self.current_running_job.take().unwrap();
+        while self.running_jobs.len() < self.max_concurrent_jobs
+            && self.start_job() != JobStart::NothingStartable
+        {}
 
+        self.metrics.set_jobs(
+            self.queue.len(),
+            self.total_queued_bytes(),
+            self.running_jobs.len(),
+        );
+    }
+
+    /// Handle the result of a job that has finished
+    fn finish_job(
+        &mut self,
+        running_job: RunningJob,
+        handle: JoinHandle<Result<WorkflowReport, RunnerError>>,
+    ) {
         let result = handle.join();
 
         debug!("job  finished",);
         debug!("job: {running_job:?}");
         debug!("result: {result:?}");
+
+        self.with_connection("remove finished job from queue", |connection| {
+            db::remove_queued_job(connection, &running_job.0.file_path)
+        });
+
+        match result {
+            Ok(Ok(report)) => {
+                info!(
+                    "finished file {}: {report}",
+                    running_job.0.file_path.to_string_lossy()
+                );
+                for task_report in report.task_reports() {
+                    debug!("task stdout: {}", task_report.stdout());
+                    debug!("task stderr: {}", task_report.stderr());
+                }
+                if let Some(throughput) = report.throughput_bytes_per_second() {
+                    info!("processed at {:.2} MiB/s", throughput / (1024.0 * 1024.0));
+                }
+                if let (Some(duration), Some(estimated_duration_ms)) =
+                    (report.duration(), report.estimated_duration_ms())
+                {
+                    debug!(
+                        "took {:.0} s, it was estimated to take {:.0} s",
+                        duration.as_secs_f64(),
+                        estimated_duration_ms as f64 / 1000.0
+                    );
+                }
+                if !report.was_successful() {
+                    warn!("workflow completed, but not every task reported success");
+                }
+                self.summary.record_report(&report);
+                self.metrics.record_report(&report);
+                self.record_history(&running_job.0, &report);
+                self.clear_failures(&running_job.0);
+
+                if report.reason() == SkipReason::Completed && !self.completion_cooldown.is_zero() {
+                    self.recently_completed
+                        .insert(running_job.0.file_path.clone(), Instant::now());
+                }
+            }
+            Ok(Err(err)) if err.is_deferral() => {
+                info!(
+                    "{err}, deferring it for {} seconds",
+                    self.defer_duration.as_secs()
+                );
+                self.defer(running_job.0);
+            }
+            // the file was not processed, so there is neither a failure nor anything to record
+            Ok(Err(err)) if err.is_source_file_disappeared() => {
+                info!("{err}, skipping it");
+            }
+            Ok(Err(err)) => {
+                error!("job failed: {err}");
+                self.summary.failed += 1;
+                self.metrics.record_failure();
+                self.record_failure(&running_job.0, err.failed_task());
+            }
+            Err(_) => {
+                error!("runner thread panicked");
+                self.summary.failed += 1;
+                self.metrics.record_failure();
+                self.record_failure(&running_job.0, None);
+            }
+        }
+    }
+
+    /// Whether another job of the library may run next to the ones that are already running
+    fn library_has_room(&self, library: &str) -> bool {
+        let Some(max_concurrent_jobs) = self.max_concurrent_jobs_per_library.get(library) else {
+            return true;
+        };
+
+        let running = self
+            .running_jobs
+            .iter()
+            .filter(|(running_job, _)| running_job.0.library == library)
+            .count();
+
+        running < *max_concurrent_jobs
+    }
+
+    /// Perform an operation on the state database, if one is used. Failures are logged.
+    fn with_connection<T>(
+        &self,
+        description: &str,
+        operation: impl FnOnce(&Connection) -> rusqlite::Result<T>,
+    ) -> Option<T> {
+        let connection = self.connection.as_ref()?;
+
+        operation(connection)
+            .inspect_err(|err| error!("unable to {description}: {err}"))
+            .ok()
+    }
+
+    /// Whether the file is quarantined because it failed too often
+    fn is_quarantined(&self, file_path: &Path) -> bool {
+        let Some(connection) = &self.connection else {
+            return false;
+        };
+
+        db::is_quarantined(connection, file_path).unwrap_or_else(|err| {
+            error!("unable to check quarantine: {err}");
+            false
+        })
     }
 
-    /// Start a new job based on the first requested job in the queue
-    fn start_job(&mut self) {
-        if self.current_running_job.is_some() {
-            warn!("trying to start job but one is already running");
+    /// Queue a job again, to be started once the defer duration has passed
+    fn defer(&mut self, job: JobRequest) {
+        self.with_connection("persist deferred job", |connection| {
+            db::persist_queued_job(connection, &job.library, &job.file_path)
+        });
+
+        let mut runnable_job = RunnableJob::new(job);
+        runnable_job.deferred_until = Some(Instant::now() + self.defer_duration);
+        self.queue.push_back(runnable_job);
+    }
+
+    /// Record a failed job in the history, quarantining its file once it failed too often.
+    /// Of the tasks that have run, only the one that failed is known.
+    fn record_failure(&self, job: &JobRequest, failed_task: Option<&str>) {
+        let Some(connection) = &self.connection else {
             return;
+        };
+
+        let record = db::JobRecord {
+            source_file_path: &job.file_path,
+            workflow: &job.workflow.name,
+            outcome: db::FAILED_OUTCOME,
+            duration: None,
+            throughput_bytes_per_second: None,
+            task_counts: None,
+            tasks: failed_task.into_iter().map(String::from).collect(),
+            builtins: job.workflow.builtin_references(),
+        };
+        if let Err(err) = db::record_job_history(connection, &record) {
+            error!("unable to record job history: {err}");
         }
 
-        let job_request = match self.queue.pop_front() {
-            Some(job_request) => job_request,
-            None => {
-                debug!("nothing in queue; cannot start a new job");
-                return;
+        match db::record_failure(connection, &job.file_path, self.quarantine_threshold) {
+            Ok(true) => warn!(
+                "file {} failed {} times and has been quarantined, it will be skipped until it is cleared with \"omzet quarantine clear\"",
+                job.file_path.to_string_lossy(),
+                self.quarantine_threshold
+            ),
+            Ok(false) => {}
+            Err(err) => error!("unable to record job failure: {err}"),
+        }
+    }
+
+    /// Forget earlier failures of a job's file after it was processed successfully
+    fn clear_failures(&self, job: &JobRequest) {
+        let Some(connection) = &self.connection else {
+            return;
+        };
+
+        if let Err(err) = db::clear_failures(connection, &job.file_path) {
+            error!("unable to clear job failures: {err}");
+        }
+    }
+
+    /// Record the outcome of a job in the history, when a state database is used
+    fn record_history(&self, job: &JobRequest, report: &WorkflowReport) {
+        let Some(connection) = &self.connection else {
+            return;
+        };
+
+        let record = db::JobRecord {
+            source_file_path: &job.file_path,
+            workflow: &job.workflow.name,
+            outcome: report.reason().as_str(),
+            duration: report.duration(),
+            throughput_bytes_per_second: report.throughput_bytes_per_second(),
+            task_counts: Some(db::TaskCounts {
+                run: report.task_count(),
+                skipped: report.skipped_task_count(),
+            }),
+            tasks: report
+                .task_reports()
+                .iter()
+                .filter_map(TaskReport::task)
+                .map(String::from)
+                .collect(),
+            builtins: job.workflow.builtin_references(),
+        };
+
+        if let Err(err) = db::record_job_history(connection, &record) {
+            error!("unable to record job history: {err}");
+        }
+
+        // the durations make the estimate of the next runs of these tasks more accurate
+        for task_report in report.task_reports() {
+            let (Some(task), Some(duration)) = (task_report.task(), task_report.duration()) else {
+                continue;
+            };
+
+            if let Err(err) = db::record_task_duration(connection, task, duration) {
+                error!("unable to record task duration: {err}");
             }
+        }
+    }
+
+    /// Start a new job based on the greatest startable job in the queue, of which the library has
+    /// room for another job.
+    fn start_job(&mut self) -> JobStart {
+        let now = Instant::now();
+
+        let position = self
+            .queue
+            .iter()
+            .enumerate()
+            .filter(|(_, job)| job.is_startable_at(now) && self.library_has_room(&job.library))
+            .max_by(|(_, job), (_, other)| job.cmp(other))
+            .map(|(position, _)| position);
+
+        let Some(mut job_request) = position.and_then(|position| self.queue.remove(position))
+        else {
+            debug!("nothing startable in queue; cannot start a new job");
+            return JobStart::NothingStartable;
         };
 
+        if file_usage::is_opened_for_writing(&job_request.file_path) {
+            info!(
+                "file {} is being written to by another process, deferring it for {} seconds",
+                job_request.file_path.to_string_lossy(),
+                self.defer_duration.as_secs()
+            );
+
+            // like a job that is deferred after it ran, it is started after the others
+            job_request.deferred_until = Some(now + self.defer_duration);
+            job_request.enqueued_at = now;
+            self.queue.push_back(job_request);
+            return JobStart::Deferred;
+        }
+
         debug!(
             "starting job for file {}",
             job_request.file_path.to_string_lossy()
         );
 
+        self.with_connection("mark queued job as running", |connection| {
+            db::mark_queued_job_running(connection, &job_request.file_path)
+        });
+
         let workflow = job_request.workflow.clone();
         let file_path = job_request.file_path.clone();
+        let runner = Arc::clone(&self.runner);
+        let wakeup = Arc::clone(&self.wakeup);
 
         let handle = thread::Builder::new()
             .name(String::from("runner"))
             .spawn(move || {
-                let runner = Runner::new();
-                runner.run_workflow(&workflow, PathBuf::from(file_path))
+                let result = runner.run_workflow(&workflow, PathBuf::from(file_path));
+                wakeup.notify();
+                result
             })
             .expect("unable to start worker");
 
-        self.current_running_job = Some((RunningJob(job_request.0), handle));
+        self.running_jobs
+            .push((RunningJob(job_request.request), handle));
+
+        JobStart::Started
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::workflow::test_workflow;
+
+    use super::*;
+
+    /// Pretends to run workflows, only files named "ok.mkv" complete successfully
+    struct FakeRunner;
+
+    impl WorkflowRunner for FakeRunner {
+        fn run_workflow(
+            &self,
+            workflow: &Workflow,
+            source_file: PathBuf,
+        ) -> Result<WorkflowReport, RunnerError> {
+            if source_file.ends_with("ok.mkv") {
+                return Ok(WorkflowReport::new_with_reports(
+                    workflow.clone(),
+                    vec![TaskReport::success()],
+                ));
+            }
+
+            Err(RunnerError::TaskFailed {
+                task: String::from("fake"),
+                file: source_file,
+                exit_code: 1,
+            })
+        }
+    }
+
+    #[test]
+    fn workflow_report_is_successful_when_all_tasks_succeeded() {
+        let workflow = test_workflow("movies", PathBuf::from("/tmp/omzet"));
+
+        let report = WorkflowReport::new_with_reports(
+            workflow.clone(),
+            vec![
+                TaskReport::new(Some(0), String::from("done"), String::new()),
+                TaskReport::success(),
+            ],
+        );
+        assert_eq!(2, report.task_count());
+        assert!(report.was_successful());
+        assert_eq!("movies", report.workflow_name());
+        assert_eq!("done", report.task_reports()[0].stdout());
+
+        let report = WorkflowReport::new_with_reports(
+            workflow,
+            vec![TaskReport::new(
+                Some(1),
+                String::new(),
+                String::from("oops"),
+            )],
+        );
+        assert!(!report.was_successful());
+        assert!(!report.task_reports()[0].succeeded());
+        assert_eq!("oops", report.task_reports()[0].stderr());
+
+        // a task without an exit code was killed by a signal
+        let killed = TaskReport::new(None, String::new(), String::new());
+        assert!(!killed.succeeded());
+        assert_eq!(
+            "[✗] fake (killed by a signal)",
+            killed.for_task("fake", Duration::ZERO).to_string()
+        );
+    }
+
+    #[test]
+    fn workflow_report_is_displayed_as_summary() {
+        let workflow = test_workflow("movies", PathBuf::from("/tmp/omzet"));
+
+        let report = WorkflowReport::new_with_reports(
+            workflow.clone(),
+            vec![
+                TaskReport::success().for_task("remux", Duration::from_millis(2100)),
+                TaskReport::failure(String::from("oops"))
+                    .for_task("transcode", Duration::from_secs(1)),
+            ],
+        )
+        .with_skipped_tasks(vec![(String::from("subtitles"), ProbeResult::Abort)])
+        .with_timing(0, Duration::from_millis(45200));
+        assert_eq!(
+            "Workflow 'movies' completed: 2 tasks run, 1 skipped, total 45.2 s\n  [✓] remux (2.1 s)\n  [✗] transcode (exit code 1)\n  [-] subtitles (probe aborted)",
+            report.to_string()
+        );
+
+        assert_eq!(
+            "Workflow 'movies' skipped: all probes requested to skip their task\n  [-] remux (probe skipped)",
+            WorkflowReport::new(workflow, SkipReason::AllProbesSkipped)
+                .with_skipped_tasks(vec![(String::from("remux"), ProbeResult::Skip)])
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn run_summary_counts_reports_by_reason() {
+        let workflow = test_workflow("movies", PathBuf::from("/tmp/omzet"));
+
+        let mut summary = RunSummary::default();
+        summary.record_report(&WorkflowReport::new_with_reports(
+            workflow.clone(),
+            vec![TaskReport::success()],
+        ));
+        summary.record_report(&WorkflowReport::new(workflow, SkipReason::AllProbesSkipped));
+
+        assert_eq!(1, summary.processed);
+        assert_eq!(1, summary.skipped);
+        assert_eq!(1, summary.tasks_run);
+        assert_eq!(
+            "dispatched=0 processed=1 skipped=1 failed=0 tasks_run=1",
+            summary.machine_line()
+        );
+    }
+
+    #[test]
+    fn orchestrator_runs_all_queued_jobs() {
+        let workflow = test_workflow("movies", PathBuf::from("/tmp/omzet"));
+
+        let (orchestrator, sender) =
+            JobOrchestrator::new(Arc::new(FakeRunner), false, 3, Duration::from_secs(30), 1)
+                .unwrap();
+
+        for file in ["/movies/ok.mkv", "/movies/broken.mkv", "/movies/ok.mkv"] {
+            sender
+                .send(Box::new(JobRequest::new(
+                    String::from("movies"),
+                    PathBuf::from(file),
+                    workflow.clone(),
+                )))
+                .unwrap();
+        }
+
+        let summary = orchestrator.run_until_idle();
+
+        assert_eq!(3, summary.dispatched);
+        assert_eq!(1, summary.processed);
+        assert_eq!(1, summary.failed);
+        assert_eq!(1, summary.tasks_run);
+    }
+
+    /// Remembers the order in which it was asked to run workflows
+    #[derive(Default)]
+    struct RecordingRunner {
+        started: std::sync::Mutex<Vec<PathBuf>>,
+    }
+
+    impl WorkflowRunner for RecordingRunner {
+        fn run_workflow(
+            &self,
+            workflow: &Workflow,
+            source_file: PathBuf,
+        ) -> Result<WorkflowReport, RunnerError> {
+            let mut started = self.started.lock().unwrap();
+
+            // a file named "deferred.mkv" is not ready the first time it is run
+            if source_file.ends_with("deferred.mkv") && !started.contains(&source_file) {
+                started.push(source_file.clone());
+                return Err(RunnerError::ProbeDeferred {
+                    task: String::from("fake"),
+                    file: source_file,
+                });
+            }
+            started.push(source_file);
+
+            Ok(WorkflowReport::new(
+                workflow.clone(),
+                SkipReason::NoTasksConfigured,
+            ))
+        }
+    }
+
+    #[test]
+    fn jobs_are_started_in_the_order_they_were_dispatched() {
+        let workflow = test_workflow("movies", PathBuf::from("/tmp/omzet"));
+        let runner = Arc::new(RecordingRunner::default());
+
+        let (orchestrator, sender) =
+            JobOrchestrator::new(runner.clone(), false, 3, Duration::from_secs(30), 1).unwrap();
+
+        let files = ["/movies/c.mkv", "/movies/a.mkv", "/movies/b.mkv"].map(PathBuf::from);
+        for file in &files {
+            sender
+                .send(Box::new(JobRequest::new(
+                    String::from("movies"),
+                    file.clone(),
+                    workflow.clone(),
+                )))
+                .unwrap();
+        }
+
+        orchestrator.run_until_idle();
+
+        assert_eq!(files.to_vec(), *runner.started.lock().unwrap());
+    }
+
+    #[test]
+    fn size_of_queued_source_files_is_totalled() {
+        let temp_test_dir = tempdir::TempDir::new("omzet-test").unwrap();
+        let workflow = test_workflow("movies", PathBuf::from("/tmp/omzet"));
+
+        let (mut orchestrator, sender) =
+            JobOrchestrator::new(Arc::new(FakeRunner), false, 3, Duration::from_secs(30), 1)
+                .unwrap();
+
+        fs::write(temp_test_dir.path().join("a.mkv"), [0; 300]).unwrap();
+        fs::write(temp_test_dir.path().join("b.mkv"), [0; 200]).unwrap();
+        // a file that cannot be read counts as empty
+        for file in ["a.mkv", "b.mkv", "a.mkv", "missing.mkv"] {
+            sender
+                .send(Box::new(JobRequest::new(
+                    String::from("movies"),
+                    temp_test_dir.path().join(file),
+                    workflow.clone(),
+                )))
+                .unwrap();
+        }
+
+        orchestrator.handle_incoming_job_requests();
+
+        assert_eq!(3, orchestrator.queue.len());
+        assert_eq!(500, orchestrator.total_queued_bytes());
+    }
+
+    #[test]
+    fn completed_file_is_not_queued_again_during_cooldown() {
+        let workflow = test_workflow("movies", PathBuf::from("/tmp/omzet"));
+
+        let (mut orchestrator, sender) =
+            JobOrchestrator::new(Arc::new(FakeRunner), false, 3, Duration::from_secs(30), 1)
+                .unwrap();
+        orchestrator.set_completion_cooldown(Duration::from_secs(60));
+
+        let send = |file: &str| {
+            sender
+                .send(Box::new(JobRequest::new(
+                    String::from("movies"),
+                    PathBuf::from(file),
+                    workflow.clone(),
+                )))
+                .unwrap();
+        };
+
+        send("/movies/ok.mkv");
+        send("/movies/fails.mkv");
+        loop {
+            orchestrator.handle_incoming_job_requests();
+            orchestrator.handle_runner();
+
+            if orchestrator.queue.is_empty() && orchestrator.running_jobs.is_empty() {
+                break;
+            }
+
+            orchestrator.wakeup.wait(Duration::from_millis(100));
+        }
+
+        // only the completed file is in its cooldown, a failed file can be retried
+        send("/movies/ok.mkv");
+        send("/movies/fails.mkv");
+        orchestrator.handle_incoming_job_requests();
+
+        assert_eq!(1, orchestrator.queue.len());
+        assert_eq!(
+            PathBuf::from("/movies/fails.mkv"),
+            orchestrator.queue[0].file_path
+        );
+    }
+
+    #[test]
+    fn file_of_running_job_is_not_queued_again() {
+        let workflow = test_workflow("movies", PathBuf::from("/tmp/omzet"));
+
+        let (mut orchestrator, sender) = JobOrchestrator::new(
+            Arc::new(ConcurrencyRunner::default()),
+            false,
+            3,
+            Duration::from_secs(30),
+            1,
+        )
+        .unwrap();
+
+        let send = || {
+            sender
+                .send(Box::new(JobRequest::new(
+                    String::from("movies"),
+                    PathBuf::from("/movies/a.mkv"),
+                    workflow.clone(),
+                )))
+                .unwrap();
+        };
+
+        send();
+        orchestrator.handle_incoming_job_requests();
+        orchestrator.handle_runner();
+        assert_eq!(1, orchestrator.running_jobs.len());
+
+        send();
+        send();
+        orchestrator.handle_incoming_job_requests();
+
+        assert!(orchestrator.queue.is_empty());
+    }
+
+    #[test]
+    fn jobs_with_higher_priority_are_started_first() {
+        let workflow = test_workflow("movies", PathBuf::from("/tmp/omzet"));
+        let runner = Arc::new(RecordingRunner::default());
+
+        let (orchestrator, sender) =
+            JobOrchestrator::new(runner.clone(), false, 3, Duration::from_secs(30), 1).unwrap();
+
+        for (file, priority) in [("a.mkv", 0), ("b.mkv", 5), ("c.mkv", 0), ("d.mkv", 5)] {
+            sender
+                .send(Box::new(
+                    JobRequest::new(
+                        String::from("movies"),
+                        PathBuf::from("/movies").join(file),
+                        workflow.clone(),
+                    )
+                    .with_priority(priority),
+                ))
+                .unwrap();
+        }
+
+        orchestrator.run_until_idle();
+
+        assert_eq!(
+            ["b.mkv", "d.mkv", "a.mkv", "c.mkv"]
+                .map(|file| PathBuf::from("/movies").join(file))
+                .to_vec(),
+            *runner.started.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn deferred_job_is_started_again_after_the_others() {
+        let workflow = test_workflow("movies", PathBuf::from("/tmp/omzet"));
+        let runner = Arc::new(RecordingRunner::default());
+
+        let (orchestrator, sender) =
+            JobOrchestrator::new(runner.clone(), false, 3, Duration::ZERO, 1).unwrap();
+
+        for file in ["/movies/deferred.mkv", "/movies/ready.mkv"] {
+            sender
+                .send(Box::new(JobRequest::new(
+                    String::from("movies"),
+                    PathBuf::from(file),
+                    workflow.clone(),
+                )))
+                .unwrap();
+        }
+
+        let summary = orchestrator.run_until_idle();
+
+        assert_eq!(
+            [
+                "/movies/deferred.mkv",
+                "/movies/ready.mkv",
+                "/movies/deferred.mkv"
+            ]
+            .map(PathBuf::from)
+            .to_vec(),
+            *runner.started.lock().unwrap()
+        );
+        assert_eq!(0, summary.failed);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn job_after_a_file_in_use_is_started() {
+        let temp_test_dir = tempdir::TempDir::new("omzet-test").unwrap();
+        let workflow = test_workflow("movies", PathBuf::from("/tmp/omzet"));
+
+        let (mut orchestrator, sender) = JobOrchestrator::new(
+            Arc::new(RecordingRunner::default()),
+            false,
+            3,
+            Duration::from_secs(30),
+            2,
+        )
+        .unwrap();
+
+        let in_use = temp_test_dir.path().join("in-use.mkv");
+        let _writer = fs::File::create(&in_use).unwrap();
+        let ready = temp_test_dir.path().join("ready.mkv");
+        fs::write(&ready, "").unwrap();
+
+        // the file in use is picked first
+        for (file, priority) in [(&in_use, 5), (&ready, 0)] {
+            sender
+                .send(Box::new(
+                    JobRequest::new(String::from("movies"), file.clone(), workflow.clone())
+                        .with_priority(priority),
+                ))
+                .unwrap();
+        }
+
+        orchestrator.handle_incoming_job_requests();
+        orchestrator.handle_runner();
+
+        assert_eq!(1, orchestrator.running_jobs.len());
+        assert_eq!(ready, orchestrator.running_jobs[0].0 .0.file_path);
+        assert_eq!(in_use, orchestrator.queue[0].file_path);
+    }
+
+    #[test]
+    fn notification_sent_before_waiting_is_not_missed() {
+        let wakeup = Wakeup::default();
+        wakeup.notify();
+
+        let started = Instant::now();
+        wakeup.wait(Duration::from_secs(10));
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        // the notification was consumed, so the next wait lasts until the timeout
+        let started = Instant::now();
+        wakeup.wait(Duration::from_millis(100));
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+
+    /// Keeps track of the most jobs it ran at the same time, per directory of the files
+    #[derive(Default)]
+    struct ConcurrencyRunner {
+        running: std::sync::Mutex<HashMap<PathBuf, usize>>,
+        most_running: std::sync::Mutex<HashMap<PathBuf, usize>>,
+    }
+
+    impl WorkflowRunner for ConcurrencyRunner {
+        fn run_workflow(
+            &self,
+            workflow: &Workflow,
+            source_file: PathBuf,
+        ) -> Result<WorkflowReport, RunnerError> {
+            let directory = source_file.parent().unwrap().to_path_buf();
+
+            {
+                let mut running = self.running.lock().unwrap();
+                let count = running.entry(directory.clone()).or_default();
+                *count += 1;
+
+                let mut most_running = self.most_running.lock().unwrap();
+                let most = most_running.entry(directory.clone()).or_default();
+                *most = (*most).max(*count);
+            }
+
+            thread::sleep(Duration::from_millis(500));
+            *self.running.lock().unwrap().get_mut(&directory).unwrap() -= 1;
+
+            Ok(WorkflowReport::new(
+                workflow.clone(),
+                SkipReason::NoTasksConfigured,
+            ))
+        }
+    }
+
+    #[test]
+    fn libraries_do_not_exceed_their_concurrent_job_limit() {
+        let workflow = test_workflow("movies", PathBuf::from("/tmp/omzet"));
+        let library = |name: &str, max_concurrent_jobs| {
+            let mut library = Library::new(
+                String::from(name),
+                workflow.clone(),
+                PathBuf::from(format!("/{name}")),
+                Default::default(),
+                false,
+                false,
+                None,
+            );
+            library.max_concurrent_jobs = Some(max_concurrent_jobs);
+            library
+        };
+        let runner = Arc::new(ConcurrencyRunner::default());
+
+        let (mut orchestrator, sender) =
+            JobOrchestrator::new(runner.clone(), false, 3, Duration::from_secs(30), 3).unwrap();
+        orchestrator
+            .limit_concurrent_jobs_per_library(&[library("movies", 1), library("series", 2)]);
+
+        for name in ["movies", "series"] {
+            for file in ["a.mkv", "b.mkv", "c.mkv", "d.mkv"] {
+                sender
+                    .send(Box::new(JobRequest::new(
+                        String::from(name),
+                        PathBuf::from(format!("/{name}/{file}")),
+                        workflow.clone(),
+                    )))
+                    .unwrap();
+            }
+        }
+
+        let summary = orchestrator.run_until_idle();
+
+        assert_eq!(8, summary.skipped);
+        let most_running = runner.most_running.lock().unwrap();
+        assert_eq!(1, most_running[&PathBuf::from("/movies")]);
+        assert_eq!(2, most_running[&PathBuf::from("/series")]);
+    }
+
+    #[test]
+    fn task_output_written_to_log_file_is_truncated_in_report() {
+        let temp_test_dir = tempdir::TempDir::new("omzet-test").unwrap();
+        let log_file = temp_test_dir.path().join("logs/job/task.log");
+
+        let stdout = format!("{}end of stdout", "é".repeat(TASK_OUTPUT_TAIL_BYTES));
+        let mut report = TaskReport::new(Some(2), stdout.clone(), String::from("oops"));
+
+        report.write_to_log_file(&log_file).unwrap();
+
+        assert_eq!(Some(log_file.as_path()), report.log_file());
+        assert!(report.stdout().len() <= TASK_OUTPUT_TAIL_BYTES);
+        assert!(report.stdout().ends_with("end of stdout"));
+        assert_eq!("oops", report.stderr());
+
+        let contents = fs::read_to_string(&log_file).unwrap();
+        assert!(contents.starts_with("exit code: 2"));
+        assert!(contents.contains(&stdout));
+    }
+
+    #[test]
+    fn throughput_is_derived_from_file_size_and_duration() {
+        let workflow = test_workflow("movies", PathBuf::from("/tmp/omzet"));
+
+        let report = WorkflowReport::new_with_reports(workflow.clone(), vec![])
+            .with_timing(1000, Duration::from_secs(4));
+        assert_eq!(Some(Duration::from_secs(4)), report.duration());
+        assert_eq!(Some(250.0), report.throughput_bytes_per_second());
+
+        let report =
+            WorkflowReport::new_with_reports(workflow, vec![]).with_timing(1000, Duration::ZERO);
+        assert_eq!(None, report.throughput_bytes_per_second());
     }
 }
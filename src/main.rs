@@ -1,25 +1,69 @@
 use std::{
+    env,
     io::{self},
+    path::Path,
     process::exit,
 };
 
 use app::App;
-use config::read_config;
-use tracing::{debug, error, level_filters::LevelFilter};
-use tracing_subscriber::EnvFilter;
+use clap::Parser;
+use cli::Cli;
+use config::{read_config, LoggingConfig, MonitorMode};
+use tracing::{debug, error, info, level_filters::LevelFilter};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{
+    filter::Directive, fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter,
+};
 use workflow::Workflow;
 
 mod app;
+mod cli;
+mod commands;
 mod config;
 mod db;
+mod duration;
+mod file_usage;
+mod file_watcher;
+mod fingerprint;
 mod job_orchestration;
+mod metrics;
 mod workflow;
 mod workflow_runner;
 
 fn main() {
-    setup_logging();
+    let cli = Cli::parse();
+
+    if let Some(command) = cli.command {
+        setup_logging(
+            &effective_logging(&LoggingConfig::default(), cli.quiet),
+            cli.log_file.as_deref(),
+            use_color(cli.no_color),
+        );
+
+        match commands::run(command, cli.config.as_deref()) {
+            Ok(_) => exit(0),
+            Err(err) => {
+                error!("{}", err);
+                exit(1);
+            }
+        }
+    }
+
+    // the logging configuration is part of the config, so logging can only be set up afterwards
+    let config = read_config(cli.config.as_deref());
+
+    let default_logging = LoggingConfig::default();
+    let logging = config
+        .as_ref()
+        .map(|config| &config.logging)
+        .unwrap_or(&default_logging);
+    setup_logging(
+        &effective_logging(logging, cli.quiet),
+        cli.log_file.as_deref().or(logging.file.as_deref()),
+        use_color(cli.no_color),
+    );
 
-    let config = match read_config() {
+    let mut config = match config {
         Ok(config) => config,
         Err(err) => {
             error!("error occurred while trying to read configuration");
@@ -29,7 +73,29 @@ fn main() {
         }
     };
 
-    let app = App::new(config);
+    if cli.watch {
+        config.monitor_mode = MonitorMode::Watch;
+    }
+
+    let app = App::new(config, !cli.no_db).with_metrics_address(cli.metrics_addr);
+
+    if cli.run_once {
+        match app.run_once() {
+            Ok(summary) => {
+                if !cli.quiet || summary.failed > 0 {
+                    eprintln!("{summary}");
+                }
+                if cli.machine_summary {
+                    println!("{}", summary.machine_line());
+                }
+                exit(if summary.failed > 0 { 1 } else { 0 });
+            }
+            Err(err) => {
+                error!("{}", err);
+                exit(1);
+            }
+        }
+    }
 
     match app.run() {
         Ok(_) => {
@@ -44,14 +110,83 @@ fn main() {
     }
 }
 
-fn setup_logging() {
-    tracing_subscriber::fmt()
-        .with_writer(io::stderr)
-        .with_env_filter(
-            EnvFilter::builder()
-                .with_default_directive(LevelFilter::INFO.into())
-                .from_env_lossy(),
+/// The logging configuration to use, `--quiet` only keeps warnings and errors of it.
+/// Either way, directives in `RUST_LOG` take precedence, see [`setup_logging`].
+fn effective_logging(logging: &LoggingConfig, quiet: bool) -> LoggingConfig {
+    if quiet {
+        logging.quiet()
+    } else {
+        logging.clone()
+    }
+}
+
+/// Whether output may be colored, which is disabled by `--no-color` or a non-empty `NO_COLOR`
+/// environment variable, see <https://no-color.org>
+fn use_color(no_color: bool) -> bool {
+    !no_color && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+/// Set up logging using the configured levels, any directive from `RUST_LOG` takes precedence.
+/// Logs always go to stderr and, when given, to a daily rotated log file. Both use the same levels.
+/// Only the logs on stderr are colored, when `color` allows it.
+fn setup_logging(logging: &LoggingConfig, log_file: Option<&Path>, color: bool) {
+    let mut filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .parse_lossy(logging.directives());
+
+    if let Ok(env_directives) = env::var(EnvFilter::DEFAULT_ENV) {
+        for directive in env_directives
+            .split(',')
+            .filter_map(|directive| directive.parse::<Directive>().ok())
+        {
+            filter = filter.add_directive(directive);
+        }
+    }
+
+    let file_layer = log_file.and_then(|log_file| match open_log_file(log_file) {
+        Ok(appender) => Some(
+            fmt::layer()
+                .with_writer(appender)
+                .with_ansi(false)
+                .with_thread_names(true),
+        ),
+        Err(err) => {
+            // logging is not set up yet, so this can only be reported on stderr directly
+            eprintln!(
+                "unable to log to file {}, only logging to stderr: {err}",
+                log_file.to_string_lossy()
+            );
+            None
+        }
+    });
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            fmt::layer()
+                .with_writer(io::stderr)
+                .with_ansi(color)
+                .with_thread_names(true),
         )
-        .with_thread_names(true)
+        .with(file_layer)
         .init();
+
+    info!("omzet {}", env!("CARGO_PKG_VERSION"));
+}
+
+/// Open a log file that is rotated daily, the date is appended to the file name
+fn open_log_file(path: &Path) -> Result<RollingFileAppender, String> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| String::from("the path does not contain a file name"))?;
+    let directory = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(file_name.to_string_lossy())
+        .build(directory)
+        .map_err(|err| err.to_string())
 }
@@ -0,0 +1,231 @@
+//! Metrics of the orchestrator in the Prometheus text format, served over HTTP on request.
+//! The server is deliberately tiny: it answers `GET /metrics` and nothing else.
+
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use tracing::{debug, info};
+
+use crate::job_orchestration::{SkipReason, WorkflowReport};
+
+/// Counters and gauges shared between the orchestrator and the metrics server
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    processed: AtomicU64,
+    skipped: AtomicU64,
+    failed: AtomicU64,
+    /// Size by which transformed files are smaller than their source files
+    bytes_saved: AtomicU64,
+    /// Size by which transformed files are larger than their source files
+    bytes_added: AtomicU64,
+    queued: AtomicUsize,
+    /// Total size of the source files of the queued jobs
+    queued_bytes: AtomicU64,
+    running: AtomicUsize,
+}
+
+impl Metrics {
+    pub(crate) fn record_report(&self, report: &WorkflowReport) {
+        match report.reason() {
+            SkipReason::Completed => self.processed.fetch_add(1, Ordering::Relaxed),
+            SkipReason::AllProbesSkipped
+            | SkipReason::NoTasksConfigured
+            | SkipReason::MislabeledContainer => self.skipped.fetch_add(1, Ordering::Relaxed),
+        };
+
+        // counters only go up, so a file that grew is counted separately
+        match report.bytes_saved() {
+            Some(bytes_saved) if bytes_saved >= 0 => {
+                self.bytes_saved
+                    .fetch_add(bytes_saved.unsigned_abs(), Ordering::Relaxed);
+            }
+            Some(bytes_added) => {
+                self.bytes_added
+                    .fetch_add(bytes_added.unsigned_abs(), Ordering::Relaxed);
+            }
+            None => {}
+        }
+    }
+
+    pub(crate) fn record_failure(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_jobs(&self, queued: usize, queued_bytes: u64, running: usize) {
+        self.queued.store(queued, Ordering::Relaxed);
+        self.queued_bytes.store(queued_bytes, Ordering::Relaxed);
+        self.running.store(running, Ordering::Relaxed);
+    }
+
+    /// Render the metrics in the Prometheus text exposition format
+    pub(crate) fn render(&self) -> String {
+        let metrics = [
+            (
+                "omzet_jobs_processed_total",
+                "counter",
+                "Files of which all tasks have run",
+                self.processed.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "omzet_jobs_skipped_total",
+                "counter",
+                "Files for which no task had to run",
+                self.skipped.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "omzet_jobs_failed_total",
+                "counter",
+                "Files of which the workflow failed",
+                self.failed.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "omzet_bytes_saved_total",
+                "counter",
+                "Size by which transformed files are smaller than their source files",
+                self.bytes_saved.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "omzet_bytes_added_total",
+                "counter",
+                "Size by which transformed files are larger than their source files",
+                self.bytes_added.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "omzet_jobs_queued",
+                "gauge",
+                "Jobs waiting to be started",
+                self.queued.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "omzet_queued_bytes",
+                "gauge",
+                "Total size of the source files of the queued jobs",
+                self.queued_bytes.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "omzet_jobs_running",
+                "gauge",
+                "Jobs that are currently running",
+                self.running.load(Ordering::Relaxed).to_string(),
+            ),
+        ];
+
+        let mut output = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(output, "# HELP {name} {help}");
+            let _ = writeln!(output, "# TYPE {name} {kind}");
+            let _ = writeln!(output, "{name} {value}");
+        }
+
+        output
+    }
+}
+
+/// Time a client gets to send its request, or to receive the response, before it is disconnected
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serve the metrics on `http://<address>/metrics` in a separate thread, with a thread per
+/// connection so a slow client does not hold up the others.
+/// Returns the address that is listened on, which differs from the given one for port 0.
+pub(crate) fn serve(address: SocketAddr, metrics: Arc<Metrics>) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(address)?;
+    let address = listener.local_addr()?;
+
+    thread::Builder::new()
+        .name(String::from("metrics"))
+        .spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let metrics = Arc::clone(&metrics);
+                let spawned = thread::Builder::new()
+                    .name(String::from("metrics-request"))
+                    .spawn(move || {
+                        if let Err(err) = respond(stream, &metrics) {
+                            debug!("unable to respond to metrics request: {err}");
+                        }
+                    });
+                if let Err(err) = spawned {
+                    debug!("unable to handle metrics request: {err}");
+                }
+            }
+        })?;
+
+    info!("serving metrics on http://{address}/metrics");
+
+    Ok(address)
+}
+
+/// Answer a single request, the connection is closed afterwards
+fn respond(mut stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    let (status, body) = match request_line.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+        ["GET", "/metrics"] => ("200 OK", metrics.render()),
+        _ => ("404 Not Found", String::from("only /metrics is served\n")),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    #[test]
+    fn metrics_are_served_in_prometheus_format() {
+        let metrics = Arc::new(Metrics::default());
+        metrics.record_failure();
+        metrics.set_jobs(4, 2_000_000, 1);
+
+        let address = serve("127.0.0.1:0".parse().unwrap(), Arc::clone(&metrics)).unwrap();
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response
+            .contains("# TYPE omzet_jobs_failed_total counter\nomzet_jobs_failed_total 1\n"));
+        assert!(response.contains("omzet_jobs_queued 4\n"));
+        assert!(response.contains("omzet_queued_bytes 2000000\n"));
+        assert!(response.contains("omzet_jobs_running 1\n"));
+    }
+
+    #[test]
+    fn idle_connection_does_not_hold_up_requests() {
+        let address = serve("127.0.0.1:0".parse().unwrap(), Arc::new(Metrics::default())).unwrap();
+
+        let _idle = TcpStream::connect(address).unwrap();
+
+        let started_at = std::time::Instant::now();
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(started_at.elapsed() < REQUEST_TIMEOUT);
+    }
+}
@@ -1,32 +1,307 @@
-use std::path::PathBuf;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::db;
+pub(crate) use crate::workflow_runner::{BuiltinTask, InvalidBuiltinTask};
 
 #[derive(Debug, Clone)]
 pub(crate) struct Library {
     pub(crate) name: String,
     pub(crate) workflow: Workflow,
     pub(crate) directory: PathBuf,
+    /// The order in which scanned files are dispatched
+    pub(crate) scan_sort: SortStrategy,
+    /// Only dispatch files of which the size did not change since the previous scan
+    pub(crate) wait_for_stable_size: bool,
+    /// Also scan hidden files and directories, those of which the name starts with a "."
+    pub(crate) scan_hidden: bool,
+    /// Dispatch at most this many files per scan, the next scan continues with the remainder
+    pub(crate) max_file_count_per_scan: Option<usize>,
+    /// Run at most this many jobs of the library at the same time, only the global limit applies
+    /// when absent
+    pub(crate) max_concurrent_jobs: Option<usize>,
+    /// Jobs of a library with a higher priority are started before those of other libraries
+    pub(crate) priority: u8,
 }
 
 impl Library {
-    pub(crate) fn new(name: String, workflow: Workflow, directory: PathBuf) -> Self {
+    pub(crate) fn new(
+        name: String,
+        workflow: Workflow,
+        directory: PathBuf,
+        scan_sort: SortStrategy,
+        wait_for_stable_size: bool,
+        scan_hidden: bool,
+        max_file_count_per_scan: Option<usize>,
+    ) -> Self {
         Self {
             name,
             workflow,
             directory,
+            scan_sort,
+            wait_for_stable_size,
+            scan_hidden,
+            max_file_count_per_scan,
+            max_concurrent_jobs: None,
+            priority: 0,
         }
     }
+
+    /// Check the library for issues that are worth a warning, but do not prevent monitoring it
+    pub(crate) fn validate(&self) -> Vec<LibraryWarning> {
+        let mut warnings = vec![];
+
+        match fs::read_dir(&self.directory) {
+            Ok(mut entries) => {
+                if entries.next().is_none() {
+                    warnings.push(LibraryWarning::DirectoryEmpty);
+                }
+            }
+            Err(_) => warnings.push(LibraryWarning::DirectoryUnreadable),
+        }
+
+        if is_on_same_filesystem(&self.directory, &self.workflow.scratchpad_directory) {
+            warnings.push(LibraryWarning::ScratchpadOnSameFilesystem);
+        }
+
+        let is_suppressed = |extension: &str| {
+            self.workflow
+                .suppress_extension_warnings
+                .iter()
+                .any(|suppressed| suppressed.eq_ignore_ascii_case(extension))
+        };
+
+        warnings.extend(
+            self.workflow
+                .included_extensions
+                .iter()
+                .filter(|extension| !is_known_media_extension(extension))
+                .filter(|extension| !is_suppressed(extension))
+                .map(|extension| LibraryWarning::UnusualExtension(extension.clone())),
+        );
+
+        warnings
+    }
 }
 
-/// A workflow defines which things need to happen when a new file is detected
+const VIDEO_EXTENSIONS: &[&str] = &[
+    "3gp", "avi", "divx", "flv", "m2ts", "m4v", "mkv", "mov", "mp4", "mpeg", "mpg", "mts", "ogv",
+    "ts", "vob", "webm", "wmv",
+];
+const AUDIO_EXTENSIONS: &[&str] = &[
+    "aac", "ac3", "aiff", "alac", "ape", "dts", "eac3", "flac", "m4a", "mka", "mp3", "ogg", "opus",
+    "wav", "wma", "wv",
+];
+const SUBTITLE_EXTENSIONS: &[&str] = &["ass", "idx", "srt", "ssa", "sub", "sup", "vtt"];
+
+/// Whether the extension is one of the common media extensions, regardless of its case
+fn is_known_media_extension(extension: &str) -> bool {
+    [VIDEO_EXTENSIONS, AUDIO_EXTENSIONS, SUBTITLE_EXTENSIONS]
+        .iter()
+        .flat_map(|extensions| extensions.iter())
+        .any(|known| known.eq_ignore_ascii_case(extension))
+}
+
+/// A configuration issue of a library that does not prevent it from being monitored
 #[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum LibraryWarning {
+    /// The library directory does not exist or cannot be read
+    DirectoryUnreadable,
+    /// There is nothing in the library directory, so monitoring it is wasted effort
+    DirectoryEmpty,
+    /// Tasks working in the scratchpad compete with the library for disk I/O
+    ScratchpadOnSameFilesystem,
+    /// An included extension is not a known media extension, which is often a typo
+    UnusualExtension(String),
+}
+
+impl Display for LibraryWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LibraryWarning::DirectoryUnreadable => {
+                write!(f, "the library directory does not exist or cannot be read")
+            }
+            LibraryWarning::DirectoryEmpty => write!(f, "the library directory is empty"),
+            LibraryWarning::ScratchpadOnSameFilesystem => write!(
+                f,
+                "the scratchpad directory is on the same filesystem as the library, tasks will compete with it for disk I/O"
+            ),
+            LibraryWarning::UnusualExtension(extension) => write!(
+                f,
+                "the included extension \"{extension}\" is not a known media extension, add it to the workflow's suppress_extension_warnings if it is not a typo"
+            ),
+        }
+    }
+}
+
+/// Whether both paths are on the same filesystem.
+/// Paths that do not exist yet are compared by their nearest existing ancestor.
+#[cfg(unix)]
+fn is_on_same_filesystem(path: &Path, other: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let device = |path: &Path| {
+        path.ancestors()
+            .find_map(|ancestor| fs::metadata(ancestor).ok())
+            .map(|metadata| metadata.dev())
+    };
+
+    matches!((device(path), device(other)), (Some(device), Some(other_device)) if device == other_device)
+}
+
+#[cfg(not(unix))]
+fn is_on_same_filesystem(_path: &Path, _other: &Path) -> bool {
+    false
+}
+
+/// Determines the order of the files found while scanning a library
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SortStrategy {
+    /// By path, alphabetically
+    #[default]
+    Name,
+    /// Oldest modified files first
+    MtimeAsc,
+    /// Newest modified files first
+    MtimeDesc,
+    /// Smallest files first
+    SizeAsc,
+    /// Largest files first
+    SizeDesc,
+}
+
+/// A workflow defines which things need to happen when a new file is detected
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) struct Workflow {
     pub(crate) name: String,
-    pub(crate) scratchpad_directory: String,
+    pub(crate) scratchpad_directory: PathBuf,
     pub(crate) included_extensions: Vec<String>,
+    /// Glob patterns of file names to exclude, even if they have an included extension
+    pub(crate) excluded_patterns: Vec<String>,
     pub(crate) tasks: Vec<Task>,
+    /// Prefix of the temporary files in the scratchpad directory
+    pub(crate) temp_prefix: String,
+    /// Source files larger than this are refused when the workflow is run
+    pub(crate) max_source_size_bytes: Option<u64>,
+    /// Included extensions that are not warned about, even though they are not known media extensions
+    pub(crate) suppress_extension_warnings: Vec<String>,
+    /// Limits on the resources the tasks of the workflow may use
+    pub(crate) resource_limits: ResourceLimits,
+    /// Tasks only inspect the source file, so it is neither copied into the scratchpad nor replaced.
+    /// The tasks get the original file as input and must not modify it, omzet cannot prevent that.
+    pub(crate) read_only: bool,
+    /// How often copying the source file in and moving the result back is retried on transient errors
+    pub(crate) io_retries: u32,
+    /// Permission bits of the transformed file, e.g. `0o644`. Those of the source file when not set
+    pub(crate) output_file_mode: Option<u32>,
+    /// Directories the transformed file is hardlinked into as well, e.g. other libraries it belongs to
+    pub(crate) link_destinations: Vec<PathBuf>,
+    /// What to do when a link destination already contains a file with the same name
+    pub(crate) on_link_collision: LinkCollisionPolicy,
+    /// Skip files of which the content does not match the container their extension claims
+    pub(crate) verify_container: bool,
+    /// How the output of a task is compared with its input, to notice tasks that changed nothing
+    pub(crate) unchanged_output_check: UnchangedOutputCheck,
+    /// Amount of probes that run at the same time for a file, they only read the file
+    pub(crate) parallel_probes: NonZeroUsize,
+}
+
+/// Determines how a file that is in the way of linking a transformed file is handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LinkCollisionPolicy {
+    /// Leave the existing file, the transformed file is not linked there
+    #[default]
+    Skip,
+    /// Replace the existing file with the transformed file
+    Replace,
+}
+
+/// Determines how the output of a task is compared with its input. Output that is unchanged is
+/// discarded, and a source file that none of the tasks changed is not replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum UnchangedOutputCheck {
+    /// Outputs are never compared
+    #[default]
+    Off,
+    /// Output of the same size as the input is considered unchanged, without reading either
+    Size,
+    /// Only output of which every byte matches the input is unchanged
+    Content,
+}
+
+/// Hard limits on the resources of a task's process, enforced with a cgroup on Linux
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct ResourceLimits {
+    /// Share of a single CPU the task may use, e.g. 200 allows two full CPUs
+    pub(crate) cpu_quota_percent: Option<u32>,
+    pub(crate) memory_limit_bytes: Option<u64>,
+}
+
+impl ResourceLimits {
+    pub(crate) fn is_unlimited(&self) -> bool {
+        self.cpu_quota_percent.is_none() && self.memory_limit_bytes.is_none()
+    }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+impl Workflow {
+    /// Whether any of the workflow's tasks is a builtin task
+    pub(crate) fn uses_builtin_tasks(&self) -> bool {
+        self.tasks
+            .iter()
+            .any(|task| matches!(task, Task::Builtin(_)))
+    }
+
+    /// References to the workflow's builtin tasks, including the defaults of their parameters
+    pub(crate) fn builtin_references(&self) -> Vec<String> {
+        self.tasks
+            .iter()
+            .filter_map(|task| match task {
+                Task::Builtin(builtin_task) => Some(builtin_task.reference()),
+                Task::Custom(_) => None,
+            })
+            .collect()
+    }
+}
+
+/// A workflow for "mkv" files without any tasks, of which every other setting is the default.
+/// Tests change the settings they are about with struct update syntax.
+#[cfg(test)]
+pub(crate) fn test_workflow(name: &str, scratchpad_directory: PathBuf) -> Workflow {
+    Workflow {
+        name: name.to_owned(),
+        scratchpad_directory,
+        included_extensions: vec![String::from("mkv")],
+        excluded_patterns: vec![],
+        tasks: vec![],
+        temp_prefix: String::from("omzet-"),
+        max_source_size_bytes: None,
+        suppress_extension_warnings: vec![],
+        resource_limits: ResourceLimits::default(),
+        read_only: false,
+        io_retries: 3,
+        output_file_mode: None,
+        link_destinations: vec![],
+        on_link_collision: LinkCollisionPolicy::Skip,
+        verify_container: false,
+        unchanged_output_check: UnchangedOutputCheck::Off,
+        parallel_probes: NonZeroUsize::MIN,
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub(crate) enum Task {
     Custom(CustomTask),
     Builtin(BuiltinTask),
@@ -39,6 +314,88 @@ impl Task {
             Task::Builtin(builtin_task) => builtin_task.name(),
         }
     }
+
+    /// Estimate how long the task takes for a source file of the given size. Custom tasks are
+    /// estimated by their recorded durations, so they cannot be estimated without a connection.
+    pub(crate) fn estimated_duration_ms(
+        &self,
+        connection: Option<&Connection>,
+        source_file_size_bytes: u64,
+    ) -> Option<u64> {
+        match self {
+            Task::Custom(custom_task) => connection.and_then(|connection| {
+                db::mean_task_duration(connection, &custom_task.id)
+                    .inspect_err(|err| warn!("unable to estimate duration of task: {err}"))
+                    .ok()
+                    .flatten()
+            }),
+            Task::Builtin(builtin_task) => {
+                Some(builtin_task.estimated_duration_ms(source_file_size_bytes))
+            }
+        }
+    }
+
+    /// Whether the task applies to the given file, based on its extension
+    pub(crate) fn applies_to(&self, path: &Path) -> bool {
+        match self {
+            Task::Custom(custom_task) => custom_task.applies_to(path),
+            Task::Builtin(_) => true,
+        }
+    }
+
+    /// How long the result of this task's probe may be cached, if at all
+    pub(crate) fn probe_cache_ttl(&self) -> Option<Duration> {
+        match self {
+            Task::Custom(custom_task) => custom_task.probe_cache_ttl,
+            Task::Builtin(_) => None,
+        }
+    }
+
+    /// What should happen when the probe of this task aborts
+    pub(crate) fn probe_abort_policy(&self) -> ProbeAbortPolicy {
+        match self {
+            Task::Custom(custom_task) => custom_task.on_probe_abort,
+            Task::Builtin(_) => ProbeAbortPolicy::Abort,
+        }
+    }
+}
+
+/// Determines how an aborted probe (e.g. the probe could not be executed) is handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ProbeAbortPolicy {
+    /// Abort the entire workflow
+    #[default]
+    Abort,
+    /// Treat the probe as if it requested the task to run
+    Run,
+    /// Treat the probe as if it requested the task to be skipped
+    Skip,
+}
+
+/// Program that runs the container of a task
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ContainerRuntime {
+    #[default]
+    Docker,
+    Podman,
+}
+
+impl ContainerRuntime {
+    pub(crate) fn program(&self) -> &'static str {
+        match self {
+            ContainerRuntime::Docker => "docker",
+            ContainerRuntime::Podman => "podman",
+        }
+    }
+}
+
+/// A container image in which the command of a task runs, instead of directly on the host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TaskContainer {
+    pub(crate) image: String,
+    pub(crate) runtime: ContainerRuntime,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -47,15 +404,163 @@ pub(crate) struct CustomTask {
     pub(crate) id: String,
     /// A small description of what the task does
     pub(crate) description: String,
-    /// The probe is a CLI command to check if the command should be executed
+    /// The probe is a CLI command to check if the command should be executed.
+    /// Exiting with 0 runs the task, 1 skips it, 75 defers the job and any other code aborts it.
     pub(crate) probe: Option<Runnable>,
     /// The command is a CLI command to actually perform the task
     pub(crate) command: Runnable,
+    /// What to do when the probe aborts
+    pub(crate) on_probe_abort: ProbeAbortPolicy,
+    /// Extensions of files the task applies to. When absent the task applies to all files
+    pub(crate) applies_to_extensions: Option<Vec<String>>,
+    /// How long the result of the probe may be reused for an unchanged file.
+    /// When absent the probe runs every time.
+    pub(crate) probe_cache_ttl: Option<Duration>,
+    /// The probe is killed and aborted when it runs longer than this
+    pub(crate) probe_timeout: Option<Duration>,
+    /// Bytes of stdout and of stderr that are kept whole, of longer output only the start and end
+    /// are kept
+    pub(crate) output_capture_bytes: usize,
+    /// The command runs with `sh` inside this container when set. The probe still runs on the host.
+    pub(crate) container: Option<TaskContainer>,
+}
+
+/// Output of a task up to 64 KiB per stream is kept whole, which is plenty for most tasks
+pub(crate) const DEFAULT_OUTPUT_CAPTURE_BYTES: usize = 64 * 1024;
+
+/// A mistake in the definition of a custom task
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ValidationError {
+    #[error("the id must not be empty or contain whitespace")]
+    InvalidId,
+    #[error("the id must not start with \"builtin.\", which is reserved for builtin tasks")]
+    ReservedIdPrefix,
+    #[error("the description must not be empty")]
+    EmptyDescription,
+    #[error("the command must not be empty")]
+    EmptyCommand,
+    #[error("the probe must not be empty when it is set")]
+    EmptyProbe,
+    #[error("the container image must not be empty when it is set")]
+    EmptyContainerImage,
+    #[error("a command running in a container must not declare an interpreter, it is run with sh")]
+    InterpreterInContainer,
+}
+
+/// A script that can be executed, such as the probe or command of a task
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Runnable {
+    script: String,
+    references_omzet_variables: bool,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum InvalidRunnable {
+    #[error("the script is empty")]
+    Empty,
 }
 
-type Runnable = String;
+/// A script references a workflow variable that is not defined
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error("variable \"{0}\" is not defined")]
+pub(crate) struct UndefinedVariable(pub(crate) String);
+
+/// Names of workflow variables start with a lowercase letter, so `${OMZET_INPUT}` and other
+/// environment variables in uppercase are left for the shell
+fn is_variable_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+impl Runnable {
+    /// Create a runnable from a script. Surrounding whitespace is trimmed.
+    pub(crate) fn new(script: &str) -> Result<Self, InvalidRunnable> {
+        let script = script.trim();
+
+        if script.is_empty() {
+            return Err(InvalidRunnable::Empty);
+        }
+
+        Ok(Self {
+            script: script.to_owned(),
+            references_omzet_variables: script.contains("OMZET_"),
+        })
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        &self.script
+    }
+
+    /// Replace references like `${crf}` by the value of that workflow variable
+    pub(crate) fn substitute(
+        &self,
+        variables: &HashMap<String, String>,
+    ) -> Result<Self, UndefinedVariable> {
+        let mut script = String::with_capacity(self.script.len());
+        let mut rest = self.script.as_str();
+
+        while let Some(start) = rest.find("${") {
+            script.push_str(&rest[..start]);
+            let reference = &rest[start + 2..];
+
+            match reference
+                .find('}')
+                .map(|end| &reference[..end])
+                .filter(|name| is_variable_name(name))
+            {
+                Some(name) => {
+                    let value = variables
+                        .get(name)
+                        .ok_or_else(|| UndefinedVariable(name.to_owned()))?;
+                    script.push_str(value);
+                    rest = &reference[name.len() + 1..];
+                }
+                None => {
+                    script.push_str("${");
+                    rest = reference;
+                }
+            }
+        }
+        script.push_str(rest);
+
+        Ok(Self {
+            references_omzet_variables: script.contains("OMZET_"),
+            script,
+        })
+    }
+
+    /// Whether the script references any of the `OMZET_*` environment variables
+    pub(crate) fn references_omzet_variables(&self) -> bool {
+        self.references_omzet_variables
+    }
+
+    /// Render the script for the given interpreter.
+    /// A script that already starts with a shebang is left as is.
+    pub(crate) fn render(&self, interpreter: &str) -> String {
+        if self.script.starts_with("#!") {
+            return self.script.clone();
+        }
+
+        format!("#!{interpreter}\n{}", self.script)
+    }
+}
 
 impl CustomTask {
+    /// Substitute the workflow variables in the probe and the command
+    pub(crate) fn substitute_variables(
+        &mut self,
+        variables: &HashMap<String, String>,
+    ) -> Result<(), UndefinedVariable> {
+        if let Some(probe) = &self.probe {
+            self.probe = Some(probe.substitute(variables)?);
+        }
+        self.command = self.command.substitute(variables)?;
+
+        Ok(())
+    }
+
     pub(crate) fn new(
         id: String,
         description: String,
@@ -67,54 +572,169 @@ impl CustomTask {
             description,
             probe,
             command,
+            on_probe_abort: ProbeAbortPolicy::default(),
+            applies_to_extensions: None,
+            probe_cache_ttl: None,
+            probe_timeout: None,
+            output_capture_bytes: DEFAULT_OUTPUT_CAPTURE_BYTES,
+            container: None,
         }
     }
-}
 
-#[derive(Debug, PartialEq, Eq, Clone)]
-pub(crate) enum BuiltinTask {
-    TranscodeToH265,
-}
+    /// Check the task for mistakes, reporting all of them at once
+    pub(crate) fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = vec![];
 
-impl BuiltinTask {
-    fn name(&self) -> &str {
-        match self {
-            BuiltinTask::TranscodeToH265 => "transcode to h265 (builtin)",
+        if self.id.is_empty() || self.id.contains(char::is_whitespace) {
+            errors.push(ValidationError::InvalidId);
+        }
+        if self.id.starts_with("builtin.") {
+            errors.push(ValidationError::ReservedIdPrefix);
+        }
+        if self.description.trim().is_empty() {
+            errors.push(ValidationError::EmptyDescription);
+        }
+        if self.command.as_str().is_empty() {
+            errors.push(ValidationError::EmptyCommand);
+        }
+        if self
+            .probe
+            .as_ref()
+            .is_some_and(|probe| probe.as_str().is_empty())
+        {
+            errors.push(ValidationError::EmptyProbe);
+        }
+        if let Some(container) = &self.container {
+            if container.image.trim().is_empty() {
+                errors.push(ValidationError::EmptyContainerImage);
+            }
+            if self.command.as_str().starts_with("#!") {
+                errors.push(ValidationError::InterpreterInContainer);
+            }
         }
-    }
-}
 
-#[derive(thiserror::Error, Debug)]
-#[error("the referenced builtin task ({id}) does not exist")]
-pub(crate) struct UnknownBuiltinTask {
-    id: String,
-}
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
 
-impl TryFrom<&str> for BuiltinTask {
-    type Error = UnknownBuiltinTask;
+    /// Whether the task applies to the given file, based on its extension
+    pub(crate) fn applies_to(&self, path: &Path) -> bool {
+        let Some(extensions) = &self.applies_to_extensions else {
+            return true;
+        };
 
-    fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value {
-            "builtin.transcode_to_h265" => Ok(BuiltinTask::TranscodeToH265),
-            _ => Err(UnknownBuiltinTask {
-                id: String::from(value),
-            }),
-        }
+        path.extension()
+            .map(|extension| {
+                extensions
+                    .iter()
+                    .any(|allowed| extension.eq_ignore_ascii_case(allowed))
+            })
+            .unwrap_or(false)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use tempdir::TempDir;
 
     use super::*;
 
+    #[test]
+    fn workflow_variables_are_substituted() {
+        let variables = HashMap::from([(String::from("crf"), String::from("23"))]);
+        let runnable =
+            Runnable::new("ffmpeg -i \"$OMZET_INPUT\" -crf ${crf} \"${OMZET_OUTPUT}\"").unwrap();
+
+        assert_eq!(
+            "ffmpeg -i \"$OMZET_INPUT\" -crf 23 \"${OMZET_OUTPUT}\"",
+            runnable.substitute(&variables).unwrap().as_str()
+        );
+        assert_eq!(
+            Err(UndefinedVariable(String::from("preset"))),
+            Runnable::new("x265 --preset ${preset}")
+                .unwrap()
+                .substitute(&variables)
+        );
+    }
+
+    #[test]
+    fn library_warns_about_empty_directory() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+
+        let library = Library::new(
+            String::from("movies"),
+            test_workflow("movies", temp_test_dir.path().join("scratchpad")),
+            temp_test_dir.path().to_path_buf(),
+            SortStrategy::default(),
+            false,
+            false,
+            None,
+        );
+
+        let warnings = library.validate();
+        assert!(warnings.contains(&LibraryWarning::DirectoryEmpty));
+        #[cfg(unix)]
+        assert!(warnings.contains(&LibraryWarning::ScratchpadOnSameFilesystem));
+
+        fs::write(temp_test_dir.path().join("movie.mkv"), "movie").unwrap();
+        assert!(!library.validate().contains(&LibraryWarning::DirectoryEmpty));
+
+        let mut missing_library = library.clone();
+        missing_library.directory = temp_test_dir.path().join("missing");
+        assert!(missing_library
+            .validate()
+            .contains(&LibraryWarning::DirectoryUnreadable));
+    }
+
+    #[test]
+    fn library_warns_about_unusual_extensions() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+
+        let mut library = Library::new(
+            String::from("movies"),
+            Workflow {
+                included_extensions: vec![
+                    String::from("MKV"),
+                    String::from("mkvv"),
+                    String::from("iso"),
+                ],
+                suppress_extension_warnings: vec![String::from("ISO")],
+                ..test_workflow("movies", PathBuf::from("/tmp/omzet"))
+            },
+            temp_test_dir.path().to_path_buf(),
+            SortStrategy::default(),
+            false,
+            false,
+            None,
+        );
+
+        let unusual_extensions = |library: &Library| {
+            library
+                .validate()
+                .into_iter()
+                .filter(|warning| matches!(warning, LibraryWarning::UnusualExtension(_)))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec![LibraryWarning::UnusualExtension(String::from("mkvv"))],
+            unusual_extensions(&library)
+        );
+
+        library.workflow.suppress_extension_warnings.clear();
+        assert_eq!(2, unusual_extensions(&library).len());
+    }
+
     #[test]
     fn task_can_be_created() {
         let task = CustomTask::new(
             "test-task".to_owned(),
             "some description".to_owned(),
-            Some("echo probe".to_owned()),
-            "echo done".to_owned(),
+            Some(Runnable::new("echo probe").unwrap()),
+            Runnable::new("echo done").unwrap(),
         );
 
         assert_eq!("test-task", task.id.as_str());
@@ -123,4 +743,61 @@ mod tests {
         assert_eq!("echo probe", task.probe.unwrap().as_str());
         assert_eq!("echo done", task.command.as_str());
     }
+
+    #[test]
+    fn custom_task_reports_all_validation_errors() {
+        let valid_task = CustomTask::new(
+            "test-task".to_owned(),
+            "some description".to_owned(),
+            Some(Runnable::new("echo probe").unwrap()),
+            Runnable::new("echo done").unwrap(),
+        );
+        assert!(valid_task.validate().is_ok());
+
+        let empty_runnable = Runnable {
+            script: String::new(),
+            references_omzet_variables: false,
+        };
+
+        let mut task = valid_task.clone();
+        task.id = String::from("builtin.my task");
+        task.description = String::from("  ");
+        task.command = empty_runnable.clone();
+        task.probe = Some(empty_runnable);
+
+        assert_eq!(
+            Err(vec![
+                ValidationError::InvalidId,
+                ValidationError::ReservedIdPrefix,
+                ValidationError::EmptyDescription,
+                ValidationError::EmptyCommand,
+                ValidationError::EmptyProbe,
+            ]),
+            task.validate()
+        );
+
+        let mut task = valid_task;
+        task.id = String::new();
+        assert_eq!(Err(vec![ValidationError::InvalidId]), task.validate());
+    }
+
+    #[test]
+    fn runnable_is_validated_on_creation() {
+        assert!(matches!(
+            Runnable::new("  \n "),
+            Err(InvalidRunnable::Empty)
+        ));
+
+        let runnable = Runnable::new("\n  echo \"$OMZET_INPUT\"\n").unwrap();
+        assert_eq!("echo \"$OMZET_INPUT\"", runnable.as_str());
+        assert!(runnable.references_omzet_variables());
+        assert_eq!(
+            "#!/bin/sh\necho \"$OMZET_INPUT\"",
+            runnable.render("/bin/sh")
+        );
+
+        let runnable = Runnable::new("#!/bin/bash\nexit 0").unwrap();
+        assert!(!runnable.references_omzet_variables());
+        assert_eq!("#!/bin/bash\nexit 0", runnable.render("/bin/sh"));
+    }
 }
@@ -0,0 +1,440 @@
+//! Tasks that are implemented by omzet itself, instead of by a script in the configuration.
+//! Each builtin lives in its own module and is registered in [`BUILTINS`] under the id it is
+//! referenced by, e.g. `builtin.transcode_audio(codec=aac,bitrate=256k)`.
+//! The builtins use ffmpeg, without the "builtin-tasks" feature none are registered.
+
+#[cfg(feature = "builtin-tasks")]
+mod probe_interlaced;
+#[cfg(feature = "builtin-tasks")]
+mod transcode_audio;
+#[cfg(feature = "builtin-tasks")]
+mod transcode_to_h265;
+
+use std::{
+    fmt::Debug,
+    process::{Command, ExitStatus},
+    sync::Arc,
+};
+
+use crate::job_orchestration::TaskReport;
+
+use super::common::{ProbeResult, ProbeRunner, ProbingContext, TaskContext, TaskRunner};
+
+/// The builtin tasks that can be referenced, adding a builtin only requires registering it here
+const BUILTINS: &[Registration] = &[
+    #[cfg(feature = "builtin-tasks")]
+    transcode_to_h265::REGISTRATION,
+    #[cfg(feature = "builtin-tasks")]
+    transcode_audio::REGISTRATION,
+    #[cfg(feature = "builtin-tasks")]
+    probe_interlaced::REGISTRATION,
+];
+
+/// Implementation of a builtin task
+trait Builtin: ProbeRunner + TaskRunner + Debug + Send + Sync {
+    /// The parameters of the builtin, including the defaults of those that were not given
+    fn parameters(&self) -> Vec<(&'static str, String)>;
+}
+
+/// Makes a builtin available under its id
+#[derive(Debug)]
+struct Registration {
+    /// The id the builtin is referenced by, e.g. "builtin.transcode_to_h265"
+    id: &'static str,
+    /// Human readable name, used in logs
+    name: &'static str,
+    /// What the builtin does, shown by `omzet list-builtins`
+    description: &'static str,
+    /// The parameters the builtin accepts, in the order it reports them
+    parameters: &'static [ParameterDescription],
+    /// Bytes of the source file the task typically processes per second, to estimate how long it
+    /// takes. Absent for a task that takes no noticeable time, whatever the size of the file
+    estimated_bytes_per_second: Option<u64>,
+    create: fn(&mut Parameters) -> Result<Arc<dyn Builtin>, InvalidBuiltinTask>,
+}
+
+/// Describes a parameter a builtin accepts, its default is whatever the builtin reports without it
+#[derive(Debug)]
+pub(crate) struct ParameterDescription {
+    pub(crate) name: &'static str,
+    /// Type of the value, e.g. "string" or "number"
+    pub(crate) kind: &'static str,
+    pub(crate) description: &'static str,
+}
+
+/// A reference to a builtin task in a workflow
+#[derive(Debug, Clone)]
+pub(crate) struct BuiltinTask {
+    registration: &'static Registration,
+    builtin: Arc<dyn Builtin>,
+}
+
+impl BuiltinTask {
+    /// Every builtin task, with the default value for each of its parameters
+    pub(crate) fn all() -> Vec<BuiltinTask> {
+        BUILTINS
+            .iter()
+            .map(|registration| {
+                let builtin = (registration.create)(&mut Parameters {
+                    reference: registration.id,
+                    parameters: vec![],
+                })
+                .expect("builtins can be created without parameters");
+
+                Self {
+                    registration,
+                    builtin,
+                }
+            })
+            .collect()
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        self.registration.name
+    }
+
+    /// The id to reference the builtin by in the configuration
+    pub(crate) fn config_key(&self) -> &str {
+        self.registration.id
+    }
+
+    /// Reference to the builtin with all of its parameters, including those left to their default,
+    /// e.g. `builtin.transcode_audio(codec=aac,bitrate=256k)`
+    pub(crate) fn reference(&self) -> String {
+        let parameters = self.builtin.parameters();
+        if parameters.is_empty() {
+            return String::from(self.registration.id);
+        }
+
+        let parameters: Vec<String> = parameters
+            .into_iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect();
+        format!("{}({})", self.registration.id, parameters.join(","))
+    }
+
+    pub(crate) fn description(&self) -> &str {
+        self.registration.description
+    }
+
+    /// Rough estimate of how long the task takes for a source file of the given size
+    pub(crate) fn estimated_duration_ms(&self, source_file_size_bytes: u64) -> u64 {
+        self.registration
+            .estimated_bytes_per_second
+            .map_or(0, |bytes_per_second| {
+                source_file_size_bytes.saturating_mul(1000) / bytes_per_second
+            })
+    }
+
+    /// The parameters the builtin accepts, with the value they have for this task
+    pub(crate) fn parameters(&self) -> Vec<(&'static ParameterDescription, String)> {
+        self.registration
+            .parameters
+            .iter()
+            .zip(self.builtin.parameters())
+            .map(|(description, (_, value))| (description, value))
+            .collect()
+    }
+}
+
+impl PartialEq for BuiltinTask {
+    fn eq(&self, other: &Self) -> bool {
+        self.registration.id == other.registration.id
+            && self.builtin.parameters() == other.builtin.parameters()
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum InvalidBuiltinTask {
+    #[error("the referenced builtin task ({id}) does not exist")]
+    Unknown { id: String },
+    #[error("builtin task \"{id}\" has an invalid parameter \"{parameter}\"")]
+    InvalidParameter { id: String, parameter: String },
+}
+
+/// The parameters a builtin was referenced with, each is taken by the builtin that uses it
+struct Parameters<'a> {
+    /// Only used to report invalid parameters, which no builtin can have without the feature
+    #[cfg_attr(not(feature = "builtin-tasks"), allow(dead_code))]
+    reference: &'a str,
+    parameters: Vec<(&'a str, &'a str)>,
+}
+
+#[cfg(feature = "builtin-tasks")]
+impl<'a> Parameters<'a> {
+    /// Take the value of a parameter, if it was given
+    fn take(&mut self, key: &str) -> Option<&'a str> {
+        self.parameters
+            .iter()
+            .position(|(parameter, _)| *parameter == key)
+            .map(|position| self.parameters.remove(position).1)
+    }
+
+    fn take_or(&mut self, key: &str, default: &'a str) -> &'a str {
+        self.take(key).unwrap_or(default)
+    }
+
+    /// Error for a parameter with a value the builtin cannot use
+    fn invalid(&self, key: &str, value: &str) -> InvalidBuiltinTask {
+        InvalidBuiltinTask::InvalidParameter {
+            id: String::from(self.reference),
+            parameter: format!("{key}={value}"),
+        }
+    }
+}
+
+impl TryFrom<&str> for BuiltinTask {
+    type Error = InvalidBuiltinTask;
+
+    /// Look up a reference to a builtin task, parameters can be passed as
+    /// `builtin.transcode_audio(codec=aac,bitrate=256k)`
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let (id, parameters) = match value.split_once('(') {
+            Some((id, parameters)) => (
+                id,
+                parameters.strip_suffix(')').ok_or_else(|| {
+                    InvalidBuiltinTask::InvalidParameter {
+                        id: String::from(value),
+                        parameter: String::from(parameters),
+                    }
+                })?,
+            ),
+            None => (value, ""),
+        };
+
+        let parameters = parameters
+            .split(',')
+            .map(str::trim)
+            .filter(|parameter| !parameter.is_empty())
+            .map(|parameter| {
+                parameter
+                    .split_once('=')
+                    .map(|(key, value)| (key.trim(), value.trim()))
+                    .filter(|(_, value)| !value.is_empty())
+                    .ok_or_else(|| InvalidBuiltinTask::InvalidParameter {
+                        id: String::from(value),
+                        parameter: String::from(parameter),
+                    })
+            })
+            .collect::<Result<Vec<(&str, &str)>, InvalidBuiltinTask>>()?;
+
+        let registration = BUILTINS
+            .iter()
+            .find(|registration| registration.id == id)
+            .ok_or_else(|| InvalidBuiltinTask::Unknown {
+                id: String::from(value),
+            })?;
+
+        let mut parameters = Parameters {
+            reference: value,
+            parameters,
+        };
+        let builtin = (registration.create)(&mut parameters)?;
+
+        // every parameter should have been used by the builtin
+        if let Some((parameter, _)) = parameters.parameters.first() {
+            return Err(InvalidBuiltinTask::InvalidParameter {
+                id: String::from(value),
+                parameter: String::from(*parameter),
+            });
+        }
+
+        Ok(Self {
+            registration,
+            builtin,
+        })
+    }
+}
+
+impl ProbeRunner for BuiltinTask {
+    fn run_probe(&self, context: ProbingContext) -> ProbeResult {
+        self.builtin.run_probe(context)
+    }
+}
+
+impl TaskRunner for BuiltinTask {
+    fn run_task(&self, context: TaskContext) -> TaskReport {
+        self.builtin.run_task(context)
+    }
+}
+
+/// Run a prepared ffmpeg command and report its outcome
+#[cfg(feature = "builtin-tasks")]
+fn run_ffmpeg(
+    mut command: Command,
+    resource_limits: &crate::workflow::ResourceLimits,
+) -> TaskReport {
+    use std::process::Stdio;
+
+    use super::cgroup::prepare_cgroup;
+
+    tracing::debug!("running {command:?}");
+
+    let cgroup = prepare_cgroup(resource_limits);
+    if let Some(cgroup) = &cgroup {
+        cgroup.confine(&mut command);
+    }
+
+    let child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+
+    match child.and_then(|child| child.wait_with_output()) {
+        Ok(output) => TaskReport::try_from(output).unwrap_or_else(|err| {
+            TaskReport::failure(format!("ffmpeg output is not valid UTF-8: {err}"))
+        }),
+        Err(err) => TaskReport::failure(format!("unable to execute ffmpeg: {err}")),
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum FfmpegUnavailable {
+    #[error("unable to execute ffmpeg: {0}")]
+    CannotExecute(#[source] std::io::Error),
+    #[error("ffmpeg exited unexpectedly with {0}")]
+    UnexpectedExit(ExitStatus),
+}
+
+/// Verify that ffmpeg is usable, so builtin tasks do not fail on every file at runtime
+pub(crate) fn check_ffmpeg_available() -> Result<(), FfmpegUnavailable> {
+    let status = Command::new("ffmpeg")
+        .arg("-version")
+        .output()
+        .map_err(FfmpegUnavailable::CannotExecute)?
+        .status;
+
+    if !status.success() {
+        return Err(FfmpegUnavailable::UnexpectedExit(status));
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "builtin-tasks")]
+#[derive(thiserror::Error, Debug)]
+enum CodecError {
+    #[error(transparent)]
+    Ffmpeg(#[from] ez_ffmpeg::error::Error),
+    #[error("received unexpected stream for context")]
+    UnexpectedStream,
+    #[error("unknown error while getting codec")]
+    Unknown,
+}
+
+#[cfg(all(test, feature = "builtin-tasks"))]
+mod tests {
+    use super::*;
+
+    fn parameters_of(reference: &str) -> Vec<(&'static str, String)> {
+        BuiltinTask::try_from(reference)
+            .unwrap()
+            .builtin
+            .parameters()
+    }
+
+    #[test]
+    fn builtin_task_is_parsed_with_parameters() {
+        assert!(parameters_of("builtin.transcode_to_h265").is_empty());
+        assert_eq!(
+            vec![
+                ("codec", String::from("aac")),
+                ("bitrate", String::from("256k"))
+            ],
+            parameters_of("builtin.transcode_audio")
+        );
+        assert_eq!(
+            vec![
+                ("codec", String::from("eac3")),
+                ("bitrate", String::from("640k"))
+            ],
+            parameters_of("builtin.transcode_audio(codec=eac3, bitrate=640k)")
+        );
+        assert_eq!(
+            vec![("threshold", String::from("0.1"))],
+            parameters_of("builtin.probe_interlaced")
+        );
+        assert_eq!(
+            vec![("threshold", String::from("0.25"))],
+            parameters_of("builtin.probe_interlaced(threshold=0.25)")
+        );
+        assert_eq!(
+            BuiltinTask::try_from("builtin.transcode_audio").unwrap(),
+            BuiltinTask::try_from("builtin.transcode_audio(codec=aac)").unwrap()
+        );
+
+        assert!(matches!(
+            BuiltinTask::try_from("builtin.transcode_audio(volume=2)"),
+            Err(InvalidBuiltinTask::InvalidParameter { .. })
+        ));
+        assert!(matches!(
+            BuiltinTask::try_from("builtin.transcode_to_h265(codec=aac)"),
+            Err(InvalidBuiltinTask::InvalidParameter { .. })
+        ));
+        assert!(matches!(
+            BuiltinTask::try_from("builtin.transcode_audio(codec=aac"),
+            Err(InvalidBuiltinTask::InvalidParameter { .. })
+        ));
+        assert!(matches!(
+            BuiltinTask::try_from("builtin.probe_interlaced(threshold=2)"),
+            Err(InvalidBuiltinTask::InvalidParameter { .. })
+        ));
+        assert!(matches!(
+            BuiltinTask::try_from("builtin.shrink"),
+            Err(InvalidBuiltinTask::Unknown { .. })
+        ));
+    }
+
+    #[test]
+    fn builtin_reference_includes_default_parameters() {
+        let reference = BuiltinTask::try_from("builtin.transcode_audio(codec=eac3)")
+            .unwrap()
+            .reference();
+        assert_eq!(
+            "builtin.transcode_audio(codec=eac3,bitrate=256k)",
+            reference
+        );
+        assert_eq!(
+            BuiltinTask::try_from("builtin.transcode_audio(codec=eac3)").unwrap(),
+            BuiltinTask::try_from(reference.as_str()).unwrap()
+        );
+
+        assert_eq!(
+            "builtin.transcode_to_h265",
+            BuiltinTask::try_from("builtin.transcode_to_h265")
+                .unwrap()
+                .reference()
+        );
+    }
+
+    #[test]
+    fn builtin_parameters_are_described() {
+        for builtin_task in BuiltinTask::all() {
+            let described: Vec<&str> = builtin_task
+                .registration
+                .parameters
+                .iter()
+                .map(|parameter| parameter.name)
+                .collect();
+            let reported: Vec<&str> = builtin_task
+                .builtin
+                .parameters()
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect();
+
+            assert_eq!(described, reported, "{}", builtin_task.config_key());
+        }
+    }
+
+    #[test]
+    fn builtin_ids_are_unique() {
+        for (index, registration) in BUILTINS.iter().enumerate() {
+            assert!(registration.id.starts_with("builtin."));
+            assert!(!BUILTINS[index + 1..]
+                .iter()
+                .any(|other| other.id == registration.id));
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    path::Path,
+    process::{Command, ExitStatus},
+    sync::Arc,
+};
+
+use tracing::{debug, warn};
+
+use crate::job_orchestration::TaskReport;
+
+use super::{
+    Builtin, ParameterDescription, ProbeResult, ProbeRunner, ProbingContext, Registration,
+    TaskContext, TaskRunner,
+};
+
+pub(super) const REGISTRATION: Registration = Registration {
+    id: "builtin.probe_interlaced",
+    name: "probe interlaced (builtin)",
+    description: "Only runs the tasks after it for interlaced videos, detected by analysing the \
+        first seconds. Does not change the file itself.",
+    parameters: &[ParameterDescription {
+        name: "threshold",
+        kind: "number",
+        description: "share of the analysed frames that must be interlaced, between 0 and 1",
+    }],
+    // the analysis is done by the probe, the task itself does nothing
+    estimated_bytes_per_second: None,
+    create: |parameters| {
+        let threshold = match parameters.take("threshold") {
+            Some(threshold) => threshold
+                .parse::<f64>()
+                .ok()
+                .filter(|threshold| (0.0..=1.0).contains(threshold))
+                .ok_or_else(|| parameters.invalid("threshold", threshold))?,
+            None => 0.1,
+        };
+
+        Ok(Arc::new(ProbeInterlaced { threshold }))
+    },
+};
+
+/// Only runs when the video is interlaced, detected by analysing its first seconds with
+/// ffmpeg's "idet" filter. The task itself does not change the file.
+#[derive(Debug)]
+struct ProbeInterlaced {
+    /// Share of the analysed frames that must be interlaced, between 0 and 1
+    threshold: f64,
+}
+
+impl Builtin for ProbeInterlaced {
+    fn parameters(&self) -> Vec<(&'static str, String)> {
+        vec![("threshold", self.threshold.to_string())]
+    }
+}
+
+impl ProbeRunner for ProbeInterlaced {
+    fn run_probe(&self, context: ProbingContext) -> ProbeResult {
+        detect_interlaced_share(context.path)
+            .map(|share| {
+                debug!(
+                    "{:.1}% of the analysed frames are interlaced",
+                    share * 100.0
+                );
+                if share > self.threshold {
+                    ProbeResult::Run
+                } else {
+                    ProbeResult::Skip
+                }
+            })
+            .inspect_err(|err| warn!("unable to detect interlacing: {err}"))
+            .unwrap_or(ProbeResult::Abort)
+    }
+}
+
+impl TaskRunner for ProbeInterlaced {
+    // a probe only, the tasks after it do the actual work
+    fn run_task(&self, _context: TaskContext) -> TaskReport {
+        TaskReport::success()
+    }
+}
+
+/// Seconds of the video that are analysed to detect interlacing
+const INTERLACE_DETECTION_SECONDS: &str = "5";
+
+#[derive(thiserror::Error, Debug)]
+enum InterlaceDetectionError {
+    #[error("unable to execute ffmpeg: {0}")]
+    CannotExecute(#[source] std::io::Error),
+    #[error("ffmpeg exited with {0}")]
+    UnexpectedExit(ExitStatus),
+    #[error("ffmpeg did not report any analysed frames")]
+    NoFramesAnalysed,
+}
+
+/// Determine the share of the frames at the start of the video that is interlaced, by
+/// decoding them with ffmpeg's "idet" filter. This is slow compared to reading stream info.
+fn detect_interlaced_share(path: &Path) -> Result<f64, InterlaceDetectionError> {
+    let output = Command::new("ffmpeg")
+        .args([
+            "-hide_banner",
+            "-nostdin",
+            "-t",
+            INTERLACE_DETECTION_SECONDS,
+            "-i",
+        ])
+        .arg(path)
+        .args(["-map", "0:v:0", "-vf", "idet", "-f", "null", "-"])
+        .output()
+        .map_err(InterlaceDetectionError::CannotExecute)?;
+
+    if !output.status.success() {
+        return Err(InterlaceDetectionError::UnexpectedExit(output.status));
+    }
+
+    // the filter reports its statistics as ffmpeg's log output
+    parse_multi_frame_detection(&String::from_utf8_lossy(&output.stderr))
+        .ok_or(InterlaceDetectionError::NoFramesAnalysed)
+}
+
+/// Parse the share of interlaced frames from the "Multi frame detection" line of the idet filter,
+/// e.g. "Multi frame detection: TFF: 12 BFF: 0 Progressive: 100 Undetermined: 3"
+fn parse_multi_frame_detection(log: &str) -> Option<f64> {
+    let (_, statistics) = log
+        .lines()
+        .find_map(|line| line.split_once("Multi frame detection:"))?;
+
+    let mut interlaced = 0;
+    let mut total = 0;
+    let mut tokens = statistics.split_whitespace();
+
+    while let (Some(label), Some(count)) = (tokens.next(), tokens.next()) {
+        let count: u64 = count.parse().ok()?;
+        if label == "TFF:" || label == "BFF:" {
+            interlaced += count;
+        }
+        total += count;
+    }
+
+    (total > 0).then(|| interlaced as f64 / total as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interlaced_share_is_parsed_from_idet_statistics() {
+        let log = "\
+[Parsed_idet_0 @ 0x5581] Repeated Fields: Neither:   120 Top:     0 Bottom:     0
+[Parsed_idet_0 @ 0x5581] Single frame detection: TFF:    20 BFF:     0 Progressive:    90 Undetermined:    10
+[Parsed_idet_0 @ 0x5581] Multi frame detection: TFF:    25 BFF:     5 Progressive:    90 Undetermined:     0
+";
+        assert_eq!(Some(0.25), parse_multi_frame_detection(log));
+
+        assert_eq!(None, parse_multi_frame_detection("no statistics at all"));
+        assert_eq!(
+            None,
+            parse_multi_frame_detection(
+                "Multi frame detection: TFF: 0 BFF: 0 Progressive: 0 Undetermined: 0"
+            )
+        );
+    }
+}
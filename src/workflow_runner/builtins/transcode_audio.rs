@@ -0,0 +1,123 @@
+use std::{path::Path, process::Command, sync::Arc};
+
+use ez_ffmpeg::stream_info::{find_all_stream_infos, StreamInfo};
+
+use crate::job_orchestration::TaskReport;
+
+use super::{
+    run_ffmpeg, Builtin, CodecError, ParameterDescription, ProbeResult, ProbeRunner,
+    ProbingContext, Registration, TaskContext, TaskRunner,
+};
+
+pub(super) const REGISTRATION: Registration = Registration {
+    id: "builtin.transcode_audio",
+    name: "transcode audio (builtin)",
+    description: "Re-encode audio streams in codecs that many players do not support (DTS, \
+        TrueHD, PCM), copying all other streams. Skips files without such streams.",
+    parameters: &[
+        ParameterDescription {
+            name: "codec",
+            kind: "string",
+            description: "ffmpeg encoder to use, e.g. \"aac\" or \"eac3\"",
+        },
+        ParameterDescription {
+            name: "bitrate",
+            kind: "string",
+            description: "bitrate of the re-encoded streams, e.g. \"256k\"",
+        },
+    ],
+    // only the audio is encoded, the video is copied
+    estimated_bytes_per_second: Some(64 * 1024 * 1024),
+    create: |parameters| {
+        Ok(Arc::new(TranscodeAudio {
+            codec: String::from(parameters.take_or("codec", "aac")),
+            bitrate: String::from(parameters.take_or("bitrate", "256k")),
+        }))
+    },
+};
+
+/// Re-encode audio streams in codecs that many players do not support
+#[derive(Debug)]
+struct TranscodeAudio {
+    /// ffmpeg encoder to use, e.g. "aac" or "eac3"
+    codec: String,
+    /// Bitrate of the re-encoded streams, e.g. "256k"
+    bitrate: String,
+}
+
+impl Builtin for TranscodeAudio {
+    fn parameters(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("codec", self.codec.clone()),
+            ("bitrate", self.bitrate.clone()),
+        ]
+    }
+}
+
+impl ProbeRunner for TranscodeAudio {
+    fn run_probe(&self, context: ProbingContext) -> ProbeResult {
+        get_audio_streams_to_convert(context.path)
+            .map(|streams| {
+                if streams.is_empty() {
+                    ProbeResult::Skip
+                } else {
+                    ProbeResult::Run
+                }
+            })
+            .unwrap_or(ProbeResult::Abort)
+    }
+}
+
+impl TaskRunner for TranscodeAudio {
+    /// Re-encode the audio streams that need conversion. All other streams, the chapters and the
+    /// global metadata are copied as is, like the video transcode does.
+    fn run_task(&self, context: TaskContext) -> TaskReport {
+        let streams = match get_audio_streams_to_convert(context.input_path) {
+            Ok(streams) => streams,
+            Err(err) => {
+                return TaskReport::failure(format!("unable to inspect audio streams: {err}"))
+            }
+        };
+
+        let mut command = Command::new("ffmpeg");
+        command
+            .current_dir(context.directory)
+            .args(["-hide_banner", "-nostdin", "-y", "-i"])
+            .arg(context.input_path)
+            .args(["-map", "0", "-map_metadata", "0", "-map_chapters", "0"])
+            .args(["-c", "copy"]);
+
+        // all streams are mapped in order, so an input index is also the output index
+        for index in streams {
+            command
+                .arg(format!("-c:{index}"))
+                .arg(&self.codec)
+                .arg(format!("-b:{index}"))
+                .arg(&self.bitrate);
+        }
+
+        command.arg(context.output_path);
+
+        run_ffmpeg(command, context.resource_limits)
+    }
+}
+
+/// Audio codecs that many players cannot decode, so they are worth converting
+const AUDIO_CODECS_TO_CONVERT: [&str; 4] = ["dts", "truehd", "mlp", "pcm_bluray"];
+
+/// Get the indices of the audio streams in a codec that needs conversion
+fn get_audio_streams_to_convert(path: &Path) -> Result<Vec<i32>, CodecError> {
+    let streams = find_all_stream_infos(path.to_string_lossy())?
+        .into_iter()
+        .filter_map(|stream_info| match stream_info {
+            StreamInfo::Audio {
+                index, codec_name, ..
+            } => AUDIO_CODECS_TO_CONVERT
+                .contains(&codec_name.as_str())
+                .then_some(index),
+            _ => None,
+        })
+        .collect();
+
+    Ok(streams)
+}
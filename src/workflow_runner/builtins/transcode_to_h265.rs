@@ -0,0 +1,200 @@
+use std::{path::Path, process::Command, sync::Arc};
+
+use ez_ffmpeg::stream_info::{find_video_stream_info, StreamInfo};
+
+use crate::job_orchestration::TaskReport;
+
+use super::{
+    run_ffmpeg, Builtin, CodecError, ProbeResult, ProbeRunner, ProbingContext, Registration,
+    TaskContext, TaskRunner,
+};
+
+pub(super) const REGISTRATION: Registration = Registration {
+    id: "builtin.transcode_to_h265",
+    name: "transcode to h265 (builtin)",
+    description: "Re-encode the video to h265 (HEVC), copying all other streams, the chapters and \
+        the metadata. Skips files that are already h265.",
+    parameters: &[],
+    // encoding is the slow part, the duration grows with the bitrate and so with the file size
+    estimated_bytes_per_second: Some(4 * 1024 * 1024),
+    create: |_| Ok(Arc::new(TranscodeToH265)),
+};
+
+#[derive(Debug)]
+struct TranscodeToH265;
+
+impl Builtin for TranscodeToH265 {
+    fn parameters(&self) -> Vec<(&'static str, String)> {
+        vec![]
+    }
+}
+
+impl ProbeRunner for TranscodeToH265 {
+    fn run_probe(&self, context: ProbingContext) -> ProbeResult {
+        get_codec_name(context.path)
+            .map(|codec| match codec.as_str() {
+                "hevc" => ProbeResult::Skip,
+                _ => ProbeResult::Run,
+            })
+            .unwrap_or(ProbeResult::Abort)
+    }
+}
+
+impl TaskRunner for TranscodeToH265 {
+    /// Re-encode the video to HEVC. Every other stream, the chapters and the global metadata
+    /// are copied as is, so transcoding never loses any of them.
+    fn run_task(&self, context: TaskContext) -> TaskReport {
+        let mut command = Command::new("ffmpeg");
+        command
+            .current_dir(context.directory)
+            .args(["-hide_banner", "-nostdin", "-y", "-i"])
+            .arg(context.input_path)
+            .args(["-map", "0", "-map_metadata", "0", "-map_chapters", "0"])
+            // copy everything, except for actual video streams; "V" excludes attached pictures
+            .args(["-c", "copy", "-c:V", "libx265"])
+            .arg(context.output_path);
+
+        run_ffmpeg(command, context.resource_limits)
+    }
+}
+
+/// Get the human readable codec name
+fn get_codec_name(path: &Path) -> Result<String, CodecError> {
+    let result = find_video_stream_info(path.to_string_lossy())?;
+
+    match result {
+        Some(stream_info) => match stream_info {
+            StreamInfo::Video { codec_name, .. } => Ok(codec_name),
+            _ => Err(CodecError::UnexpectedStream),
+        },
+        None => Err(CodecError::Unknown),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use tempdir::TempDir;
+
+    use crate::{workflow::ResourceLimits, workflow_runner::check_ffmpeg_available};
+
+    use super::*;
+
+    fn ffprobe_entries(path: &Path, entries: &str) -> usize {
+        let output = Command::new("ffprobe")
+            .args(["-v", "error", "-show_entries", entries, "-of", "csv=p=0"])
+            .arg(path)
+            .output()
+            .unwrap();
+
+        String::from_utf8(output.stdout).unwrap().lines().count()
+    }
+
+    /// Generate a short video encoded with the given codec
+    #[cfg(feature = "integration-tests")]
+    fn generate_video_fixture(directory: &Path, file_name: &str, codec: &str) -> PathBuf {
+        let path = directory.join(file_name);
+
+        let status = Command::new("ffmpeg")
+            .args(["-v", "error", "-nostdin", "-y"])
+            .args(["-f", "lavfi", "-i", "testsrc=duration=1:size=64x64:rate=10"])
+            .args(["-c:v", codec])
+            .arg(&path)
+            .status()
+            .expect("ffmpeg is required for the integration tests");
+        assert!(status.success());
+
+        path
+    }
+
+    #[cfg(feature = "integration-tests")]
+    #[test]
+    fn probe_requests_transcode_for_h264_video() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let path = generate_video_fixture(temp_test_dir.path(), "h264.mkv", "libx264");
+
+        assert_eq!(
+            ProbeResult::Run,
+            TranscodeToH265.run_probe(ProbingContext::new(&path, temp_test_dir.path()))
+        );
+    }
+
+    #[cfg(feature = "integration-tests")]
+    #[test]
+    fn probe_skips_hevc_video() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let path = generate_video_fixture(temp_test_dir.path(), "hevc.mkv", "libx265");
+
+        assert_eq!(
+            ProbeResult::Skip,
+            TranscodeToH265.run_probe(ProbingContext::new(&path, temp_test_dir.path()))
+        );
+    }
+
+    #[cfg(feature = "integration-tests")]
+    #[test]
+    fn probe_aborts_for_non_video_file() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let path = temp_test_dir.path().join("notes.mkv");
+        fs::write(&path, "not a video").unwrap();
+
+        assert_eq!(
+            ProbeResult::Abort,
+            TranscodeToH265.run_probe(ProbingContext::new(&path, temp_test_dir.path()))
+        );
+    }
+
+    #[test]
+    fn transcode_preserves_streams_and_chapters() {
+        if check_ffmpeg_available().is_err() {
+            eprintln!("ffmpeg is not available, skipping test");
+            return;
+        }
+
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let directory = temp_test_dir.path();
+        let input_path = directory.join("input.mkv");
+        let output_path = directory.join("output.mkv");
+
+        fs::write(
+            directory.join("metadata.txt"),
+            ";FFMETADATA1\ntitle=omzet\n[CHAPTER]\nTIMEBASE=1/1000\nSTART=0\nEND=500\ntitle=first\n[CHAPTER]\nTIMEBASE=1/1000\nSTART=500\nEND=1000\ntitle=second\n",
+        )
+        .unwrap();
+        fs::write(
+            directory.join("subtitles.srt"),
+            "1\n00:00:00,000 --> 00:00:00,500\nhello\n",
+        )
+        .unwrap();
+
+        let status = Command::new("ffmpeg")
+            .current_dir(directory)
+            .args(["-v", "error", "-nostdin", "-y"])
+            .args(["-f", "lavfi", "-i", "testsrc=duration=1:size=64x64:rate=10"])
+            .args(["-f", "lavfi", "-i", "sine=duration=1"])
+            .args(["-i", "subtitles.srt", "-i", "metadata.txt"])
+            .args(["-map", "0", "-map", "1", "-map", "2"])
+            .args(["-map_metadata", "3", "-map_chapters", "3"])
+            .args(["-c:v", "libx264", "-c:a", "aac", "-c:s", "srt"])
+            .arg(&input_path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let report = TranscodeToH265.run_task(TaskContext::new(
+            &input_path,
+            &output_path,
+            &PathBuf::from(directory),
+            &ResourceLimits::default(),
+        ));
+        assert!(report.succeeded(), "{}", report.stderr());
+
+        assert_eq!(
+            ffprobe_entries(&input_path, "stream=index"),
+            ffprobe_entries(&output_path, "stream=index")
+        );
+        assert_eq!(2, ffprobe_entries(&output_path, "chapter=id"));
+        assert_eq!("hevc", get_codec_name(&output_path).unwrap());
+    }
+}
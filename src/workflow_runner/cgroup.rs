@@ -0,0 +1,235 @@
+//! Enforces the resource limits of a workflow by placing the processes of its tasks in a
+//! cgroup (v2). This only works on Linux, when omzet is allowed to create cgroups below its own,
+//! e.g. when running as a systemd service with `Delegate=yes`.
+
+#[cfg(target_os = "linux")]
+use std::sync::{Mutex, PoisonError};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use tracing::{debug, warn};
+
+use crate::workflow::ResourceLimits;
+
+/// Mount point of the unified cgroup (v2) hierarchy
+#[cfg(target_os = "linux")]
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Name of the leaf cgroup omzet moves itself into, next to the cgroups of the tasks
+#[cfg(target_os = "linux")]
+const OWN_CGROUP_NAME: &str = "omzet";
+
+/// The cgroup in which the cgroups of the tasks are created, once omzet has moved out of it
+#[cfg(target_os = "linux")]
+static DELEGATED_CGROUP: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Period in which the CPU quota is enforced, in microseconds
+const CPU_PERIOD_MICROS: u64 = 100_000;
+
+/// Smallest quota the kernel accepts, in microseconds
+const MIN_CPU_QUOTA_MICROS: u64 = 1_000;
+
+#[derive(Debug, thiserror::Error)]
+pub(super) enum CgroupError {
+    #[cfg(not(target_os = "linux"))]
+    #[error("cgroups are only supported on Linux")]
+    UnsupportedPlatform,
+    #[error("unable to determine the cgroup (v2) of omzet: {0}")]
+    UnknownCurrentCgroup(#[source] io::Error),
+    #[error("unable to set up \"{}\": {source}", .path.display())]
+    CannotSetUp { path: PathBuf, source: io::Error },
+}
+
+/// A cgroup created for a single task, it is removed again when dropped
+pub(super) struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    /// Create a cgroup that enforces the limits, next to the leaf cgroup omzet runs in
+    #[cfg(target_os = "linux")]
+    fn create(limits: &ResourceLimits) -> Result<Self, CgroupError> {
+        let parent = delegated_cgroup()?;
+
+        let mut controllers = vec![];
+        if limits.cpu_quota_percent.is_some() {
+            controllers.push("+cpu");
+        }
+        if limits.memory_limit_bytes.is_some() {
+            controllers.push("+memory");
+        }
+        write(
+            &parent.join("cgroup.subtree_control"),
+            &controllers.join(" "),
+        )?;
+
+        let path = parent.join(format!("omzet-{}", uuid::Uuid::new_v4()));
+        fs::create_dir(&path).map_err(|source| CgroupError::CannotSetUp {
+            path: path.clone(),
+            source,
+        })?;
+
+        // from here on the cgroup is removed again, also when setting a limit fails
+        let cgroup = Self { path };
+
+        if let Some(percent) = limits.cpu_quota_percent {
+            write(&cgroup.path.join("cpu.max"), &cpu_max(percent))?;
+        }
+        if let Some(bytes) = limits.memory_limit_bytes {
+            write(&cgroup.path.join("memory.max"), &bytes.to_string())?;
+        }
+
+        Ok(cgroup)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn create(_limits: &ResourceLimits) -> Result<Self, CgroupError> {
+        Err(CgroupError::UnsupportedPlatform)
+    }
+
+    /// Let the process of the command join the cgroup after it is forked, before it executes the
+    /// program. Every process it starts is limited as well. A process that cannot join is not
+    /// started, as its limits would not apply.
+    #[cfg(target_os = "linux")]
+    pub(super) fn confine(&self, command: &mut Command) {
+        use std::{
+            ffi::CString,
+            os::unix::{ffi::OsStrExt, process::CommandExt},
+        };
+
+        let procs = CString::new(self.path.join("cgroup.procs").as_os_str().as_bytes())
+            .expect("paths do not contain NUL bytes");
+
+        // between forking and executing only async-signal-safe functions may be called, nothing
+        // allocates here
+        let join = move || {
+            let fd = unsafe { libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // writing "0" moves the process that writes it
+            let written = unsafe { libc::write(fd, b"0".as_ptr().cast(), 1) };
+            let error = io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+
+            if written < 0 {
+                return Err(error);
+            }
+            Ok(())
+        };
+
+        unsafe { command.pre_exec(join) };
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub(super) fn confine(&self, _command: &mut Command) {}
+}
+
+/// The cgroup omzet was started in, below which the cgroups of the tasks are created. cgroup v2
+/// only enables controllers for the children of a cgroup without processes of its own, so omzet
+/// first moves itself into a leaf cgroup. This happens once, later tasks reuse the cgroup.
+#[cfg(target_os = "linux")]
+fn delegated_cgroup() -> Result<PathBuf, CgroupError> {
+    let mut delegated = DELEGATED_CGROUP
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Some(parent) = delegated.as_ref() {
+        return Ok(parent.clone());
+    }
+
+    let contents =
+        fs::read_to_string("/proc/self/cgroup").map_err(CgroupError::UnknownCurrentCgroup)?;
+    let current = parse_cgroup_path(&contents).ok_or_else(|| {
+        CgroupError::UnknownCurrentCgroup(io::Error::new(
+            io::ErrorKind::NotFound,
+            "omzet is not part of a cgroup v2 hierarchy",
+        ))
+    })?;
+    let parent = Path::new(CGROUP_ROOT).join(current.trim_start_matches('/'));
+
+    // left behind by an earlier run of omzet in the same cgroup
+    let own = parent.join(OWN_CGROUP_NAME);
+    match fs::create_dir(&own) {
+        Err(source) if source.kind() != io::ErrorKind::AlreadyExists => {
+            return Err(CgroupError::CannotSetUp { path: own, source });
+        }
+        _ => {}
+    }
+    // moves every thread of omzet, including those running other jobs
+    write(&own.join("cgroup.procs"), &std::process::id().to_string())?;
+
+    debug!("moved omzet into cgroup {}", own.display());
+    *delegated = Some(parent.clone());
+
+    Ok(parent)
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        // fails while processes started by the task still linger, those keep the cgroup alive
+        if let Err(err) = fs::remove_dir(&self.path) {
+            debug!("unable to remove cgroup {}: {err}", self.path.display());
+        }
+    }
+}
+
+/// Set up a cgroup for the limits, before the process of a task is spawned.
+/// Nothing is set up without limits, or when they cannot be enforced, which is warned about.
+pub(super) fn prepare_cgroup(limits: &ResourceLimits) -> Option<Cgroup> {
+    if limits.is_unlimited() {
+        return None;
+    }
+
+    Cgroup::create(limits)
+        .inspect_err(|err| warn!("unable to enforce resource limits, running without them: {err}"))
+        .ok()
+}
+
+fn write(path: &Path, contents: &str) -> Result<(), CgroupError> {
+    fs::write(path, contents).map_err(|source| CgroupError::CannotSetUp {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// The path of the cgroup (v2) in the contents of "/proc/<pid>/cgroup"
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_cgroup_path(contents: &str) -> Option<&str> {
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(str::trim)
+}
+
+/// Value for "cpu.max" that allows the percentage of a single CPU
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn cpu_max(percent: u32) -> String {
+    let quota = (u64::from(percent) * CPU_PERIOD_MICROS / 100).max(MIN_CPU_QUOTA_MICROS);
+
+    format!("{quota} {CPU_PERIOD_MICROS}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_quota_is_relative_to_a_single_cpu() {
+        assert_eq!("200000 100000", cpu_max(200));
+        assert_eq!("50000 100000", cpu_max(50));
+        assert_eq!("1000 100000", cpu_max(0));
+    }
+
+    #[test]
+    fn cgroup_v2_path_is_parsed() {
+        let contents = "12:cpuset:/\n0::/system.slice/omzet.service\n";
+        assert_eq!(
+            Some("/system.slice/omzet.service"),
+            parse_cgroup_path(contents)
+        );
+        assert_eq!(None, parse_cgroup_path("12:cpuset:/\n"));
+    }
+}
@@ -1,13 +1,15 @@
 use std::path::Path;
 
-use crate::job_orchestration::TaskReport;
+use crate::{job_orchestration::TaskReport, workflow::ResourceLimits};
 
 /// Blocks or allows running of a task
-#[derive(Debug, PartialEq)]
-pub(super) enum ProbeResult {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ProbeResult {
     Run,
     Skip,
     Abort,
+    /// The file is not ready for the task yet, the whole job is retried later
+    Defer,
 }
 
 pub(super) trait ProbeRunner {
@@ -35,14 +37,21 @@ pub(super) struct TaskContext<'a> {
     pub(super) input_path: &'a Path,
     pub(super) output_path: &'a Path,
     pub(super) directory: &'a Path,
+    pub(super) resource_limits: &'a ResourceLimits,
 }
 
 impl<'a> TaskContext<'a> {
-    pub(super) fn new(input_path: &'a Path, output_path: &'a Path, directory: &'a Path) -> Self {
+    pub(super) fn new(
+        input_path: &'a Path,
+        output_path: &'a Path,
+        directory: &'a Path,
+        resource_limits: &'a ResourceLimits,
+    ) -> Self {
         Self {
             input_path,
             output_path,
             directory,
+            resource_limits,
         }
     }
 }
@@ -0,0 +1,128 @@
+//! Recognizing the container of a media file by its first bytes, to notice files of which the
+//! extension does not match their content, e.g. an error page saved as ".mkv" by a failed download.
+
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+/// Amount of bytes at the start of a file that is needed to recognize every container
+const HEADER_SIZE: usize = 12;
+
+/// The containers that can be recognized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Container {
+    /// Matroska and WebM
+    Matroska,
+    /// MP4 and QuickTime
+    Mp4,
+    Avi,
+    Wave,
+    MpegTransportStream,
+    MpegProgramStream,
+    Flv,
+    Ogg,
+    Flac,
+    Mp3,
+    Asf,
+}
+
+impl Container {
+    /// The container a file with this extension is expected to be, unknown extensions are absent
+    pub(super) fn for_extension(extension: &str) -> Option<Self> {
+        let container = match extension.to_ascii_lowercase().as_str() {
+            "mkv" | "mka" | "mks" | "webm" => Container::Matroska,
+            "mp4" | "m4v" | "m4a" | "mov" | "3gp" => Container::Mp4,
+            "avi" => Container::Avi,
+            "wav" => Container::Wave,
+            "ts" | "m2ts" | "mts" => Container::MpegTransportStream,
+            "mpg" | "mpeg" | "vob" => Container::MpegProgramStream,
+            "flv" => Container::Flv,
+            "ogg" | "ogv" | "oga" | "opus" => Container::Ogg,
+            "flac" => Container::Flac,
+            "mp3" => Container::Mp3,
+            "wmv" | "wma" | "asf" => Container::Asf,
+            _ => return None,
+        };
+
+        Some(container)
+    }
+
+    /// Recognize the container by the first bytes of a file
+    pub(super) fn detect(header: &[u8]) -> Option<Self> {
+        let container = match header {
+            [0x1A, 0x45, 0xDF, 0xA3, ..] => Container::Matroska,
+            [_, _, _, _, b'f', b't', b'y', b'p', ..]
+            | [_, _, _, _, b'm', b'o', b'o', b'v', ..]
+            | [_, _, _, _, b'm', b'd', b'a', b't', ..]
+            | [_, _, _, _, b'w', b'i', b'd', b'e', ..]
+            | [_, _, _, _, b'f', b'r', b'e', b'e', ..] => Container::Mp4,
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'A', b'V', b'I', b' ', ..] => Container::Avi,
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Container::Wave,
+            // M2TS prefixes every packet of a transport stream with a 4 byte timestamp
+            [0x47, ..] | [_, _, _, _, 0x47, ..] => Container::MpegTransportStream,
+            [0x00, 0x00, 0x01, 0xBA, ..] => Container::MpegProgramStream,
+            [b'F', b'L', b'V', ..] => Container::Flv,
+            [b'O', b'g', b'g', b'S', ..] => Container::Ogg,
+            [b'f', b'L', b'a', b'C', ..] => Container::Flac,
+            [b'I', b'D', b'3', ..] => Container::Mp3,
+            [0xFF, second, ..] if second & 0xE0 == 0xE0 => Container::Mp3,
+            [0x30, 0x26, 0xB2, 0x75, ..] => Container::Asf,
+            _ => return None,
+        };
+
+        Some(container)
+    }
+}
+
+/// Whether the content of the file does not match the container its extension claims.
+/// Files with an extension of which the container is unknown never mismatch.
+pub(super) fn is_mislabeled(path: &Path) -> io::Result<bool> {
+    let Some(expected) = path
+        .extension()
+        .and_then(|extension| Container::for_extension(&extension.to_string_lossy()))
+    else {
+        return Ok(false);
+    };
+
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    File::open(path)?
+        .take(HEADER_SIZE as u64)
+        .read_to_end(&mut header)?;
+
+    Ok(Container::detect(&header) != Some(expected))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn file_is_mislabeled_when_content_does_not_match_extension() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let directory = temp_test_dir.path();
+
+        fs::write(
+            directory.join("movie.mkv"),
+            [0x1A, 0x45, 0xDF, 0xA3, 0x9F, 0x42, 0x86, 0x81],
+        )
+        .unwrap();
+        fs::write(directory.join("clip.mp4"), b"\0\0\0\x20ftypisom\0\0\x02\0").unwrap();
+        fs::write(
+            directory.join("download.mkv"),
+            "<!DOCTYPE html><html>Not Found</html>",
+        )
+        .unwrap();
+        fs::write(directory.join("notes.txt"), "anything").unwrap();
+
+        assert!(!is_mislabeled(&directory.join("movie.mkv")).unwrap());
+        assert!(!is_mislabeled(&directory.join("clip.mp4")).unwrap());
+        assert!(is_mislabeled(&directory.join("download.mkv")).unwrap());
+        assert!(!is_mislabeled(&directory.join("notes.txt")).unwrap());
+    }
+}
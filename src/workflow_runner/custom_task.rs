@@ -1,15 +1,23 @@
 use std::{
-    collections::HashMap,
-    io::{BufRead, BufReader},
-    path::{Path, PathBuf},
+    collections::{HashMap, VecDeque},
+    io::{BufRead, BufReader, Read, Write},
+    path::Path,
+    process::{Child, Command, ExitStatus, Stdio},
+    thread::{self, sleep},
+    time::{Duration, Instant},
 };
 
-use run_script::ScriptOptions;
-use tracing::debug;
+use tracing::{debug, warn};
 
-use crate::{job_orchestration::TaskReport, workflow::CustomTask};
+use crate::{
+    job_orchestration::TaskReport,
+    workflow::{CustomTask, ResourceLimits, Runnable, TaskContainer},
+};
 
-use super::common::{ProbeResult, ProbeRunner, ProbingContext, TaskRunner};
+use super::{
+    cgroup::prepare_cgroup,
+    common::{ProbeResult, ProbeRunner, ProbingContext, TaskContext, TaskRunner},
+};
 
 impl ProbeRunner for CustomTask {
     fn run_probe(&self, context: ProbingContext) -> ProbeResult {
@@ -20,7 +28,7 @@ impl ProbeRunner for CustomTask {
         };
 
         match run_script(
-            probe.as_str(),
+            probe,
             HashMap::from([
                 (
                     "OMZET_INPUT".to_owned(),
@@ -29,21 +37,46 @@ impl ProbeRunner for CustomTask {
                 ("OMZET_TASK".to_owned(), self.id.to_owned()),
             ]),
             context.directory,
+            self.probe_timeout,
+            // probes only inspect the file, so they are not worth limiting
+            &ResourceLimits::default(),
+            self.output_capture_bytes,
         ) {
-            Ok((exit_code, ..)) => match exit_code {
-                0 => ProbeResult::Run,
-                _ => ProbeResult::Skip,
-            },
-            Err(_) => ProbeResult::Abort,
+            Ok((Some(exit_code), ..)) => probe_result_of(exit_code),
+            // e.g. killed because it ran out of memory
+            Ok((None, ..)) => {
+                warn!("probe of task \"{}\" was killed by a signal", self.id);
+                ProbeResult::Abort
+            }
+            Err(err @ ScriptError::TimedOut(_)) => {
+                warn!("probe of task \"{}\" {err}, it was killed", self.id);
+                ProbeResult::Abort
+            }
+            Err(err @ ScriptError::CannotStart(_)) => {
+                warn!("probe of task \"{}\" {err}", self.id);
+                ProbeResult::Abort
+            }
         }
     }
 }
 
+/// Exit code of a probe that requests its task to be skipped
+const PROBE_EXIT_SKIP: i32 = 1;
+/// Exit code of a probe that requests the job to be deferred, EX_TEMPFAIL of sysexits.h
+const PROBE_EXIT_DEFER: i32 = 75;
+
+/// Interpret the exit code of a probe. Any code without a meaning means the probe itself failed.
+fn probe_result_of(exit_code: i32) -> ProbeResult {
+    match exit_code {
+        0 => ProbeResult::Run,
+        PROBE_EXIT_SKIP => ProbeResult::Skip,
+        PROBE_EXIT_DEFER => ProbeResult::Defer,
+        _ => ProbeResult::Abort,
+    }
+}
+
 impl TaskRunner for CustomTask {
-    fn run_task(
-        &self,
-        context: super::common::TaskContext,
-    ) -> crate::job_orchestration::TaskReport {
+    fn run_task(&self, context: TaskContext) -> TaskReport {
         let env_vars: HashMap<String, String> = HashMap::from([
             (
                 "OMZET_INPUT".to_owned(),
@@ -55,31 +88,208 @@ impl TaskRunner for CustomTask {
             ),
         ]);
 
-        let result = run_script(&self.command, env_vars, context.directory)
-            .expect("failed to run task script"); // @todo use error type
+        let result = match &self.container {
+            Some(container) => run_in_container(
+                container,
+                &self.command,
+                env_vars,
+                context,
+                self.output_capture_bytes,
+            ),
+            None => run_script(
+                &self.command,
+                env_vars,
+                context.directory,
+                None,
+                context.resource_limits,
+                self.output_capture_bytes,
+            ),
+        };
 
-        TaskReport::new(Some(result.0), result.1, result.2)
+        // a container runtime that fails, e.g. because the image cannot be pulled, exits with a
+        // code of its own and its error on stderr, which makes for a failed task like any other
+        match result {
+            Ok((exit_code, stdout, stderr)) => TaskReport::new(exit_code, stdout.text, stderr.text)
+                .with_omitted_output(stdout.omitted_bytes + stderr.omitted_bytes),
+            Err(err) => TaskReport::failure(format!("task \"{}\" {err}", self.id)),
+        }
     }
 }
 
+/// Interpreter used to run scripts that do not declare one themselves
+const DEFAULT_INTERPRETER: &str = "/bin/sh";
+
+#[derive(Debug, thiserror::Error)]
+enum ScriptError {
+    #[error("timed out after {} seconds", .0.as_secs())]
+    TimedOut(Duration),
+    #[error("could not be started: {0}")]
+    CannotStart(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
 /// Run a script. For example a task's command or probe.
+/// When it does not exit within the timeout, the script is killed.
+/// Of each output stream up to `capture_bytes` are kept, see [`CapturedOutput`].
 fn run_script(
-    script: &str,
+    script: &Runnable,
     env_vars: HashMap<String, String>,
     working_directory: &Path,
-) -> Result<(i32, String, String), String> {
-    let mut options = ScriptOptions::new();
+    timeout: Option<Duration>,
+    resource_limits: &ResourceLimits,
+    capture_bytes: usize,
+) -> Result<(Option<i32>, CapturedOutput, CapturedOutput), ScriptError> {
+    let cannot_start = |err: std::io::Error| ScriptError::CannotStart(err.into());
 
-    options.exit_on_error = true;
-    options.print_commands = true;
-    options.env_vars = Some(env_vars);
-    options.working_directory = Some(PathBuf::from(working_directory));
+    // the file is removed once the script has exited
+    let mut script_file = tempfile::Builder::new()
+        .prefix("omzet-script-")
+        .tempfile()
+        .map_err(cannot_start)?;
+    script_file
+        .write_all(script.render(DEFAULT_INTERPRETER).as_bytes())
+        .map_err(cannot_start)?;
 
-    let _args = Vec::new();
+    let mut command = script_command(script, script_file.path());
+    command
+        .envs(env_vars)
+        .current_dir(working_directory)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    // the processes the script starts can be killed together with it, see [`kill_script`]
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
 
-    let mut child = run_script::spawn(script, &_args, &options)
-        .expect("failed to spawn child when running script");
+    // the cgroup lives until the script has exited
+    let cgroup = prepare_cgroup(resource_limits);
+    if let Some(cgroup) = &cgroup {
+        cgroup.confine(&mut command);
+    }
 
+    let child = command.spawn().map_err(cannot_start)?;
+
+    collect_output(child, timeout, capture_bytes)
+}
+
+/// The command that runs the script file with the interpreter its shebang declares. A script
+/// without one runs with the default interpreter, which stops at the first command that fails
+/// and writes each command to stderr before running it.
+fn script_command(script: &Runnable, script_file: &Path) -> Command {
+    let Some(shebang) = script
+        .as_str()
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix("#!"))
+    else {
+        let mut command = Command::new(DEFAULT_INTERPRETER);
+        command.args(["-e", "-x"]).arg(script_file);
+        return command;
+    };
+
+    // like the kernel does, everything after the interpreter is passed as a single argument
+    let shebang = shebang.trim();
+    let (interpreter, argument) = match shebang.split_once(char::is_whitespace) {
+        Some((interpreter, argument)) => (interpreter, Some(argument.trim())),
+        None => (shebang, None),
+    };
+
+    let mut command = Command::new(interpreter);
+    command.args(argument).arg(script_file);
+    command
+}
+
+/// Run a script with `sh` inside a container, see [`container_command`]
+fn run_in_container(
+    container: &TaskContainer,
+    script: &Runnable,
+    env_vars: HashMap<String, String>,
+    context: TaskContext,
+    capture_bytes: usize,
+) -> Result<(Option<i32>, CapturedOutput, CapturedOutput), ScriptError> {
+    let child = container_command(container, script, &env_vars, context)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| ScriptError::CannotStart(err.into()))?;
+
+    collect_output(child, None, capture_bytes)
+}
+
+/// Build the command that runs the script inside a container of which the runtime removes it
+/// afterwards. The working directory and the directories of the input and output are mounted at
+/// the same path, so the paths in the environment are valid inside the container too. The resource
+/// limits are enforced by the runtime, a cgroup would only confine its client.
+fn container_command(
+    container: &TaskContainer,
+    script: &Runnable,
+    env_vars: &HashMap<String, String>,
+    context: TaskContext,
+) -> Command {
+    let mut command = Command::new(container.runtime.program());
+    command.args(["run", "--rm"]);
+
+    let mut directories = vec![context.directory];
+    for parent in [context.input_path, context.output_path]
+        .into_iter()
+        .filter_map(Path::parent)
+    {
+        if !directories
+            .iter()
+            .any(|directory| parent.starts_with(directory))
+        {
+            directories.push(parent);
+        }
+    }
+    for directory in directories {
+        let mut volume = directory.as_os_str().to_owned();
+        volume.push(":");
+        volume.push(directory);
+        command.arg("--volume").arg(volume);
+    }
+    command.arg("--workdir").arg(context.directory);
+
+    // files written in the container are owned by the user running omzet, not by root
+    #[cfg(target_os = "linux")]
+    {
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        command.arg("--user").arg(format!("{uid}:{gid}"));
+    }
+
+    // only the names are passed as arguments, the runtime takes the values from its environment
+    let mut names: Vec<&String> = env_vars.keys().collect();
+    names.sort();
+    for name in names {
+        command.arg("--env").arg(name).env(name, &env_vars[name]);
+    }
+
+    let limits = context.resource_limits;
+    if let Some(percent) = limits.cpu_quota_percent {
+        command
+            .arg("--cpus")
+            .arg(format!("{:.2}", f64::from(percent) / 100.0));
+    }
+    if let Some(bytes) = limits.memory_limit_bytes {
+        command.arg("--memory").arg(bytes.to_string());
+    }
+
+    // the entrypoint is replaced, images of tools often have the tool itself as their entrypoint
+    command
+        .args(["--entrypoint", "sh"])
+        .arg(&container.image)
+        .arg("-c")
+        .arg(script.as_str());
+
+    command
+}
+
+/// Capture the output of a spawned script and wait for it to exit, killing it after the timeout.
+/// The exit code is absent when the script was terminated by a signal, e.g. by the OOM killer.
+fn collect_output(
+    mut child: Child,
+    timeout: Option<Duration>,
+    capture_bytes: usize,
+) -> Result<(Option<i32>, CapturedOutput, CapturedOutput), ScriptError> {
     let child_stdout = child
         .stdout
         .take()
@@ -90,30 +300,325 @@ fn run_script(
         .take()
         .expect("failed to get stderr of child process");
 
-    let mut stdout_reader = BufReader::new(child_stdout);
-    let mut stderr_reader = BufReader::new(child_stderr);
+    // both streams are read while waiting, so a script filling either pipe does not block
+    let stdout_reader = thread::spawn(move || read_lines(child_stdout, "stdout", capture_bytes));
+    let stderr_reader = thread::spawn(move || read_lines(child_stderr, "stderr", capture_bytes));
 
-    let mut stdout_lines = String::new();
-    let mut stderr_lines = String::new();
+    let result = match wait_with_timeout(&mut child, timeout) {
+        Some(result) => result,
+        None => {
+            kill_script(&mut child);
+            if wait_with_timeout(&mut child, Some(KILL_GRACE_PERIOD)).is_none() {
+                warn!("script did not exit after it was killed, leaving it behind");
+            }
+            // waiting only gives up when there is a timeout
+            return Err(ScriptError::TimedOut(timeout.unwrap_or_default()));
+        }
+    };
+
+    let stdout_lines = stdout_reader.join().unwrap_or_default();
+    let stderr_lines = stderr_reader.join().unwrap_or_default();
+
+    Ok((result.code(), stdout_lines, stderr_lines))
+}
+
+/// Kill the script together with the processes it started, so none of them keeps the pipes open
+/// and the readers finish. A script spawned in a process group of its own is its leader.
+fn kill_script(child: &mut Child) {
+    #[cfg(target_os = "linux")]
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    let _ = child.kill();
+}
+
+/// Time a killed script gets to exit. A process stuck in the kernel may not exit even when killed,
+/// which must not block the runner forever.
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Wait for the child to exit, giving up once the timeout has passed. Without a timeout it waits
+/// until the child exits.
+fn wait_with_timeout(child: &mut Child, timeout: Option<Duration>) -> Option<ExitStatus> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+    loop {
+        if let Some(status) = child.try_wait().expect("failed to wait for child") {
+            return Some(status);
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return None;
+        }
+
+        sleep(Duration::from_millis(50));
+    }
+}
+
+/// Output of a script. When it is longer than the capture limit, only the lines at its start and end
+/// are kept, each up to half of the limit, separated by a line noting what was left out.
+#[derive(Debug, Default)]
+struct CapturedOutput {
+    text: String,
+    /// Bytes of output between the start and end that were not kept
+    omitted_bytes: usize,
+}
+
+/// Read all lines of a stream of the child, logging each line and capturing up to `capture_bytes`
+fn read_lines(stream: impl Read, stream_name: &str, capture_bytes: usize) -> CapturedOutput {
+    let mut reader = BufReader::new(stream);
+    let head_bytes = capture_bytes / 2;
+    let tail_bytes = capture_bytes - head_bytes;
+
+    let mut head = String::new();
+    let mut tail: VecDeque<String> = VecDeque::new();
+    let mut tail_length = 0;
+    let mut omitted_bytes = 0;
     let mut current_line = String::new();
 
-    while stdout_reader.read_line(&mut current_line).unwrap_or(0) > 0 {
-        debug!("stdout: {}", current_line.trim_end());
-        stdout_lines.push_str(&current_line);
+    while reader.read_line(&mut current_line).unwrap_or(0) > 0 {
+        debug!("{stream_name}: {}", current_line.trim_end());
+
+        if tail.is_empty() && head.len() + current_line.len() <= head_bytes {
+            head.push_str(&current_line);
+        } else {
+            tail_length += current_line.len();
+            tail.push_back(current_line.clone());
+
+            while tail_length > tail_bytes {
+                let Some(line) = tail.pop_front() else {
+                    break;
+                };
+                tail_length -= line.len();
+                omitted_bytes += line.len();
+            }
+        }
+
         current_line.clear();
     }
 
-    while stderr_reader.read_line(&mut current_line).unwrap_or(0) > 0 {
-        debug!("stderr: {}", current_line.trim_end());
-        stderr_lines.push_str(&current_line);
-        current_line.clear();
+    let mut text = head;
+    if omitted_bytes > 0 {
+        text.push_str(&format!("[... {omitted_bytes} bytes omitted ...]\n"));
+    }
+    text.extend(tail);
+
+    CapturedOutput {
+        text,
+        omitted_bytes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::workflow::ContainerRuntime;
+
+    #[test]
+    fn probe_exceeding_timeout_is_aborted() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+
+        let mut task = CustomTask::new(
+            "slow-task".to_owned(),
+            "has a slow probe".to_owned(),
+            Some(Runnable::new("sleep 5").unwrap()),
+            Runnable::new("echo done").unwrap(),
+        );
+        task.probe_timeout = Some(Duration::from_millis(200));
+
+        let started_at = Instant::now();
+        let result = task.run_probe(ProbingContext::new(
+            &temp_test_dir.path().join("movie.mkv"),
+            temp_test_dir.path(),
+        ));
+
+        assert_eq!(ProbeResult::Abort, result);
+        assert!(started_at.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn processes_started_by_a_timed_out_probe_are_killed() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let pid_file = temp_test_dir.path().join("pid");
+
+        let mut task = CustomTask::new(
+            "slow-task".to_owned(),
+            "starts a slow process".to_owned(),
+            Some(
+                Runnable::new(&format!(
+                    "sleep 30 & echo $! > {}; wait",
+                    pid_file.display()
+                ))
+                .unwrap(),
+            ),
+            Runnable::new("echo done").unwrap(),
+        );
+        task.probe_timeout = Some(Duration::from_millis(200));
+
+        let started_at = Instant::now();
+        let result = task.run_probe(ProbingContext::new(
+            &temp_test_dir.path().join("movie.mkv"),
+            temp_test_dir.path(),
+        ));
+
+        assert_eq!(ProbeResult::Abort, result);
+        assert!(started_at.elapsed() < Duration::from_secs(5));
+
+        // the signal may not have been handled yet, a killed process not yet reaped is a zombie
+        let pid = std::fs::read_to_string(pid_file).unwrap();
+        let has_exited = || {
+            std::fs::read_to_string(format!("/proc/{}/stat", pid.trim()))
+                .map_or(true, |stat| stat.contains(") Z "))
+        };
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while !has_exited() && Instant::now() < deadline {
+            sleep(Duration::from_millis(10));
+        }
+        assert!(has_exited());
+    }
+
+    #[test]
+    fn waiting_only_gives_up_with_a_timeout() {
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "sleep 0.2; exit 3"])
+            .spawn()
+            .unwrap();
+        assert_eq!(
+            Some(3),
+            wait_with_timeout(&mut child, None).and_then(|status| status.code())
+        );
+
+        let mut child = std::process::Command::new("sleep")
+            .arg("5")
+            .spawn()
+            .unwrap();
+        assert!(wait_with_timeout(&mut child, Some(Duration::from_millis(100))).is_none());
+
+        child.kill().unwrap();
+        assert!(wait_with_timeout(&mut child, Some(KILL_GRACE_PERIOD)).is_some());
+    }
+
+    #[test]
+    fn probe_exit_codes_are_interpreted() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+
+        for (exit_code, expected) in [
+            (0, ProbeResult::Run),
+            (1, ProbeResult::Skip),
+            (75, ProbeResult::Defer),
+            (2, ProbeResult::Abort),
+            (127, ProbeResult::Abort),
+        ] {
+            let task = CustomTask::new(
+                "probed-task".to_owned(),
+                "has a probe".to_owned(),
+                Some(Runnable::new(&format!("exit {exit_code}")).unwrap()),
+                Runnable::new("echo done").unwrap(),
+            );
+
+            let result = task.run_probe(ProbingContext::new(
+                &temp_test_dir.path().join("movie.mkv"),
+                temp_test_dir.path(),
+            ));
+
+            assert_eq!(expected, result, "exit code {exit_code}");
+        }
+
+        let killed_task = CustomTask::new(
+            "probed-task".to_owned(),
+            "has a probe that is killed".to_owned(),
+            Some(Runnable::new("kill -9 $$").unwrap()),
+            Runnable::new("echo done").unwrap(),
+        );
+        let result = killed_task.run_probe(ProbingContext::new(
+            &temp_test_dir.path().join("movie.mkv"),
+            temp_test_dir.path(),
+        ));
+        assert_eq!(ProbeResult::Abort, result);
     }
 
-    let result = child.wait().expect("failed to wait for child");
+    #[test]
+    fn container_mounts_the_directories_of_the_files() {
+        let container = TaskContainer {
+            image: "ffmpeg:latest".to_owned(),
+            runtime: ContainerRuntime::Podman,
+        };
+        let script = Runnable::new("ffmpeg -i \"$OMZET_INPUT\" \"$OMZET_OUTPUT\"").unwrap();
+        let env_vars = HashMap::from([
+            ("OMZET_OUTPUT".to_owned(), "/scratch/out.mkv".to_owned()),
+            ("OMZET_INPUT".to_owned(), "/library/movie.mkv".to_owned()),
+        ]);
+        let resource_limits = ResourceLimits {
+            cpu_quota_percent: Some(150),
+            memory_limit_bytes: None,
+        };
 
-    Ok((
-        result.code().expect("child was terminal by a signal"),
-        stdout_lines,
-        stderr_lines,
-    ))
+        let command = container_command(
+            &container,
+            &script,
+            &env_vars,
+            TaskContext::new(
+                Path::new("/library/movie.mkv"),
+                Path::new("/scratch/out.mkv"),
+                Path::new("/scratch"),
+                &resource_limits,
+            ),
+        );
+        let mut args: Vec<_> = command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        // the user differs per machine
+        if let Some(position) = args.iter().position(|arg| arg == "--user") {
+            args.drain(position..position + 2);
+        }
+
+        assert_eq!("podman", command.get_program());
+        assert_eq!(
+            vec![
+                "run",
+                "--rm",
+                "--volume",
+                "/scratch:/scratch",
+                "--volume",
+                "/library:/library",
+                "--workdir",
+                "/scratch",
+                "--env",
+                "OMZET_INPUT",
+                "--env",
+                "OMZET_OUTPUT",
+                "--cpus",
+                "1.50",
+                "--entrypoint",
+                "sh",
+                "ffmpeg:latest",
+                "-c",
+                script.as_str(),
+            ],
+            args
+        );
+        assert!(command
+            .get_envs()
+            .any(|(name, value)| name == "OMZET_INPUT"
+                && value == Some("/library/movie.mkv".as_ref())));
+    }
+
+    #[test]
+    fn long_output_is_captured_as_head_and_tail() {
+        let output = "line 1\nline 2\n";
+        let captured = read_lines(output.as_bytes(), "stdout", 64);
+        assert_eq!(output, captured.text);
+        assert_eq!(0, captured.omitted_bytes);
+
+        let output: String = (1..=20).map(|line| format!("line {line:02}\n")).collect();
+        let captured = read_lines(output.as_bytes(), "stdout", 32);
+        assert_eq!(
+            "line 01\nline 02\n[... 128 bytes omitted ...]\nline 19\nline 20\n",
+            captured.text
+        );
+        assert_eq!(128, captured.omitted_bytes);
+    }
 }
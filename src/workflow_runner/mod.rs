@@ -1,9 +1,16 @@
-mod builtin_task;
+mod builtins;
+mod cgroup;
 mod common;
+mod container;
 mod custom_task;
 mod runner;
 mod task;
 mod util;
 
+pub(crate) use builtins::{
+    check_ffmpeg_available, BuiltinTask, FfmpegUnavailable, InvalidBuiltinTask,
+};
+pub(crate) use common::ProbeResult;
 pub(crate) use runner::Runner;
 pub(crate) use runner::RunnerError;
+pub(crate) use runner::WorkflowRunner;
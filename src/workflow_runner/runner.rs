@@ -1,29 +1,99 @@
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
 use std::{
-    fs,
+    fs::{self, File},
+    io::{self, BufRead, BufReader},
+    num::NonZeroUsize,
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, PoisonError,
+    },
+    thread,
+    time::Instant,
 };
 
-use tracing::{debug, info, warn};
+use rusqlite::Connection;
+use tracing::{debug, error, info, info_span, warn, Span};
+use uuid::Uuid;
 
 use crate::{
-    job_orchestration::{TaskReport, WorkflowReport},
-    workflow::Task,
-    workflow_runner::util::{generate_output_file_name, generate_target_file},
+    db,
+    fingerprint::fingerprint_file,
+    job_orchestration::{SkipReason, TaskReport, WorkflowReport},
+    workflow::{LinkCollisionPolicy, ProbeAbortPolicy, ResourceLimits, Task, UnchangedOutputCheck},
+    workflow_runner::util::{
+        generate_output_file_name, generate_target_file, replace_atomically, retry_transient,
+        FileNameError, IO_RETRY_BASE_DELAY,
+    },
     Workflow,
 };
 
-use super::common::{ProbeResult, ProbeRunner, ProbingContext, TaskContext, TaskRunner};
+use super::{
+    common::{ProbeResult, ProbeRunner, ProbingContext, TaskContext, TaskRunner},
+    container,
+};
 
 #[derive(thiserror::Error, Debug)]
 pub(crate) enum RunnerError {
-    #[error(transparent)]
-    PreparationFailed(#[from] PreparationError),
+    #[error("unable to prepare run for \"{}\": {source}", .file.display())]
+    PreparationFailed {
+        file: PathBuf,
+        source: PreparationError,
+    },
+
+    #[error("probe of task \"{task}\" was aborted for \"{}\"", .file.display())]
+    ProbeAborted { task: String, file: PathBuf },
+
+    #[error("probe of task \"{task}\" deferred \"{}\", it is not ready yet", .file.display())]
+    ProbeDeferred { task: String, file: PathBuf },
+
+    #[error("task \"{task}\" failed for \"{}\" with exit code {exit_code}", .file.display())]
+    TaskFailed {
+        task: String,
+        file: PathBuf,
+        exit_code: i32,
+    },
+
+    #[error("task \"{task}\" was killed by a signal for \"{}\"", .file.display())]
+    TaskKilled { task: String, file: PathBuf },
+
+    #[error("unable to set up the tasks for \"{}\": {source}", .file.display())]
+    TaskSetupFailed { file: PathBuf, source: TaskError },
+
+    #[error("unable to complete run for \"{}\": {source}", .file.display())]
+    CompletionFailed {
+        file: PathBuf,
+        source: CompletionError,
+    },
+}
+
+impl RunnerError {
+    /// Whether the job should be run again later instead of counting as failed
+    pub(crate) fn is_deferral(&self) -> bool {
+        matches!(self, RunnerError::ProbeDeferred { .. })
+    }
 
-    #[error("a task probe was aborted")]
-    ProbeAborted,
+    /// Whether the source file no longer exists, e.g. because it was moved after it was scanned
+    pub(crate) fn is_source_file_disappeared(&self) -> bool {
+        matches!(
+            self,
+            RunnerError::PreparationFailed {
+                source: PreparationError::SourceFileDisappeared(_),
+                ..
+            }
+        )
+    }
 
-    #[error(transparent)]
-    CompletionFailed(#[from] CompletionError),
+    /// Description of the task that failed, when the run failed because of a task
+    pub(crate) fn failed_task(&self) -> Option<&str> {
+        match self {
+            RunnerError::TaskFailed { task, .. } | RunnerError::TaskKilled { task, .. } => {
+                Some(task)
+            }
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -32,6 +102,23 @@ pub(crate) enum PreparationError {
     UnableToCreateScratchpad(#[source] std::io::Error),
     #[error("unable to copy in source file: {0}")]
     UnableToCopySourceFile(#[source] std::io::Error),
+    #[error("unable to read source file: {0}")]
+    UnableToReadSourceFile(#[source] std::io::Error),
+    #[error("source file \"{}\" no longer exists", .0.display())]
+    SourceFileDisappeared(PathBuf),
+    #[error("source file of {size} bytes exceeds the workflow's limit of {limit} bytes")]
+    FileTooLarge { size: u64, limit: u64 },
+    #[error("unable to name the files in the scratchpad: {0}")]
+    InvalidFileName(#[source] FileNameError),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum TaskError {
+    #[error("unable to determine the absolute path of \"{}\": {source}", .path.display())]
+    CannotCanonicalize {
+        path: PathBuf,
+        source: std::io::Error,
+    },
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -45,87 +132,368 @@ struct Context {
     scratchpad_directory: PathBuf,
     /// Path to the original source file
     source_file_path: PathBuf,
+    /// Size of the original source file
+    source_file_size_bytes: u64,
     /// Path to the file each task should use as input
     input_file: PathBuf,
     /// Path where each task should output
     output_file: PathBuf,
+    /// Limits on the resources of each task
+    resource_limits: ResourceLimits,
+    /// The input file is the source file itself, which must never be replaced
+    read_only: bool,
+    /// Retries of copying the source file in and moving the result back on transient errors
+    io_retries: u32,
+    unchanged_output_check: UnchangedOutputCheck,
+    /// Permissions the transformed file gets once it replaces the source file
+    output_permissions: fs::Permissions,
 }
 
-pub(crate) struct Runner {}
+/// Runs a workflow for a single file.
+/// Allows the orchestration to be tested without actually transforming files.
+pub(crate) trait WorkflowRunner: Send + Sync {
+    /// Will synchronously run the workflow's tasks
+    /// and produce a [`WorkflowReport`]
+    fn run_workflow(
+        &self,
+        workflow: &Workflow,
+        source_file: PathBuf,
+    ) -> Result<WorkflowReport, RunnerError>;
+}
+
+pub(crate) struct Runner {
+    /// Connection used to cache probe results and to estimate durations, caching is disabled
+    /// without one
+    probe_cache: Option<Mutex<Connection>>,
+    /// Directory in which the output of each task is written to a log file, per job
+    task_log_directory: Option<PathBuf>,
+}
 
 impl Runner {
     pub(crate) fn new() -> Self {
-        Self {}
+        Self {
+            probe_cache: None,
+            task_log_directory: None,
+        }
+    }
+
+    /// Create a runner that caches the results of cacheable probes in the DB
+    pub(crate) fn with_probe_cache(connection: Connection) -> Self {
+        Self {
+            probe_cache: Some(Mutex::new(connection)),
+            task_log_directory: None,
+        }
+    }
+
+    /// Write the full output of each task to "<directory>/<job>/<task>.log"
+    pub(crate) fn with_task_logs(mut self, directory: PathBuf) -> Self {
+        self.task_log_directory = Some(directory);
+        self
     }
 }
 
 /// The public interface of the the default runner
-impl Runner {
-    /// Will synchronously run the workflow's tasks
-    /// and produce a [`WorkflowReport`]
-    pub(crate) fn run_workflow(
+impl WorkflowRunner for Runner {
+    fn run_workflow(
         &self,
         workflow: &Workflow,
         source_file: PathBuf,
     ) -> Result<WorkflowReport, RunnerError> {
-        info!("starting workflow: {}", &workflow.name);
+        // every log line of this job is attributable to its file and workflow
+        let _job_span = info_span!(
+            "job",
+            file = %source_file.to_string_lossy(),
+            workflow = %workflow.name
+        )
+        .entered();
 
-        let context = self.prepare(Path::new(&workflow.scratchpad_directory), &source_file)?;
+        info!("starting workflow: {}", &workflow.name);
 
-        info!("running probes to determine tasks");
+        let started_at = Instant::now();
 
-        let tasks_to_run = self.probe_tasks(&workflow.tasks, &context)?;
+        if workflow.tasks.is_empty() {
+            info!("skipping file: {}", SkipReason::NoTasksConfigured);
+            return Ok(WorkflowReport::new(
+                workflow.clone(),
+                SkipReason::NoTasksConfigured,
+            ));
+        }
 
-        if tasks_to_run.is_empty() {
-            info!("no probes requested to run");
-            return Ok(WorkflowReport::new(workflow.clone()));
+        // checked before the file is copied into the scratchpad, which is wasted on a mislabeled file
+        if workflow.verify_container {
+            match container::is_mislabeled(&source_file) {
+                Ok(true) => {
+                    warn!("skipping file: {}", SkipReason::MislabeledContainer);
+                    return Ok(WorkflowReport::new(
+                        workflow.clone(),
+                        SkipReason::MislabeledContainer,
+                    ));
+                }
+                Ok(false) => {}
+                Err(err) => warn!("unable to verify the container of the file: {err}"),
+            }
         }
 
-        info!("running {} tasks", tasks_to_run.len());
+        let context = self.prepare(workflow, &source_file).map_err(|source| {
+            RunnerError::PreparationFailed {
+                file: source_file.clone(),
+                source,
+            }
+        })?;
 
-        let task_reports = self.run_tasks(tasks_to_run, &context)?;
+        let result = self.run_prepared(workflow, &context, started_at);
 
-        self.complete_run(&context)?;
+        // whatever the outcome, the files in the scratchpad are not needed anymore, a retry
+        // copies the source file again
+        discard_scratchpad_files(&context);
 
-        Ok(WorkflowReport::new_with_reports(
-            workflow.clone(),
-            task_reports,
-        ))
+        result
     }
+}
 
-    /// Probe each task to see if it needs to run for the file
-    fn probe_tasks<'a>(
+impl Runner {
+    /// Probe and run the tasks of a workflow for a file that has been copied into the scratchpad,
+    /// then replace the source file with the result
+    fn run_prepared(
         &self,
-        tasks: &'a [Task],
+        workflow: &Workflow,
         context: &Context,
-    ) -> Result<Vec<&'a Task>, RunnerError> {
-        let probing_context =
-            ProbingContext::new(&context.input_file, &context.scratchpad_directory);
+        started_at: Instant,
+    ) -> Result<WorkflowReport, RunnerError> {
+        let source_file = &context.source_file_path;
+
+        info!("running probes to determine tasks");
 
-        let probe_results: Vec<(&Task, ProbeResult)> = tasks
+        let probed_tasks = self.probe_tasks(
+            &workflow.tasks,
+            workflow.parallel_probes,
+            ProbingContext::new(&context.input_file, &context.scratchpad_directory),
+            &context.source_file_path,
+        )?;
+        let skipped_tasks = probed_tasks
+            .skipped
             .iter()
-            .map(|task| (task, task.run_probe(probing_context)))
+            .map(|(task, probe_result)| (task.description().to_owned(), *probe_result))
             .collect();
 
-        let has_aborted_probe_result = probe_results
+        if probed_tasks.to_run.is_empty() {
+            info!("skipping file: {}", SkipReason::AllProbesSkipped);
+            return Ok(
+                WorkflowReport::new(workflow.clone(), SkipReason::AllProbesSkipped)
+                    .with_skipped_tasks(skipped_tasks),
+            );
+        }
+
+        info!(
+            "running {} tasks, skipping {}",
+            probed_tasks.to_run.len(),
+            probed_tasks.skipped.len()
+        );
+
+        let estimated_duration_ms =
+            self.estimate_duration(&probed_tasks.to_run, context.source_file_size_bytes);
+        if let Some(estimated_duration_ms) = estimated_duration_ms {
+            info!(
+                "estimated to take about {:.0} s",
+                estimated_duration_ms as f64 / 1000.0
+            );
+        }
+
+        let task_reports = self.run_tasks(probed_tasks.to_run, context)?;
+
+        let mut report = WorkflowReport::new_with_reports(workflow.clone(), task_reports)
+            .with_skipped_tasks(skipped_tasks)
+            .with_estimated_duration(estimated_duration_ms)
+            .with_timing(context.source_file_size_bytes, started_at.elapsed());
+
+        if context.read_only {
+            debug!("workflow is read-only, leaving the source file as is");
+        } else if is_unchanged(
+            context.unchanged_output_check,
+            &context.input_file,
+            &context.source_file_path,
+        ) {
+            info!("the tasks did not change the file, leaving the source file as is");
+            report = report.with_unchanged_source_file();
+        } else {
+            self.complete_run(context)
+                .map_err(|source| RunnerError::CompletionFailed {
+                    file: source_file.clone(),
+                    source,
+                })?;
+            link_into_destinations(source_file, workflow);
+
+            if let Ok(metadata) = fs::metadata(source_file) {
+                report = report.with_output_size(metadata.len());
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// The tasks of a workflow, divided by whether they should run for a file
+pub(crate) struct ProbedTasks<'a> {
+    pub(crate) to_run: Vec<&'a Task>,
+    /// Tasks that do not run, with the result of their probe. An aborted probe can also skip a task
+    pub(crate) skipped: Vec<(&'a Task, ProbeResult)>,
+}
+
+impl Runner {
+    /// Determine which tasks of the workflow would run for the file, without copying it or running
+    /// any task. Unlike when the workflow runs, the probes see the path of the original file and
+    /// run in its directory, so a probe must not modify the file it inspects.
+    pub(crate) fn plan<'a>(
+        &self,
+        workflow: &'a Workflow,
+        source_file: &Path,
+    ) -> Result<ProbedTasks<'a>, RunnerError> {
+        let directory = source_file.parent().unwrap_or(Path::new("/"));
+
+        self.probe_tasks(
+            &workflow.tasks,
+            workflow.parallel_probes,
+            ProbingContext::new(source_file, directory),
+            source_file,
+        )
+    }
+}
+
+/// Logic related to determining and running the tasks
+impl Runner {
+    /// Estimate how long the tasks take together, only when every one of them can be estimated
+    fn estimate_duration(&self, tasks: &[&Task], source_file_size_bytes: u64) -> Option<u64> {
+        let connection = self
+            .probe_cache
+            .as_ref()
+            .map(|connection| connection.lock().unwrap_or_else(PoisonError::into_inner));
+
+        tasks
             .iter()
-            .any(|(_, probe_result)| *probe_result == ProbeResult::Abort);
+            .map(|task| task.estimated_duration_ms(connection.as_deref(), source_file_size_bytes))
+            .sum()
+    }
+
+    /// Probe each task to see if it needs to run for the file. Up to `parallel_probes` probes run
+    /// at the same time, their results are still handled in the order of the tasks.
+    fn probe_tasks<'a>(
+        &self,
+        tasks: &'a [Task],
+        parallel_probes: NonZeroUsize,
+        probing_context: ProbingContext,
+        source_file_path: &Path,
+    ) -> Result<ProbedTasks<'a>, RunnerError> {
+        // only fingerprint the file when there is a probe that can use the cache
+        let fingerprint = self
+            .probe_cache
+            .as_ref()
+            .filter(|_| tasks.iter().any(|task| task.probe_cache_ttl().is_some()))
+            .and_then(|_| {
+                fingerprint_file(probing_context.path)
+                    .inspect_err(|err| {
+                        warn!("unable to fingerprint file, not caching probes: {err}")
+                    })
+                    .ok()
+            });
+
+        // probes that run on another thread are still logged as part of this file's job
+        let parent_span = Span::current();
+
+        let probe_results: Vec<(&Task, ProbeResult)> =
+            map_concurrently(tasks, parallel_probes.get(), |task| {
+                let _parent_span = parent_span.enter();
+                let _task_span = info_span!("task", id = task.description()).entered();
+
+                // a task that does not apply to the file does not need its probe to run
+                if !task.applies_to(probing_context.path) {
+                    debug!(
+                        "task \"{}\" does not apply to this file's extension, skipping",
+                        task.description()
+                    );
+                    return (task, ProbeResult::Skip);
+                }
+
+                (
+                    task,
+                    self.run_probe(task, probing_context, fingerprint.as_deref()),
+                )
+            });
+
+        let mut probed_tasks = ProbedTasks {
+            to_run: vec![],
+            skipped: vec![],
+        };
+
+        for (task, probe_result) in probe_results {
+            let resolved = match probe_result {
+                ProbeResult::Abort => resolve_aborted_probe(task),
+                _ => probe_result,
+            };
 
-        if has_aborted_probe_result {
-            return Err(RunnerError::ProbeAborted);
+            match resolved {
+                ProbeResult::Run => probed_tasks.to_run.push(task),
+                ProbeResult::Skip => probed_tasks.skipped.push((task, probe_result)),
+                ProbeResult::Abort => {
+                    return Err(RunnerError::ProbeAborted {
+                        task: task.description().to_owned(),
+                        file: source_file_path.to_path_buf(),
+                    })
+                }
+                ProbeResult::Defer => {
+                    return Err(RunnerError::ProbeDeferred {
+                        task: task.description().to_owned(),
+                        file: source_file_path.to_path_buf(),
+                    })
+                }
+            }
         }
 
-        let tasks_to_run: Vec<&Task> = probe_results
-            .into_iter()
-            .filter(|(_, probe_result)| match probe_result {
-                ProbeResult::Run => true,
-                ProbeResult::Skip => false,
-                ProbeResult::Abort => false,
-            })
-            .map(|(task, _)| task)
-            .collect();
+        Ok(probed_tasks)
+    }
+
+    /// Run the probe of a task, or reuse its cached result for the same fingerprint
+    fn run_probe(
+        &self,
+        task: &Task,
+        probing_context: ProbingContext,
+        fingerprint: Option<&str>,
+    ) -> ProbeResult {
+        let (Some(probe_cache), Some(fingerprint), Some(ttl)) =
+            (&self.probe_cache, fingerprint, task.probe_cache_ttl())
+        else {
+            return task.run_probe(probing_context);
+        };
+        // the connection is not locked while the probe runs, other probes may run meanwhile
+        let connection = || probe_cache.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let cached =
+            db::get_cached_probe_result(&connection(), task.description(), fingerprint, ttl);
+        match cached {
+            Ok(cached) => {
+                if let Some(probe_result) = cached.as_deref().and_then(cached_probe_result) {
+                    debug!("using cached probe result: {probe_result:?}");
+                    return probe_result;
+                }
+            }
+            Err(err) => error!("unable to read cached probe result: {err}"),
+        }
 
-        Ok(tasks_to_run)
+        let probe_result = task.run_probe(probing_context);
+
+        // an aborted or deferred probe is not a verdict on the file, so it is worth retrying later
+        let cache_value = match probe_result {
+            ProbeResult::Run => "run",
+            ProbeResult::Skip => "skip",
+            ProbeResult::Abort | ProbeResult::Defer => return probe_result,
+        };
+
+        if let Err(err) =
+            db::cache_probe_result(&connection(), task.description(), fingerprint, cache_value)
+        {
+            error!("unable to cache probe result: {err}");
+        }
+
+        probe_result
     }
 
     fn run_tasks(
@@ -135,25 +503,84 @@ impl Runner {
     ) -> Result<Vec<TaskReport>, RunnerError> {
         let mut task_reports: Vec<TaskReport> = Vec::with_capacity(tasks.len());
 
+        // each job gets its own directory, as the same task runs for many files
+        let job_log_directory = self
+            .task_log_directory
+            .as_ref()
+            .map(|directory| directory.join(Uuid::new_v4().to_string()));
+
+        // scripts may change their working directory, so they only get absolute paths
+        let (input_file, output_file) =
+            absolute_task_paths(context).map_err(|source| RunnerError::TaskSetupFailed {
+                file: context.source_file_path.clone(),
+                source,
+            })?;
+
         for task in tasks.iter() {
+            let _task_span = info_span!("task", id = task.description()).entered();
+
             let task_context = TaskContext::new(
-                &context.input_file,
-                &context.output_file,
+                &input_file,
+                &output_file,
                 &context.scratchpad_directory,
+                &context.resource_limits,
             );
 
-            // @todo handle task failure properly
-            let task_report = task.run_task(task_context);
+            let started_at = Instant::now();
+            let mut task_report = task
+                .run_task(task_context)
+                .for_task(task.description(), started_at.elapsed());
 
-            if !fs::exists(&context.output_file).unwrap_or(false) {
+            if let Some(job_log_directory) = &job_log_directory {
+                write_task_log(&mut task_report, task, job_log_directory);
+            }
+
+            // the output of a task that was killed, e.g. by the OOM killer, is likely truncated
+            if !task_report.succeeded() {
+                if let Some(log_file) = task_report.log_file() {
+                    warn!("the output of the failed task is in {}", log_file.display());
+                }
+                return Err(match task_report.exit_code() {
+                    Some(exit_code) => RunnerError::TaskFailed {
+                        task: task.description().to_owned(),
+                        file: context.source_file_path.clone(),
+                        exit_code,
+                    },
+                    None => RunnerError::TaskKilled {
+                        task: task.description().to_owned(),
+                        file: context.source_file_path.clone(),
+                    },
+                });
+            }
+
+            if context.read_only {
+                discard_output(&context.output_file, task);
+                task_reports.push(task_report);
+                continue;
+            }
+
+            if fs::exists(&context.output_file).unwrap_or(false)
+                && is_unchanged(
+                    context.unchanged_output_check,
+                    &context.output_file,
+                    &context.input_file,
+                )
+            {
+                info!(
+                    "task \"{}\" did not change the file, discarding its output",
+                    task.description()
+                );
+                if let Err(err) = fs::remove_file(&context.output_file) {
+                    warn!("unable to remove {}: {err}", context.output_file.display());
+                }
+                task_reports.push(task_report.with_unchanged_output());
                 continue;
             }
-            // move the output file so it becomes the input file of any next task
-            let move_result = fs::rename(&context.output_file, &context.input_file)
-                .map(|_| true)
-                .unwrap_or(false);
 
-            if !move_result {
+            // move the output file so it becomes the input file of any next task
+            if fs::exists(&context.output_file).unwrap_or(false)
+                && fs::rename(&context.output_file, &context.input_file).is_err()
+            {
                 warn!(
                 "task \"{}\" did not output any file, following task will work on the same source",
                 task.description()
@@ -167,14 +594,226 @@ impl Runner {
     }
 }
 
+/// Apply the function to every item, on up to `limit` threads at the same time.
+/// The results are in the order of the items, regardless of which finished first.
+fn map_concurrently<'a, T: Sync, R: Send>(
+    items: &'a [T],
+    limit: usize,
+    function: impl Fn(&'a T) -> R + Sync,
+) -> Vec<R> {
+    if limit <= 1 || items.len() <= 1 {
+        return items.iter().map(function).collect();
+    }
+
+    let next_index = AtomicUsize::new(0);
+
+    let mut results: Vec<(usize, R)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..limit.min(items.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = vec![];
+                    loop {
+                        let index = next_index.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(index) else {
+                            return results;
+                        };
+                        results.push((index, function(item)));
+                    }
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("worker thread panicked"))
+            .collect()
+    });
+
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Resolve the absolute paths of the input and output file of the tasks.
+/// The output file does not exist yet, so it is resolved through the scratchpad directory.
+fn absolute_task_paths(context: &Context) -> Result<(PathBuf, PathBuf), TaskError> {
+    let canonicalize = |path: &Path| {
+        path.canonicalize()
+            .map_err(|source| TaskError::CannotCanonicalize {
+                path: path.to_path_buf(),
+                source,
+            })
+    };
+
+    let input_file = canonicalize(&context.input_file)?;
+    let output_file = match context.output_file.file_name() {
+        Some(file_name) => canonicalize(&context.scratchpad_directory)?.join(file_name),
+        None => canonicalize(&context.output_file)?,
+    };
+
+    Ok((input_file, output_file))
+}
+
+/// Whether the file does not differ from the original according to the check. Files that cannot
+/// be compared are considered changed, the comparison is only an optimization.
+fn is_unchanged(check: UnchangedOutputCheck, file: &Path, original: &Path) -> bool {
+    let comparison = match check {
+        UnchangedOutputCheck::Off => return false,
+        UnchangedOutputCheck::Size => have_same_size(file, original),
+        UnchangedOutputCheck::Content => have_same_content(file, original),
+    };
+
+    comparison.unwrap_or_else(|err| {
+        warn!(
+            "unable to compare {} with {}: {err}",
+            file.display(),
+            original.display()
+        );
+        false
+    })
+}
+
+fn have_same_size(file: &Path, other: &Path) -> io::Result<bool> {
+    Ok(fs::metadata(file)?.len() == fs::metadata(other)?.len())
+}
+
+/// Compare the files byte by byte, stopping at the first difference
+fn have_same_content(file: &Path, other: &Path) -> io::Result<bool> {
+    if !have_same_size(file, other)? {
+        return Ok(false);
+    }
+
+    let mut file = BufReader::new(File::open(file)?);
+    let mut other = BufReader::new(File::open(other)?);
+
+    loop {
+        let (buffer, other_buffer) = (file.fill_buf()?, other.fill_buf()?);
+        if buffer.is_empty() || other_buffer.is_empty() {
+            return Ok(buffer.is_empty() && other_buffer.is_empty());
+        }
+
+        let length = buffer.len().min(other_buffer.len());
+        if buffer[..length] != other_buffer[..length] {
+            return Ok(false);
+        }
+
+        file.consume(length);
+        other.consume(length);
+    }
+}
+
+/// Remove the output a task of a read-only workflow wrote, as it would otherwise be used by the next task
+fn discard_output(output_file: &Path, task: &Task) {
+    if !fs::exists(output_file).unwrap_or(false) {
+        return;
+    }
+
+    warn!(
+        "task \"{}\" of a read-only workflow output a file, discarding it",
+        task.description()
+    );
+    if let Err(err) = fs::remove_file(output_file) {
+        warn!("unable to remove {}: {err}", output_file.display());
+    }
+}
+
+/// Remove the copy of the source file and any task output from the scratchpad, when they do not
+/// replace the source file. The input of a read-only workflow is the source file itself, so it
+/// is kept.
+fn discard_scratchpad_files(context: &Context) {
+    let input_file = (!context.read_only).then_some(&context.input_file);
+
+    for file in input_file.into_iter().chain([&context.output_file]) {
+        match fs::remove_file(file) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                warn!("unable to remove {}: {err}", file.display());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Write the output of the task to its log file, keeping the report small.
+/// Failing to do so is not a reason to fail the task, the report then keeps the full output.
+fn write_task_log(task_report: &mut TaskReport, task: &Task, job_log_directory: &Path) {
+    // builtin tasks are described with spaces and parentheses, which are awkward in paths
+    let file_name = task.description().replace(
+        |c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '_' && c != '.',
+        "_",
+    );
+    let log_file = job_log_directory.join(format!("{file_name}.log"));
+
+    match task_report.write_to_log_file(&log_file) {
+        Ok(()) => info!("wrote task output to {}", log_file.display()),
+        Err(err) => warn!(
+            "unable to write task output to {}: {err}",
+            log_file.display()
+        ),
+    }
+}
+
+/// Convert a cached value back into a probe result
+fn cached_probe_result(value: &str) -> Option<ProbeResult> {
+    match value {
+        "run" => Some(ProbeResult::Run),
+        "skip" => Some(ProbeResult::Skip),
+        _ => None,
+    }
+}
+
+/// Resolve an aborted probe into a result using the task's configured policy
+fn resolve_aborted_probe(task: &Task) -> ProbeResult {
+    match task.probe_abort_policy() {
+        ProbeAbortPolicy::Abort => ProbeResult::Abort,
+        ProbeAbortPolicy::Run => {
+            warn!(
+                "probe of task \"{}\" aborted, running the task anyway",
+                task.description()
+            );
+            ProbeResult::Run
+        }
+        ProbeAbortPolicy::Skip => {
+            warn!(
+                "probe of task \"{}\" aborted, skipping the task",
+                task.description()
+            );
+            ProbeResult::Skip
+        }
+    }
+}
+
 /// Logic related to the start and cleanup of a run
 impl Runner {
     /// Create the area where file transformations can be done
     fn prepare(
         &self,
-        scratchpad_directory: &Path,
+        workflow: &Workflow,
         source_file_path: &Path,
     ) -> Result<Context, PreparationError> {
+        // files in e.g. a download folder are moved on regularly, also between a scan and the job
+        let disappeared_or =
+            |err: io::Error, otherwise: fn(io::Error) -> PreparationError| match err.kind() {
+                io::ErrorKind::NotFound => {
+                    PreparationError::SourceFileDisappeared(source_file_path.to_path_buf())
+                }
+                _ => otherwise(err),
+            };
+
+        let source_metadata = fs::metadata(source_file_path)
+            .map_err(|err| disappeared_or(err, PreparationError::UnableToReadSourceFile))?;
+        let source_file_size_bytes = source_metadata.len();
+
+        if let Some(limit) = workflow
+            .max_source_size_bytes
+            .filter(|limit| source_file_size_bytes > *limit)
+        {
+            return Err(PreparationError::FileTooLarge {
+                size: source_file_size_bytes,
+                limit,
+            });
+        }
+
+        let scratchpad_directory = &workflow.scratchpad_directory;
+
         debug!(
             "creating scratchpad directory at {}",
             scratchpad_directory.to_string_lossy()
@@ -183,34 +822,600 @@ impl Runner {
         fs::create_dir_all(scratchpad_directory)
             .map_err(PreparationError::UnableToCreateScratchpad)?;
 
-        let input_file_name = generate_target_file(source_file_path);
+        let input_file_name = generate_target_file(source_file_path, &workflow.temp_prefix)
+            .map_err(PreparationError::InvalidFileName)?;
         debug!("generated target file name: {}", input_file_name);
 
-        let input_file = scratchpad_directory.join(PathBuf::from(&input_file_name));
-
-        debug!(
-            "copying source file into scratchpad directory at {}",
-            input_file.to_string_lossy()
+        let output_file = scratchpad_directory.join(
+            generate_output_file_name(&input_file_name)
+                .map_err(PreparationError::InvalidFileName)?,
         );
 
-        fs::copy(source_file_path, &input_file)
-            .map_err(PreparationError::UnableToCopySourceFile)?;
+        let input_file = if workflow.read_only {
+            debug!("workflow is read-only, tasks use the source file directly");
+            source_file_path.to_path_buf()
+        } else {
+            let input_file = scratchpad_directory.join(PathBuf::from(&input_file_name));
+
+            // copying large files can take long, the span shows how long exactly
+            let _copy_span = info_span!(
+                "copy_to_scratchpad",
+                source_size_bytes = source_file_size_bytes,
+                scratchpad_path = %input_file.display()
+            )
+            .entered();
+
+            debug!(
+                "copying source file into scratchpad directory at {}",
+                input_file.to_string_lossy()
+            );
+
+            retry_transient(workflow.io_retries, IO_RETRY_BASE_DELAY, || {
+                fs::copy(source_file_path, &input_file)
+            })
+            .map_err(|err| disappeared_or(err, PreparationError::UnableToCopySourceFile))?;
+
+            input_file
+        };
 
-        let output_file = scratchpad_directory.join(generate_output_file_name(&input_file_name));
+        // tasks create their output with the default mode, the source file's is kept unless configured
+        let output_permissions = match workflow.output_file_mode {
+            #[cfg(unix)]
+            Some(mode) => fs::Permissions::from_mode(mode),
+            _ => source_metadata.permissions(),
+        };
 
         Ok(Context {
-            scratchpad_directory: scratchpad_directory.to_owned(),
+            scratchpad_directory: scratchpad_directory.clone(),
             source_file_path: source_file_path.to_path_buf(),
+            source_file_size_bytes,
             input_file,
             output_file,
+            resource_limits: workflow.resource_limits,
+            read_only: workflow.read_only,
+            io_retries: workflow.io_retries,
+            unchanged_output_check: workflow.unchanged_output_check,
+            output_permissions,
         })
     }
 
     /// Complete a run which will make sure that no artifacts are left behind
     /// and that the transformed file replaces the original source file
     fn complete_run(&self, context: &Context) -> Result<(), CompletionError> {
+        // replacing the source file can be slow, e.g. on a network mount or a spinning disk
+        let _complete_span = info_span!(
+            "complete_run",
+            source_size_bytes = context.source_file_size_bytes,
+            scratchpad_path = %context.input_file.display()
+        )
+        .entered();
+
         debug!("copying transformed file back to source file");
-        fs::rename(&context.input_file, &context.source_file_path)
-            .map_err(CompletionError::UnableToMoveFile)
+        retry_transient(context.io_retries, IO_RETRY_BASE_DELAY, || {
+            replace_atomically(&context.input_file, &context.source_file_path)
+        })
+        .map_err(CompletionError::UnableToMoveFile)?;
+
+        // the file has been replaced already, so failing the run would not undo anything
+        if let Err(err) = fs::set_permissions(
+            &context.source_file_path,
+            context.output_permissions.clone(),
+        ) {
+            warn!(
+                "unable to set the permissions of {}: {err}",
+                context.source_file_path.display()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Make the transformed file available in the workflow's link destinations as well.
+/// The source file has been replaced already, so failing to link it is only logged.
+fn link_into_destinations(source_file: &Path, workflow: &Workflow) {
+    let Some(file_name) = source_file.file_name() else {
+        return;
+    };
+
+    for destination in &workflow.link_destinations {
+        let target = destination.join(file_name);
+
+        if let Err(err) = link_file(source_file, &target, workflow.on_link_collision) {
+            warn!(
+                "unable to link {} to {}: {err}",
+                source_file.display(),
+                target.display()
+            );
+        }
+    }
+}
+
+/// Hardlink the file to the target, or copy it when the target is on another filesystem
+fn link_file(
+    source_file: &Path,
+    target: &Path,
+    on_collision: LinkCollisionPolicy,
+) -> io::Result<()> {
+    if fs::symlink_metadata(target).is_ok() {
+        match on_collision {
+            LinkCollisionPolicy::Skip => {
+                warn!(
+                    "not linking to {}, a file with that name already exists",
+                    target.display()
+                );
+                return Ok(());
+            }
+            LinkCollisionPolicy::Replace => {
+                debug!("replacing existing file {}", target.display());
+                fs::remove_file(target)?;
+            }
+        }
+    }
+
+    match fs::hard_link(source_file, target) {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            debug!(
+                "{} is on another filesystem, copying instead",
+                target.display()
+            );
+            fs::copy(source_file, target).map(|_| ())
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use tempdir::TempDir;
+
+    use crate::workflow::{test_workflow, CustomTask, ResourceLimits, Runnable};
+
+    use super::*;
+
+    #[test]
+    fn aborted_probe_is_resolved_using_task_policy() {
+        let mut custom_task = CustomTask::new(
+            "test-task".to_owned(),
+            "some description".to_owned(),
+            Some(Runnable::new("exit 1").unwrap()),
+            Runnable::new("echo done").unwrap(),
+        );
+
+        assert_eq!(
+            ProbeResult::Abort,
+            resolve_aborted_probe(&Task::Custom(custom_task.clone()))
+        );
+
+        custom_task.on_probe_abort = ProbeAbortPolicy::Run;
+        assert_eq!(
+            ProbeResult::Run,
+            resolve_aborted_probe(&Task::Custom(custom_task.clone()))
+        );
+
+        custom_task.on_probe_abort = ProbeAbortPolicy::Skip;
+        assert_eq!(
+            ProbeResult::Skip,
+            resolve_aborted_probe(&Task::Custom(custom_task))
+        );
+    }
+
+    #[test]
+    fn missing_or_too_large_source_file_is_refused() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let source_file = temp_test_dir.path().join("movie.mkv");
+        fs::write(&source_file, "four").unwrap();
+
+        let workflow = Workflow {
+            max_source_size_bytes: Some(3),
+            ..test_workflow("movies", temp_test_dir.path().join("scratchpad"))
+        };
+
+        let result = Runner::new().prepare(&workflow, &source_file);
+
+        assert!(matches!(
+            result,
+            Err(PreparationError::FileTooLarge { size: 4, limit: 3 })
+        ));
+
+        fs::remove_file(&source_file).unwrap();
+        let result = Runner::new().prepare(&workflow, &source_file);
+
+        assert!(matches!(
+            result,
+            Err(PreparationError::SourceFileDisappeared(path)) if path == source_file
+        ));
+    }
+
+    #[test]
+    fn task_paths_are_absolute() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let scratchpad_directory = temp_test_dir.path().join("scratchpad");
+        fs::create_dir_all(&scratchpad_directory).unwrap();
+
+        let context = Context {
+            scratchpad_directory: scratchpad_directory.join("."),
+            source_file_path: temp_test_dir.path().join("movie.mkv"),
+            source_file_size_bytes: 0,
+            input_file: scratchpad_directory.join("./omzet-movie.mkv"),
+            output_file: scratchpad_directory.join("./omzet-movie.out.mkv"),
+            resource_limits: ResourceLimits::default(),
+            read_only: false,
+            io_retries: 3,
+            unchanged_output_check: UnchangedOutputCheck::Off,
+            output_permissions: fs::Permissions::from_mode(0o644),
+        };
+
+        assert!(matches!(
+            absolute_task_paths(&context),
+            Err(TaskError::CannotCanonicalize { .. })
+        ));
+
+        fs::write(&context.input_file, "movie").unwrap();
+
+        let (input_file, output_file) = absolute_task_paths(&context).unwrap();
+        let scratchpad_directory = scratchpad_directory.canonicalize().unwrap();
+        assert_eq!(scratchpad_directory.join("omzet-movie.mkv"), input_file);
+        assert_eq!(
+            scratchpad_directory.join("omzet-movie.out.mkv"),
+            output_file
+        );
+    }
+
+    #[test]
+    fn task_scoped_to_extension_is_skipped_for_other_files() {
+        let mut mkv_task = CustomTask::new(
+            "mkv-task".to_owned(),
+            "only for mkv files".to_owned(),
+            None,
+            Runnable::new("echo done").unwrap(),
+        );
+        mkv_task.applies_to_extensions = Some(vec!["mkv".to_owned()]);
+
+        let tasks = vec![Task::Custom(mkv_task)];
+
+        let probe = |runner: &Runner, file_name: &str| {
+            let input_file = PathBuf::from("/tmp").join(file_name);
+            runner
+                .probe_tasks(
+                    &tasks,
+                    NonZeroUsize::MIN,
+                    ProbingContext::new(&input_file, Path::new("/tmp")),
+                    &PathBuf::from("/library").join(file_name),
+                )
+                .unwrap()
+        };
+
+        let runner = Runner::new();
+
+        let probed_tasks = probe(&runner, "movie.mkv");
+        assert_eq!(1, probed_tasks.to_run.len());
+        assert!(probed_tasks.skipped.is_empty());
+
+        let probed_tasks = probe(&runner, "movie.mp4");
+        assert!(probed_tasks.to_run.is_empty());
+        assert_eq!(ProbeResult::Skip, probed_tasks.skipped[0].1);
+    }
+
+    #[test]
+    fn parallel_probes_are_handled_in_task_order() {
+        let task = |id: &str, probe: &str| {
+            Task::Custom(CustomTask::new(
+                id.to_owned(),
+                id.to_owned(),
+                Some(Runnable::new(probe).unwrap()),
+                Runnable::new("echo done").unwrap(),
+            ))
+        };
+        fn probe(tasks: &[Task]) -> Result<ProbedTasks<'_>, RunnerError> {
+            Runner::new().probe_tasks(
+                tasks,
+                NonZeroUsize::new(3).unwrap(),
+                ProbingContext::new(Path::new("/tmp/movie.mkv"), Path::new("/tmp")),
+                Path::new("/library/movie.mkv"),
+            )
+        }
+
+        let tasks = [
+            task("slow", "sleep 0.3"),
+            task("skipped", "exit 1"),
+            task("fast", "exit 0"),
+        ];
+        let probed_tasks = probe(&tasks).unwrap();
+        let to_run: Vec<&str> = probed_tasks
+            .to_run
+            .iter()
+            .map(|task| task.description())
+            .collect();
+        assert_eq!(vec!["slow", "fast"], to_run);
+        assert_eq!("skipped", probed_tasks.skipped[0].0.description());
+
+        // the deferral of the first task wins, although the later abort finishes first
+        let tasks = [
+            task("deferred", "sleep 0.3; exit 75"),
+            task("aborted", "exit 2"),
+        ];
+        assert!(matches!(
+            probe(&tasks),
+            Err(RunnerError::ProbeDeferred { task, .. }) if task == "deferred"
+        ));
+    }
+
+    #[test]
+    fn read_only_workflow_leaves_source_file_untouched() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let scratchpad_directory = temp_test_dir.path().join("scratchpad");
+        let source_file = temp_test_dir.path().join("movie.mkv");
+        fs::write(&source_file, "original").unwrap();
+
+        let task = CustomTask::new(
+            "inspect".to_owned(),
+            "reads the file, but also writes an output".to_owned(),
+            None,
+            Runnable::new(r#"cat "$OMZET_INPUT" && echo changed > "$OMZET_OUTPUT""#).unwrap(),
+        );
+
+        let workflow = Workflow {
+            tasks: vec![Task::Custom(task)],
+            read_only: true,
+            ..test_workflow("inspect", scratchpad_directory.clone())
+        };
+
+        let report = Runner::new()
+            .run_workflow(&workflow, source_file.clone())
+            .unwrap();
+
+        assert_eq!(1, report.task_reports().len());
+        assert_eq!("original", report.task_reports()[0].stdout());
+        assert_eq!("original", fs::read_to_string(&source_file).unwrap());
+        assert_eq!(0, fs::read_dir(&scratchpad_directory).unwrap().count());
+    }
+
+    #[test]
+    fn plan_probes_the_original_file_without_running_tasks() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let scratchpad_directory = temp_test_dir.path().join("scratchpad");
+        let source_file = temp_test_dir.path().join("movie.mkv");
+        fs::write(&source_file, "original").unwrap();
+
+        let probed_task = |id: &str, probe: &str| {
+            Task::Custom(CustomTask::new(
+                id.to_owned(),
+                format!("task {id}"),
+                Some(Runnable::new(probe).unwrap()),
+                Runnable::new(r#"echo changed > "$OMZET_INPUT""#).unwrap(),
+            ))
+        };
+
+        let workflow = Workflow {
+            tasks: vec![
+                probed_task(
+                    "original",
+                    &format!(r#"test "$OMZET_INPUT" = "{}""#, source_file.display()),
+                ),
+                probed_task("skipped", "exit 1"),
+            ],
+            ..test_workflow("plan", scratchpad_directory.clone())
+        };
+
+        let plan = Runner::new().plan(&workflow, &source_file).unwrap();
+
+        assert_eq!(1, plan.to_run.len());
+        assert_eq!("original", plan.to_run[0].description());
+        assert_eq!(1, plan.skipped.len());
+        assert_eq!("original", fs::read_to_string(&source_file).unwrap());
+        assert!(!scratchpad_directory.exists());
+    }
+
+    #[test]
+    fn transformed_file_gets_configured_or_source_permissions() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let source_file = temp_test_dir.path().join("movie.mkv");
+        fs::write(&source_file, "original").unwrap();
+        fs::set_permissions(&source_file, fs::Permissions::from_mode(0o600)).unwrap();
+
+        let task = CustomTask::new(
+            "transform".to_owned(),
+            "writes a new file".to_owned(),
+            None,
+            Runnable::new(r#"echo changed > "$OMZET_OUTPUT""#).unwrap(),
+        );
+
+        let mut workflow = Workflow {
+            tasks: vec![Task::Custom(task)],
+            ..test_workflow("transform", temp_test_dir.path().join("scratchpad"))
+        };
+        let mode_of_source = || fs::metadata(&source_file).unwrap().permissions().mode() & 0o7777;
+
+        Runner::new()
+            .run_workflow(&workflow, source_file.clone())
+            .unwrap();
+        assert_eq!("changed\n", fs::read_to_string(&source_file).unwrap());
+        assert_eq!(0o600, mode_of_source());
+
+        workflow.output_file_mode = Some(0o640);
+        Runner::new()
+            .run_workflow(&workflow, source_file.clone())
+            .unwrap();
+        assert_eq!(0o640, mode_of_source());
+    }
+
+    #[test]
+    fn unchanged_output_does_not_replace_source_file() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let source_file = temp_test_dir.path().join("movie.mkv");
+        fs::write(&source_file, "original").unwrap();
+        fs::set_permissions(&source_file, fs::Permissions::from_mode(0o600)).unwrap();
+
+        let task = CustomTask::new(
+            "copy".to_owned(),
+            "outputs its input as is".to_owned(),
+            None,
+            Runnable::new(r#"cp "$OMZET_INPUT" "$OMZET_OUTPUT""#).unwrap(),
+        );
+
+        let workflow = Workflow {
+            tasks: vec![Task::Custom(task)],
+            output_file_mode: Some(0o640),
+            unchanged_output_check: UnchangedOutputCheck::Content,
+            ..test_workflow("copy", temp_test_dir.path().join("scratchpad"))
+        };
+
+        let report = Runner::new()
+            .run_workflow(&workflow, source_file.clone())
+            .unwrap()
+            .to_string();
+
+        assert!(report.contains("file unchanged"));
+        assert!(report.contains("[no change]"));
+        assert_eq!("original", fs::read_to_string(&source_file).unwrap());
+        assert_eq!(
+            0,
+            fs::read_dir(temp_test_dir.path().join("scratchpad"))
+                .unwrap()
+                .count()
+        );
+        // the permissions are only set on a file that replaced the source file
+        assert_eq!(
+            0o600,
+            fs::metadata(&source_file).unwrap().permissions().mode() & 0o7777
+        );
+    }
+
+    #[test]
+    fn killed_task_leaves_source_file_untouched() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let source_file = temp_test_dir.path().join("movie.mkv");
+        fs::write(&source_file, "original").unwrap();
+
+        // like an encoder that is killed halfway, it leaves a partial output behind
+        let task = CustomTask::new(
+            "killed".to_owned(),
+            "is killed while writing its output".to_owned(),
+            None,
+            Runnable::new(r#"echo partial > "$OMZET_OUTPUT"; kill -9 $$"#).unwrap(),
+        );
+        let workflow = Workflow {
+            tasks: vec![Task::Custom(task)],
+            ..test_workflow("killed", temp_test_dir.path().join("scratchpad"))
+        };
+
+        let result = Runner::new().run_workflow(&workflow, source_file.clone());
+
+        assert!(matches!(
+            result,
+            Err(RunnerError::TaskKilled { task, .. }) if task == "killed"
+        ));
+        assert_eq!("original", fs::read_to_string(&source_file).unwrap());
+        assert_eq!(
+            0,
+            fs::read_dir(temp_test_dir.path().join("scratchpad"))
+                .unwrap()
+                .count()
+        );
+    }
+
+    #[test]
+    fn deferred_file_leaves_nothing_in_scratchpad() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let scratchpad_directory = temp_test_dir.path().join("scratchpad");
+        let source_file = temp_test_dir.path().join("movie.mkv");
+        fs::write(&source_file, "original").unwrap();
+
+        let task = CustomTask::new(
+            "deferred".to_owned(),
+            "is never ready".to_owned(),
+            Some(Runnable::new("exit 75").unwrap()),
+            Runnable::new("echo done").unwrap(),
+        );
+        let workflow = Workflow {
+            tasks: vec![Task::Custom(task)],
+            ..test_workflow("deferred", scratchpad_directory.clone())
+        };
+
+        // every retry copies the source file into the scratchpad again
+        for _ in 0..2 {
+            let result = Runner::new().run_workflow(&workflow, source_file.clone());
+
+            assert!(matches!(result, Err(RunnerError::ProbeDeferred { .. })));
+            assert_eq!(0, fs::read_dir(&scratchpad_directory).unwrap().count());
+        }
+        assert_eq!("original", fs::read_to_string(&source_file).unwrap());
+    }
+
+    #[test]
+    fn failed_task_of_read_only_workflow_keeps_source_file() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let scratchpad_directory = temp_test_dir.path().join("scratchpad");
+        let source_file = temp_test_dir.path().join("movie.mkv");
+        fs::write(&source_file, "original").unwrap();
+
+        let task = CustomTask::new(
+            "failing".to_owned(),
+            "writes an output and fails".to_owned(),
+            None,
+            Runnable::new(r#"echo changed > "$OMZET_OUTPUT"; exit 1"#).unwrap(),
+        );
+        let workflow = Workflow {
+            tasks: vec![Task::Custom(task)],
+            read_only: true,
+            ..test_workflow("failing", scratchpad_directory.clone())
+        };
+
+        let result = Runner::new().run_workflow(&workflow, source_file.clone());
+
+        assert!(matches!(result, Err(RunnerError::TaskFailed { .. })));
+        assert_eq!("original", fs::read_to_string(&source_file).unwrap());
+        assert_eq!(0, fs::read_dir(&scratchpad_directory).unwrap().count());
+    }
+
+    #[test]
+    fn transformed_file_is_linked_into_destinations() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let source_file = temp_test_dir.path().join("movie.mkv");
+        fs::write(&source_file, "original").unwrap();
+
+        let destinations = [
+            temp_test_dir.path().join("kids"),
+            temp_test_dir.path().join("classics"),
+        ];
+        for destination in &destinations {
+            fs::create_dir(destination).unwrap();
+        }
+        fs::write(destinations[1].join("movie.mkv"), "other").unwrap();
+
+        let task = CustomTask::new(
+            "transform".to_owned(),
+            "writes a new file".to_owned(),
+            None,
+            Runnable::new(r#"echo changed > "$OMZET_OUTPUT""#).unwrap(),
+        );
+
+        let mut workflow = Workflow {
+            tasks: vec![Task::Custom(task)],
+            link_destinations: destinations.to_vec(),
+            ..test_workflow("transform", temp_test_dir.path().join("scratchpad"))
+        };
+        let content_in =
+            |destination: &Path| fs::read_to_string(destination.join("movie.mkv")).unwrap();
+
+        Runner::new()
+            .run_workflow(&workflow, source_file.clone())
+            .unwrap();
+        assert_eq!("changed\n", content_in(&destinations[0]));
+        assert_eq!("other", content_in(&destinations[1]));
+
+        // a hardlink shares the content with the source file
+        fs::write(&source_file, "linked").unwrap();
+        assert_eq!("linked", content_in(&destinations[0]));
+
+        workflow.on_link_collision = LinkCollisionPolicy::Replace;
+        Runner::new()
+            .run_workflow(&workflow, source_file.clone())
+            .unwrap();
+        assert_eq!("changed\n", content_in(&destinations[0]));
+        assert_eq!("changed\n", content_in(&destinations[1]));
     }
 }
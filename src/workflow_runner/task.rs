@@ -1,9 +1,6 @@
 use crate::{job_orchestration, workflow::Task};
 
-use super::{
-    builtin_task,
-    common::{self, ProbeRunner, ProbingContext, TaskContext, TaskRunner},
-};
+use super::common::{self, ProbeRunner, ProbingContext, TaskContext, TaskRunner};
 
 impl ProbeRunner for Task {
     fn run_probe(&self, context: ProbingContext) -> common::ProbeResult {
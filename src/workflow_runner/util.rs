@@ -1,49 +1,266 @@
-use std::path::Path;
+use std::{
+    fs::{self, File},
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
 
+use tracing::warn;
 use uuid::Uuid;
 
-/// Generate a target file from the source file
-pub(super) fn generate_target_file(source_file_path: &Path) -> String {
-    let uuid = Uuid::new_v4();
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum FileNameError {
+    #[error("\"{}\" has no file name", .0.display())]
+    MissingFileName(PathBuf),
+    #[error("\"{}\" has no extension", .0.display())]
+    MissingExtension(PathBuf),
+}
 
-    let file_name = source_file_path
+/// Split a path into its file stem and extension
+fn split_file_name(path: &Path) -> Result<(String, String), FileNameError> {
+    let file_name = path
         .file_stem()
-        .expect("failed to take file name");
-    let extension = source_file_path
+        .ok_or_else(|| FileNameError::MissingFileName(path.to_path_buf()))?;
+    let extension = path
         .extension()
-        .expect("failed to take file extension");
+        .ok_or_else(|| FileNameError::MissingExtension(path.to_path_buf()))?;
 
-    format!(
-        "{}-{}.{}",
-        file_name.to_string_lossy(),
-        uuid,
-        extension.to_string_lossy()
-    )
+    Ok((
+        file_name.to_string_lossy().to_string(),
+        extension.to_string_lossy().to_string(),
+    ))
+}
+
+/// Generate a target file from the source file, starting with the given prefix
+pub(super) fn generate_target_file(
+    source_file_path: &Path,
+    prefix: &str,
+) -> Result<String, FileNameError> {
+    let uuid = Uuid::new_v4();
+
+    let (file_name, extension) = split_file_name(source_file_path)?;
+
+    Ok(format!("{prefix}{file_name}-{uuid}.{extension}"))
 }
 
-pub(super) fn generate_output_file_name(target_file_name: &str) -> String {
-    let path = Path::new(target_file_name);
+pub(super) fn generate_output_file_name(target_file_name: &str) -> Result<String, FileNameError> {
+    let (file_name, extension) = split_file_name(Path::new(target_file_name))?;
+
+    Ok(format!("{file_name}.out.{extension}"))
+}
 
-    let file_name = path.file_stem().expect("failed to take file name");
-    let extension = path.extension().expect("failed to take file extension");
+/// Delay before the first retry of a transient IO error, it doubles for every next retry
+pub(super) const IO_RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
 
-    format!(
-        "{}.out.{}",
-        file_name.to_string_lossy(),
-        extension.to_string_lossy()
+/// Whether an IO error is likely to go away by itself, e.g. a hiccup of a network mount
+fn is_transient(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+            | ErrorKind::TimedOut
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+            | ErrorKind::StaleNetworkFileHandle
     )
 }
 
+/// Run an IO operation, retrying it with an increasing delay as long as it fails transiently.
+/// Permanent errors, like a missing file, are returned right away.
+pub(super) fn retry_transient<T>(
+    retries: u32,
+    base_delay: Duration,
+    mut operation: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut attempt = 0;
+
+    loop {
+        match operation() {
+            Err(err) if attempt < retries && is_transient(&err) => {
+                let delay = base_delay * 2u32.pow(attempt);
+                attempt += 1;
+                warn!("transient IO error, retry {attempt} of {retries} in {delay:?}: {err}");
+                thread::sleep(delay);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Path next to the target where its replacement is placed before it is renamed over the target.
+/// The name is hidden and has no media extension, so a scan never picks up a leftover one.
+fn staging_path(target: &Path) -> Result<PathBuf, FileNameError> {
+    let file_name = target
+        .file_name()
+        .ok_or_else(|| FileNameError::MissingFileName(target.to_path_buf()))?;
+
+    Ok(target.with_file_name(format!(".{}.omzet-replace", file_name.to_string_lossy())))
+}
+
+/// Move the replacement to the staging path and make sure it is on disk.
+/// Returns whether it was moved, instead of copied because it is on another filesystem.
+fn stage(replacement: &Path, staging: &Path) -> io::Result<bool> {
+    let moved = match fs::rename(replacement, staging) {
+        Ok(()) => true,
+        Err(err) if err.kind() == ErrorKind::CrossesDevices => false,
+        Err(err) => return Err(err),
+    };
+
+    let copied = if moved {
+        Ok(())
+    } else {
+        fs::copy(replacement, staging).map(drop)
+    };
+    if let Err(err) = copied.and_then(|()| File::open(staging)?.sync_all()) {
+        unstage(replacement, staging, moved);
+        return Err(err);
+    }
+
+    Ok(moved)
+}
+
+/// Undo the staging of a replacement. A moved replacement is put back, so a retry can stage it
+/// again, a copy is removed.
+fn unstage(replacement: &Path, staging: &Path, moved: bool) {
+    let _ = if moved {
+        fs::rename(staging, replacement)
+    } else {
+        fs::remove_file(staging)
+    };
+}
+
+/// Replace the target by the replacement, so that an interruption at any moment leaves either the
+/// original or the complete replacement at the target, never nothing or a partial file.
+/// The replacement is first placed next to the target and synced, then renamed over it.
+pub(super) fn replace_atomically(replacement: &Path, target: &Path) -> io::Result<()> {
+    let staging =
+        staging_path(target).map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+
+    let moved = stage(replacement, &staging)?;
+
+    if let Err(err) = fs::rename(&staging, target) {
+        unstage(replacement, &staging, moved);
+        return Err(err);
+    }
+
+    // the target is replaced, a failure to clean up the copied replacement does not undo that
+    if !moved {
+        if let Err(err) = fs::remove_file(replacement) {
+            warn!(
+                "unable to remove replacement {} after copying it: {err}",
+                replacement.display()
+            );
+        }
+    }
+
+    // the rename itself is only durable once the directory is synced
+    #[cfg(unix)]
+    if let Some(directory) = target.parent() {
+        File::open(directory)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
+    use tempdir::TempDir;
+
     use super::*;
 
+    #[test]
+    fn interrupted_replacement_leaves_original_intact() {
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        let target = temp_test_dir.path().join("movie.mkv");
+        let replacement = temp_test_dir.path().join("scratchpad.mkv");
+        fs::write(&target, "original").unwrap();
+        fs::write(&replacement, "transformed").unwrap();
+
+        // interrupted after staging, before the rename over the target
+        let staging = staging_path(&target).unwrap();
+        stage(&replacement, &staging).unwrap();
+        assert_eq!("original", fs::read_to_string(&target).unwrap());
+
+        fs::rename(&staging, &replacement).unwrap();
+        replace_atomically(&replacement, &target).unwrap();
+        assert_eq!("transformed", fs::read_to_string(&target).unwrap());
+        assert!(!staging.exists());
+
+        // a replacement that cannot be staged does not touch the target at all
+        assert!(replace_atomically(&replacement, &target).is_err());
+        assert_eq!("transformed", fs::read_to_string(&target).unwrap());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn replacement_on_another_filesystem_is_removed() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_test_dir = TempDir::new("omzet-test").unwrap();
+        // only meaningful when the scratchpad can be on another filesystem than the target
+        let Ok(scratchpad) = TempDir::new_in("/dev/shm", "omzet-test") else {
+            return;
+        };
+        let device = |path: &Path| fs::metadata(path).unwrap().dev();
+        if device(temp_test_dir.path()) == device(scratchpad.path()) {
+            return;
+        }
+
+        let target = temp_test_dir.path().join("movie.mkv");
+        let replacement = scratchpad.path().join("scratchpad.mkv");
+        fs::write(&target, "original").unwrap();
+        fs::write(&replacement, "transformed").unwrap();
+
+        replace_atomically(&replacement, &target).unwrap();
+
+        assert_eq!("transformed", fs::read_to_string(&target).unwrap());
+        assert!(!replacement.exists());
+        assert!(!staging_path(&target).unwrap().exists());
+    }
+
     #[test]
     fn test_subject_file_generation() {
         let source_file = "/tmp/test_file.mkv";
         let path = Path::new(source_file);
-        let subject_file = generate_target_file(path);
+        let subject_file = generate_target_file(path, "omzet-").unwrap();
+
+        assert!(subject_file.len() == "omzet-".len() + "test_file.mkv".len() + 37);
+        assert!(subject_file.starts_with("omzet-test_file-"));
+    }
+
+    #[test]
+    fn file_without_extension_is_refused() {
+        assert!(matches!(
+            generate_target_file(Path::new("/tmp/test_file"), "omzet-"),
+            Err(FileNameError::MissingExtension(_))
+        ));
+        assert!(matches!(
+            generate_output_file_name(".."),
+            Err(FileNameError::MissingFileName(_))
+        ));
+    }
+
+    #[test]
+    fn only_transient_errors_are_retried() {
+        let failing = |kind: ErrorKind, failures: u32| {
+            let mut attempts = 0;
+            move || {
+                attempts += 1;
+                if attempts <= failures {
+                    Err(io::Error::from(kind))
+                } else {
+                    Ok(attempts)
+                }
+            }
+        };
 
-        assert!(subject_file.len() == "test_file.mkv".len() + 37);
+        assert_eq!(
+            3,
+            retry_transient(2, Duration::ZERO, failing(ErrorKind::Interrupted, 2)).unwrap()
+        );
+        assert!(retry_transient(1, Duration::ZERO, failing(ErrorKind::TimedOut, 2)).is_err());
+        assert!(retry_transient(3, Duration::ZERO, failing(ErrorKind::NotFound, 1)).is_err());
     }
 }